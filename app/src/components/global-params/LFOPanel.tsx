@@ -8,7 +8,7 @@ import { PanelKnob } from '../PanelKnob';
 import { PanelGroup } from '../PanelGroup';
 import { colors, typography } from '../../tokens';
 
-// Ordered to match LfoDestination enum in lfo.rs
+// Ids match ModDestination::from_id in mod_matrix.rs
 const DESTINATIONS: { value: number; label: string; group: string }[] = [
   // FM Synth
   { value: 0,  label: 'Mod A',      group: 'FM Synth' },
@@ -19,7 +19,11 @@ export const OPERATOR_COLORS = [
   colors.operator.modB2,
 ] as const;
 
-export const WAVEFORM_NAMES = ['Sine', 'Square', 'Saw', 'Triangle', 'Noise'] as const;
+export const WAVEFORM_NAMES = [
+  'Sine', 'Square', 'Saw', 'Triangle', 'Noise',
+  'W1', 'W2', 'W3', 'W4', 'W5', 'W6', 'W7', 'W8',
+  'Wavetable', 'Pink', 'Brown', 'Digital',
+] as const;
 
 /** Ratio snap points — discrete values the ring snaps to */
 export const RATIO_SNAPS: number[] = [
@@ -11,9 +11,12 @@ export interface Point {
 
 /**
  * Waveform type IDs matching oscillator.rs WaveType enum order:
- * 0=Sine, 1=Square, 2=Saw, 3=Triangle, 4=Noise
+ * 0=Sine, 1=Square, 2=Saw, 3=Triangle, 4=Noise,
+ * 5–12 = TX81Z/DX11 (OPZ) waveforms W1–W8, 13 = user wavetable,
+ * 14 = Pink noise, 15 = Brown noise, 16 = Digital noise
  */
-export type WaveTypeId = 0 | 1 | 2 | 3 | 4;
+export type WaveTypeId =
+  0 | 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9 | 10 | 11 | 12 | 13 | 14 | 15 | 16;
 
 /** Per-operator state visible on the canvas */
 export interface OperatorPatch {
@@ -100,7 +103,7 @@ export interface FMCanvasPatch {
   lfo1Depth: number;        // 0.0-1.0
   lfo1Waveform: number;     // 0=Triangle,1=Sine,2=Square,3=Sawtooth,4=Exp,5=Ramp,6=Random
   lfo1Mode: number;         // 0=Free,1=Trigger,2=Hold,3=One,4=Half
-  lfo1Destination: number;  // LfoDestination index
+  lfo1Destination: number;  // ModDestination id
   lfo1Multiplier: number;   // integer multiplier
   lfo1Fade: number;         // -64 to 63
 
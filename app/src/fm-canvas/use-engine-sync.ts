@@ -7,7 +7,7 @@ import type { FMCanvasPatch } from './types';
  * The engine's LFO depth is unclamped, so we pre-scale here so that
  * depth=1.0 (100% in the UI) means "full meaningful range" for that dest.
  *
- * Destinations (must match LfoDestination enum order in lfo.rs):
+ * Destinations (must match ModDestination::from_id in mod_matrix.rs):
  *  0=ModDepthA, 1=ModDepthB, 2=RatioC, 3=RatioA, 4=RatioB,
  *  5=Feedback, 6=Harm, 7=CarrierMix,
  *  8=AmpAttack, 9=AmpDecay, 10=AmpSustain, 11=AmpRelease,
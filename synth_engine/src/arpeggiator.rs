@@ -0,0 +1,453 @@
+// src/arpeggiator.rs
+//! Tempo-synced arpeggiator in front of the voice allocator.
+//!
+//! The host's note on/off go into the arpeggiator, which keeps the set of
+//! held notes and plays them back one step at a time. It runs per sample and
+//! queues `ArpEvent`s for the synth to hand to the voices, so steps land on
+//! the exact sample. Steps follow the song position while the transport
+//! plays, and run from the first key press when it is stopped.
+
+use crate::rng::Rng;
+use crate::transport::{division_beats, Clock, NUM_DIVISIONS, SIXTEENTH};
+
+/// Note ids of generated notes carry this bit, plus the octave in bits 24–27,
+/// so octave copies of one key never collide with each other or with host ids.
+const ARP_ID: u32 = 0x8000_0000;
+
+/// Highest octave range.
+pub const MAX_OCTAVES: u32 = 4;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ArpMode {
+    #[default]
+    Up,
+    Down,
+    /// Up then down, without repeating the top and bottom notes.
+    UpDown,
+    /// In the order the keys were pressed.
+    AsPlayed,
+    Random,
+    /// Every held note at once, one octave per step.
+    Chord,
+}
+
+impl ArpMode {
+    /// 0 up, 1 down, 2 up-down, 3 as played, 4 random, 5 chord.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => ArpMode::Down,
+            2 => ArpMode::UpDown,
+            3 => ArpMode::AsPlayed,
+            4 => ArpMode::Random,
+            5 => ArpMode::Chord,
+            _ => ArpMode::Up,
+        }
+    }
+}
+
+/// A note for the voices, produced by the arpeggiator.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArpEvent {
+    NoteOn { note_id: u32, freq: f32, velocity: f32 },
+    NoteOff { note_id: u32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct HeldNote {
+    note_id:  u32,
+    freq:     f32,
+    velocity: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Arpeggiator {
+    enabled: bool,
+    mode:    ArpMode,
+    octaves: u32,
+    gate:    f32,   // fraction of a step the note sounds
+    rate:    usize, // sync division index
+    latch:   bool,
+    swing:   f32,   // 0.5 straight .. 0.75, where odd steps start within a pair
+
+    held:     Vec<HeldNote>, // in the order pressed
+    by_pitch: Vec<HeldNote>, // the same notes, lowest first
+    keys_down: usize,        // physically held keys (the rest are latched)
+    sounding: Vec<u32>,      // generated note ids currently on
+    events:   Vec<ArpEvent>, // output of the last `process`
+
+    running:   bool,
+    bpm:       f32,
+    synced:    bool,   // following the host song position
+    beats:     f64,    // clock position
+    step:      u64,    // index of the next step on the grid
+    next_step: f64,    // beat position of the next step
+    gate_off:  f64,    // beat position of the sounding notes' note-off
+    played:    usize,  // steps played since the arpeggio started
+    rng:       Rng,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            mode:    ArpMode::default(),
+            octaves: 1,
+            gate:    0.5,
+            rate:    SIXTEENTH,
+            latch:   false,
+            swing:   0.5,
+            held:      Vec::new(),
+            by_pitch:  Vec::new(),
+            keys_down: 0,
+            sounding:  Vec::new(),
+            events:    Vec::new(),
+            running:   false,
+            bpm:       120.0,
+            synced:    false,
+            beats:     0.0,
+            step:      0,
+            next_step: 0.0,
+            gate_off:  f64::INFINITY,
+            played:    0,
+            rng:       Rng::new(0),
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng.reseed(seed);
+    }
+
+    /// Turning the arpeggiator off releases its notes and forgets held keys.
+    pub fn set_enabled(&mut self, on: bool) {
+        if self.enabled && !on {
+            self.release_sounding();
+            self.held.clear();
+            self.by_pitch.clear();
+            self.keys_down = 0;
+            self.running = false;
+        }
+        self.enabled = on;
+    }
+
+    pub fn set_mode(&mut self, mode: ArpMode) { self.mode = mode; }
+
+    /// Octave range, 1..=4.
+    pub fn set_octaves(&mut self, octaves: u32) {
+        self.octaves = octaves.clamp(1, MAX_OCTAVES);
+    }
+
+    /// Gate length as a fraction of a step, 0.01..=1.0 (1.0 = legato).
+    pub fn set_gate(&mut self, gate: f32) {
+        self.gate = gate.clamp(0.01, 1.0);
+    }
+
+    /// Step length as a sync division index (see `transport::division_beats`).
+    pub fn set_rate(&mut self, rate: usize) {
+        self.rate = rate.min(NUM_DIVISIONS - 1);
+        if self.running {
+            self.schedule_from(self.beats);
+        }
+    }
+
+    /// Latched notes keep playing after the keys are released, until the
+    /// next key press after all keys were up.
+    pub fn set_latch(&mut self, on: bool) {
+        self.latch = on;
+        if !on && self.keys_down == 0 {
+            self.held.clear();
+            self.by_pitch.clear();
+        }
+    }
+
+    /// Swing 0.5 (straight) .. 0.75: where the second step of each pair
+    /// starts, as a fraction of the pair.
+    pub fn set_swing(&mut self, swing: f32) {
+        self.swing = swing.clamp(0.5, 0.75);
+    }
+
+    pub fn is_enabled(&self) -> bool { self.enabled }
+    pub fn mode(&self) -> ArpMode { self.mode }
+    pub fn octaves(&self) -> u32 { self.octaves }
+    pub fn gate(&self) -> f32 { self.gate }
+    pub fn rate(&self) -> usize { self.rate }
+    pub fn latch(&self) -> bool { self.latch }
+    pub fn swing(&self) -> f32 { self.swing }
+
+    /// A key went down.
+    pub fn note_on(&mut self, note_id: u32, freq: f32, velocity: f32) {
+        if self.latch && self.keys_down == 0 {
+            self.held.clear(); // a fresh chord replaces the latched one
+        }
+        self.held.retain(|n| n.note_id != note_id);
+        self.held.push(HeldNote { note_id, freq, velocity });
+        self.sort_held();
+        self.keys_down += 1;
+        if !self.running {
+            self.start();
+        }
+    }
+
+    /// A key went up.
+    pub fn note_off(&mut self, note_id: u32) {
+        if !self.held.iter().any(|n| n.note_id == note_id) {
+            return;
+        }
+        self.keys_down = self.keys_down.saturating_sub(1);
+        if !self.latch {
+            self.held.retain(|n| n.note_id != note_id);
+            self.sort_held();
+        }
+    }
+
+    fn sort_held(&mut self) {
+        self.by_pitch.clone_from(&self.held);
+        self.by_pitch.sort_by(|a, b| a.freq.total_cmp(&b.freq));
+    }
+
+    /// Follow the host tempo, and its song position while it plays.
+    /// Call once per control block, before the transport advances.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.bpm = clock.bpm;
+        self.synced = clock.position.is_some();
+        if let Some(position) = clock.position {
+            let jumped = (position - self.beats).abs() > self.step_beats();
+            self.beats = position;
+            if self.running && jumped {
+                self.schedule_from(position);
+            }
+        }
+    }
+
+    /// Events queued by the last `process` call.
+    pub fn events(&self) -> &[ArpEvent] {
+        &self.events
+    }
+
+    /// Advance by `dt` seconds, queueing any note-offs and note-ons that
+    /// fall in this sample (read them with `events`).
+    pub fn process(&mut self, dt: f32) {
+        self.events.clear();
+        if !self.running {
+            return;
+        }
+        self.beats += dt as f64 * self.bpm as f64 / 60.0;
+        // Note-off first, so a legato gate hands straight over to the next step
+        if self.beats >= self.gate_off {
+            self.release_sounding();
+        }
+        if self.beats >= self.next_step {
+            if self.held.is_empty() {
+                // A swung step's gate can run past the next grid step
+                self.release_sounding();
+                self.running = false;
+                return;
+            }
+            self.release_sounding();
+            self.play_step();
+            self.gate_off = self.next_step + self.step_beats() * self.gate as f64;
+            self.step += 1;
+            self.next_step = self.step_time(self.step);
+        }
+    }
+
+    fn step_beats(&self) -> f64 {
+        division_beats(self.rate) as f64
+    }
+
+    /// Start of grid step `k`: odd steps are pushed late by the swing.
+    fn step_time(&self, k: u64) -> f64 {
+        let len = self.step_beats();
+        let swing = if k % 2 == 1 { (self.swing as f64 - 0.5) * 2.0 * len } else { 0.0 };
+        k as f64 * len + swing
+    }
+
+    /// Aim the next step at the first grid step at or after `beats`.
+    fn schedule_from(&mut self, beats: f64) {
+        self.step = (beats / self.step_beats() - 1e-9).ceil().max(0.0) as u64;
+        self.next_step = self.step_time(self.step);
+    }
+
+    fn start(&mut self) {
+        self.running = true;
+        self.played = 0;
+        self.gate_off = f64::INFINITY;
+        if self.synced {
+            self.schedule_from(self.beats);
+        } else {
+            // Free running: the first step plays on the next sample
+            self.beats = 0.0;
+            self.step = 0;
+            self.next_step = 0.0;
+        }
+    }
+
+    fn release_sounding(&mut self) {
+        for note_id in self.sounding.drain(..) {
+            self.events.push(ArpEvent::NoteOff { note_id });
+        }
+        self.gate_off = f64::INFINITY;
+    }
+
+    fn trigger(&mut self, note: HeldNote, octave: u32) {
+        let note_id = ARP_ID | (octave << 24) | (note.note_id & 0x00FF_FFFF);
+        let freq = note.freq * (1u32 << octave) as f32;
+        self.events.push(ArpEvent::NoteOn { note_id, freq, velocity: note.velocity });
+        self.sounding.push(note_id);
+    }
+
+    fn play_step(&mut self) {
+        let n = self.held.len();
+        let octaves = self.octaves as usize;
+        let step = self.played;
+        self.played += 1;
+
+        if self.mode == ArpMode::Chord {
+            let octave = (step % octaves) as u32;
+            for i in 0..n {
+                self.trigger(self.held[i], octave);
+            }
+            return;
+        }
+
+        // Position in the pattern of `len` notes: `n` per octave, lowest octave first
+        let len = n * octaves;
+        let index = match self.mode {
+            ArpMode::Down => len - 1 - step % len,
+            ArpMode::UpDown if len > 2 => {
+                let cycle = 2 * len - 2;
+                let i = step % cycle;
+                if i < len { i } else { cycle - i }
+            }
+            ArpMode::Random => (self.rng.next_u32() as usize) % len,
+            _ => step % len,
+        };
+        let (octave, i) = ((index / n) as u32, index % n);
+        let note = if self.mode == ArpMode::AsPlayed { self.held[i] } else { self.by_pitch[i] };
+        self.trigger(note, octave);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+    const DT: f32 = 1.0 / SR;
+
+    /// Run for `seconds`, returning (sample, event) pairs.
+    fn run(arp: &mut Arpeggiator, seconds: f32) -> Vec<(usize, ArpEvent)> {
+        let mut out = Vec::new();
+        for s in 0..(seconds * SR) as usize {
+            arp.process(DT);
+            out.extend(arp.events().iter().map(|&e| (s, e)));
+        }
+        out
+    }
+
+    fn on_freqs(events: &[(usize, ArpEvent)]) -> Vec<f32> {
+        events.iter().filter_map(|(_, e)| match e {
+            ArpEvent::NoteOn { freq, .. } => Some(*freq),
+            _ => None,
+        }).collect()
+    }
+
+    fn c_major(arp: &mut Arpeggiator) {
+        arp.set_enabled(true);
+        arp.note_on(64, 330.0, 1.0);
+        arp.note_on(60, 262.0, 1.0);
+        arp.note_on(67, 392.0, 1.0);
+    }
+
+    #[test]
+    fn steps_are_sample_accurate_with_gate() {
+        // 120 bpm 1/16 = 125 ms = 6000 samples, half gate = 3000
+        let mut arp = Arpeggiator::new();
+        c_major(&mut arp);
+        let events = run(&mut arp, 0.3);
+        let times: Vec<usize> = events.iter().map(|(s, _)| *s).collect();
+        assert_eq!(times, vec![0, 3000, 6000, 9000, 12000]);
+        assert_eq!(on_freqs(&events), vec![262.0, 330.0, 392.0]);
+    }
+
+    #[test]
+    fn modes_order_notes() {
+        let pattern = |mode, octaves| {
+            let mut arp = Arpeggiator::new();
+            arp.set_mode(mode);
+            arp.set_octaves(octaves);
+            c_major(&mut arp);
+            on_freqs(&run(&mut arp, 0.125 * 6.0 - 0.01))
+        };
+        assert_eq!(pattern(ArpMode::Down, 1), vec![392.0, 330.0, 262.0, 392.0, 330.0, 262.0]);
+        assert_eq!(pattern(ArpMode::UpDown, 1), vec![262.0, 330.0, 392.0, 330.0, 262.0, 330.0]);
+        assert_eq!(pattern(ArpMode::AsPlayed, 1), vec![330.0, 262.0, 392.0, 330.0, 262.0, 392.0]);
+        assert_eq!(pattern(ArpMode::Up, 2), vec![262.0, 330.0, 392.0, 524.0, 660.0, 784.0]);
+        // chord: all three notes per step, one octave up on the second
+        assert_eq!(pattern(ArpMode::Chord, 2)[..6], [330.0, 262.0, 392.0, 660.0, 524.0, 784.0]);
+    }
+
+    #[test]
+    fn swing_delays_odd_steps() {
+        let mut arp = Arpeggiator::new();
+        arp.set_swing(0.75);
+        arp.set_gate(0.1);
+        c_major(&mut arp);
+        let ons: Vec<usize> = run(&mut arp, 0.3).iter()
+            .filter(|(_, e)| matches!(e, ArpEvent::NoteOn { .. }))
+            .map(|(s, _)| *s)
+            .collect();
+        assert_eq!(ons, vec![0, 9000, 12000]);
+    }
+
+    #[test]
+    fn release_during_a_swung_step_ends_its_note() {
+        // Step 1 starts at 9000 and its 0.9 gate runs to 14400, past step 2 at 12000
+        let mut arp = Arpeggiator::new();
+        arp.set_swing(0.75);
+        arp.set_gate(0.9);
+        arp.set_enabled(true);
+        arp.note_on(60, 262.0, 1.0);
+        let mut events = run(&mut arp, 0.2);
+        arp.note_off(60);
+        events.extend(run(&mut arp, 0.3));
+        let ons = events.iter().filter(|(_, e)| matches!(e, ArpEvent::NoteOn { .. })).count();
+        let offs = events.iter().filter(|(_, e)| matches!(e, ArpEvent::NoteOff { .. })).count();
+        assert_eq!((ons, offs), (2, 2), "every arp note is released");
+        assert!(!arp.running);
+    }
+
+    #[test]
+    fn latch_holds_until_next_chord() {
+        let mut arp = Arpeggiator::new();
+        arp.set_latch(true);
+        c_major(&mut arp);
+        for id in [60, 64, 67] {
+            arp.note_off(id);
+        }
+        assert_eq!(on_freqs(&run(&mut arp, 0.3)).len(), 3, "latched notes keep playing");
+        arp.note_on(72, 524.0, 1.0);
+        assert!(on_freqs(&run(&mut arp, 0.3)).iter().all(|&f| f == 524.0));
+
+        arp.set_latch(false);
+        arp.note_off(72);
+        run(&mut arp, 0.3);
+        assert!(run(&mut arp, 0.3).is_empty(), "stops once nothing is held");
+    }
+
+    #[test]
+    fn follows_song_position_while_playing() {
+        let mut arp = Arpeggiator::new();
+        arp.set_enabled(true);
+        arp.set_clock(Clock { bpm: 120.0, position: Some(0.1) });
+        arp.note_on(60, 262.0, 1.0);
+        // waits for the next 1/16 at beat 0.25: 0.15 beats = 75 ms
+        let first = run(&mut arp, 0.2)[0].0;
+        assert!((first as i64 - 3600).abs() <= 1, "{}", first);
+    }
+}
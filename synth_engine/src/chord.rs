@@ -0,0 +1,465 @@
+// src/chord.rs
+//! Chord memory: one key plays a whole chord.
+//!
+//! The chord is a set of semitone intervals above the played note, picked
+//! from `ChordType` or captured from held notes. Tones can be snapped to a
+//! key and scale, inverted, spread out, and strummed with a delay between
+//! successive tones. Chord notes come out as `ChordEvent`s, sample-accurate
+//! like the arpeggiator, which they feed when it is on.
+
+/// Note ids of chord tones carry this bit, plus the tone in bits 16–23.
+const CHORD_ID: u32 = 0x2000_0000;
+
+/// Most tones in a chord.
+pub const MAX_TONES: usize = 8;
+
+/// Longest strum delay between tones, in milliseconds.
+pub const MAX_STRUM_MS: f32 = 500.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChordType {
+    Major,
+    Minor,
+    Sus2,
+    Sus4,
+    Diminished,
+    Augmented,
+    Major7,
+    Minor7,
+    Dominant7,
+    Minor9,
+    Fifth,
+    Octave,
+}
+
+impl ChordType {
+    /// 0 major, 1 minor, 2 sus2, 3 sus4, 4 dim, 5 aug, 6 maj7, 7 min7,
+    /// 8 dom7, 9 min9, 10 fifth (power chord), 11 octave.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1  => ChordType::Minor,
+            2  => ChordType::Sus2,
+            3  => ChordType::Sus4,
+            4  => ChordType::Diminished,
+            5  => ChordType::Augmented,
+            6  => ChordType::Major7,
+            7  => ChordType::Minor7,
+            8  => ChordType::Dominant7,
+            9  => ChordType::Minor9,
+            10 => ChordType::Fifth,
+            11 => ChordType::Octave,
+            _  => ChordType::Major,
+        }
+    }
+
+    /// Semitones above the root, root included.
+    pub fn intervals(self) -> &'static [i32] {
+        match self {
+            ChordType::Major      => &[0, 4, 7],
+            ChordType::Minor      => &[0, 3, 7],
+            ChordType::Sus2       => &[0, 2, 7],
+            ChordType::Sus4       => &[0, 5, 7],
+            ChordType::Diminished => &[0, 3, 6],
+            ChordType::Augmented  => &[0, 4, 8],
+            ChordType::Major7     => &[0, 4, 7, 11],
+            ChordType::Minor7     => &[0, 3, 7, 10],
+            ChordType::Dominant7  => &[0, 4, 7, 10],
+            ChordType::Minor9     => &[0, 3, 7, 10, 14],
+            ChordType::Fifth      => &[0, 7, 12],
+            ChordType::Octave     => &[0, 12],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scale {
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+}
+
+impl Scale {
+    /// 1 major, 2 natural minor, 3 harmonic minor, 4 dorian, 5 phrygian,
+    /// 6 lydian, 7 mixolydian, 8 locrian, 9 major pentatonic, 10 minor
+    /// pentatonic; 0 (or anything else) = no quantizing.
+    pub fn from_id(id: u32) -> Option<Self> {
+        Some(match id {
+            1  => Scale::Major,
+            2  => Scale::NaturalMinor,
+            3  => Scale::HarmonicMinor,
+            4  => Scale::Dorian,
+            5  => Scale::Phrygian,
+            6  => Scale::Lydian,
+            7  => Scale::Mixolydian,
+            8  => Scale::Locrian,
+            9  => Scale::MajorPentatonic,
+            10 => Scale::MinorPentatonic,
+            _  => return None,
+        })
+    }
+
+    /// Pitch classes in the scale, bit n = n semitones above the key.
+    fn mask(self) -> u16 {
+        let degrees: &[u16] = match self {
+            Scale::Major           => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::NaturalMinor    => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor   => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::Dorian          => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian        => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian          => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian      => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian         => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+        };
+        degrees.iter().fold(0, |m, d| m | (1 << d))
+    }
+
+    /// Snap MIDI note `note` into the scale rooted at `key` (0 = C), to the
+    /// nearest scale note, the lower one on a tie.
+    pub fn quantize(self, key: u32, note: i32) -> i32 {
+        let mask = self.mask();
+        let in_scale = |n: i32| mask & (1 << (n - key as i32).rem_euclid(12)) != 0;
+        (0..12)
+            .flat_map(|d| [note - d, note + d])
+            .find(|&n| in_scale(n))
+            .unwrap_or(note)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Voicing {
+    /// Tones as stacked.
+    #[default]
+    Close,
+    /// Second-highest tone down an octave.
+    Drop2,
+    /// Every other tone up an octave.
+    Spread,
+}
+
+impl Voicing {
+    /// 0 close, 1 drop 2, 2 spread.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => Voicing::Drop2,
+            2 => Voicing::Spread,
+            _ => Voicing::Close,
+        }
+    }
+}
+
+/// A note for the arpeggiator or voices, produced by the chord memory.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChordEvent {
+    NoteOn { note_id: u32, freq: f32, velocity: f32 },
+    NoteOff { note_id: u32 },
+}
+
+#[derive(Copy, Clone, Debug)]
+struct PendingTone {
+    key:      u32, // host note id the tone belongs to
+    note_id:  u32,
+    freq:     f32,
+    velocity: f32,
+    delay:    f32, // seconds until it starts
+}
+
+#[derive(Clone, Debug)]
+pub struct ChordMemory {
+    enabled:   bool,
+    intervals: Vec<i32>,          // semitones from the played note, sorted, 0 included
+    scale:     Option<Scale>,
+    key:       u32,               // scale root, 0 = C
+    inversion: u32,
+    voicing:   Voicing,
+    strum:     f32,               // seconds between tones, < 0 strums top down
+
+    pending:  Vec<PendingTone>,
+    sounding: Vec<(u32, u32)>,    // (host note id, tone note id)
+    events:   Vec<ChordEvent>,
+}
+
+impl Default for ChordMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChordMemory {
+    pub fn new() -> Self {
+        Self {
+            enabled:   false,
+            intervals: ChordType::Major.intervals().to_vec(),
+            scale:     None,
+            key:       0,
+            inversion: 0,
+            voicing:   Voicing::default(),
+            strum:     0.0,
+            pending:   Vec::new(),
+            sounding:  Vec::new(),
+            events:    Vec::new(),
+        }
+    }
+
+    /// Turning chord memory off releases the chords it is playing.
+    pub fn set_enabled(&mut self, on: bool) {
+        self.events.clear();
+        if self.enabled && !on {
+            self.pending.clear();
+            for (_, note_id) in self.sounding.drain(..) {
+                self.events.push(ChordEvent::NoteOff { note_id });
+            }
+        }
+        self.enabled = on;
+    }
+
+    pub fn is_enabled(&self) -> bool { self.enabled }
+
+    pub fn set_chord_type(&mut self, chord: ChordType) {
+        self.set_intervals(chord.intervals());
+    }
+
+    /// Store a chord as semitones above the played note. The root (0) is
+    /// always included; at most `MAX_TONES` tones are kept.
+    pub fn set_intervals(&mut self, intervals: &[i32]) {
+        let mut tones: Vec<i32> = intervals.iter().map(|&i| i.clamp(-48, 48)).collect();
+        tones.push(0);
+        tones.sort_unstable();
+        tones.dedup();
+        tones.truncate(MAX_TONES);
+        self.intervals = tones;
+    }
+
+    /// Store the chord formed by `notes` (frequencies of held notes),
+    /// relative to the lowest one.
+    pub fn capture(&mut self, notes: &[f32]) {
+        let Some(lowest) = notes.iter().copied().filter(|&f| f > 0.0).reduce(f32::min) else { return };
+        let intervals: Vec<i32> = notes
+            .iter()
+            .filter(|&&f| f > 0.0)
+            .map(|&f| (12.0 * (f / lowest).log2()).round() as i32)
+            .collect();
+        self.set_intervals(&intervals);
+    }
+
+    pub fn intervals(&self) -> &[i32] { &self.intervals }
+
+    /// Quantize tones to `scale` in `key` (0 = C … 11 = B), `None` for off.
+    pub fn set_scale(&mut self, key: u32, scale: Option<Scale>) {
+        self.key = key % 12;
+        self.scale = scale;
+    }
+
+    /// Move the lowest `inversion` tones up an octave, at most all but the
+    /// top tone of the chord being played.
+    pub fn set_inversion(&mut self, inversion: u32) {
+        self.inversion = inversion.min(MAX_TONES as u32 - 1);
+    }
+
+    pub fn set_voicing(&mut self, voicing: Voicing) { self.voicing = voicing; }
+
+    /// Delay between tones in ms; negative strums from the top note down.
+    pub fn set_strum_ms(&mut self, ms: f32) {
+        self.strum = ms.clamp(-MAX_STRUM_MS, MAX_STRUM_MS) / 1000.0;
+    }
+
+    /// Events queued by the last `note_on`, `note_off`, `process` or `set_enabled`.
+    pub fn events(&self) -> &[ChordEvent] {
+        &self.events
+    }
+
+    /// Semitone offsets (from the played note) of the chord for MIDI note
+    /// `root`, lowest first, after scale, inversion and voicing. Fills the
+    /// front of `out` and returns the number of tones.
+    fn voiced(&self, root: i32, out: &mut [i32; MAX_TONES]) -> usize {
+        for (note, &i) in out.iter_mut().zip(&self.intervals) {
+            *note = match self.scale {
+                Some(scale) => scale.quantize(self.key, root + i),
+                None => root + i,
+            };
+        }
+        let n = sort_dedup(&mut out[..self.intervals.len()]);
+        let notes = &mut out[..n];
+        // Inverting every tone would only move the whole chord up an octave
+        for _ in 0..(self.inversion as usize).min(n - 1) {
+            notes[0] += 12;
+            notes.sort_unstable();
+        }
+        match self.voicing {
+            Voicing::Close => {}
+            Voicing::Drop2 if n >= 3 => notes[n - 2] -= 12,
+            Voicing::Drop2 => {}
+            Voicing::Spread => {
+                for note in notes.iter_mut().skip(1).step_by(2) {
+                    *note += 12;
+                }
+            }
+        }
+        let n = sort_dedup(notes);
+        for note in &mut out[..n] {
+            *note -= root;
+        }
+        n
+    }
+
+    /// A key went down: queue its chord.
+    pub fn note_on(&mut self, key: u32, freq: f32, velocity: f32) {
+        self.events.clear();
+        self.release(key);
+        let root = (69.0 + 12.0 * (freq / 440.0).log2()).round() as i32;
+        let mut offsets = [0; MAX_TONES];
+        let n = self.voiced(root, &mut offsets);
+        for (i, &offset) in offsets[..n].iter().enumerate() {
+            let order = if self.strum < 0.0 { n - 1 - i } else { i };
+            let tone = PendingTone {
+                key,
+                note_id: CHORD_ID | ((i as u32) << 16) | (key & 0xFFFF),
+                freq: freq * 2.0f32.powf(offset as f32 / 12.0),
+                velocity,
+                delay: order as f32 * self.strum.abs(),
+            };
+            if tone.delay <= 0.0 {
+                self.start(tone);
+            } else {
+                self.pending.push(tone);
+            }
+        }
+    }
+
+    /// A key went up: release its chord, dropping tones not yet strummed.
+    pub fn note_off(&mut self, key: u32) {
+        self.events.clear();
+        self.release(key);
+    }
+
+    /// Advance by `dt` seconds, queueing strummed tones that are due.
+    pub fn process(&mut self, dt: f32) {
+        self.events.clear();
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut i = 0;
+        while i < self.pending.len() {
+            self.pending[i].delay -= dt;
+            if self.pending[i].delay <= 0.0 {
+                let tone = self.pending.remove(i);
+                self.start(tone);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn start(&mut self, tone: PendingTone) {
+        self.events.push(ChordEvent::NoteOn { note_id: tone.note_id, freq: tone.freq, velocity: tone.velocity });
+        self.sounding.push((tone.key, tone.note_id));
+    }
+
+    fn release(&mut self, key: u32) {
+        self.pending.retain(|t| t.key != key);
+        let events = &mut self.events;
+        self.sounding.retain(|&(k, note_id)| {
+            if k == key {
+                events.push(ChordEvent::NoteOff { note_id });
+            }
+            k != key
+        });
+    }
+}
+
+/// Sort `notes` and move the distinct values to the front; returns their count.
+fn sort_dedup(notes: &mut [i32]) -> usize {
+    notes.sort_unstable();
+    let mut n = 0;
+    for i in 0..notes.len() {
+        if n == 0 || notes[i] != notes[n - 1] {
+            notes[n] = notes[i];
+            n += 1;
+        }
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 48_000.0;
+
+    fn voiced(chord: &ChordMemory, root: i32) -> Vec<i32> {
+        let mut out = [0; MAX_TONES];
+        let n = chord.voiced(root, &mut out);
+        out[..n].to_vec()
+    }
+
+    #[test]
+    fn one_key_plays_the_chord() {
+        let mut chord = ChordMemory::new();
+        chord.set_enabled(true);
+        chord.set_chord_type(ChordType::Minor7);
+        chord.note_on(57, 220.0, 100.0);
+        let freqs: Vec<f32> = chord.events().iter().filter_map(|e| match e {
+            ChordEvent::NoteOn { freq, .. } => Some(*freq),
+            _ => None,
+        }).collect();
+        assert_eq!(freqs.len(), 4);
+        assert!((freqs[1] - 220.0 * 2.0f32.powf(3.0 / 12.0)).abs() < 0.01);
+        chord.note_off(57);
+        assert_eq!(chord.events().len(), 4, "every tone released");
+    }
+
+    #[test]
+    fn capture_scale_and_voicing() {
+        let mut chord = ChordMemory::new();
+        chord.capture(&[329.63, 261.63, 392.0, 493.88]); // E C G B
+        assert_eq!(chord.intervals(), &[0, 4, 7, 11]);
+
+        // Major triad shape snapped into A minor from D: D F A
+        chord.set_chord_type(ChordType::Major);
+        chord.set_scale(9, Scale::from_id(2));
+        assert_eq!(voiced(&chord, 62), vec![0, 3, 7]);
+
+        chord.set_scale(0, None);
+        chord.set_inversion(1);
+        assert_eq!(voiced(&chord, 60), vec![4, 7, 12]);
+        // A triad has two inversions; asking for more stays on the second
+        chord.set_inversion(5);
+        assert_eq!(voiced(&chord, 60), vec![7, 12, 16]);
+        chord.set_inversion(0);
+        chord.set_chord_type(ChordType::Major7);
+        chord.set_voicing(Voicing::Drop2);
+        assert_eq!(voiced(&chord, 60), vec![-5, 0, 4, 11]);
+        chord.set_voicing(Voicing::Spread);
+        assert_eq!(voiced(&chord, 60), vec![0, 7, 16, 23]);
+    }
+
+    #[test]
+    fn strum_delays_tones_and_release_cancels_the_rest() {
+        let mut chord = ChordMemory::new();
+        chord.set_enabled(true);
+        chord.set_strum_ms(-10.0); // top down
+        chord.note_on(60, 261.63, 100.0);
+        let first = chord.events().to_vec();
+        assert!(matches!(first[..], [ChordEvent::NoteOn { freq, .. }] if (freq - 392.0).abs() < 0.1));
+        let mut started = 1;
+        for _ in 0..(0.015 / DT) as usize {
+            chord.process(DT);
+            started += chord.events().len();
+        }
+        assert_eq!(started, 2, "second tone after 10 ms");
+        chord.note_off(60);
+        assert_eq!(chord.events().len(), 2);
+        for _ in 0..(0.02 / DT) as usize {
+            chord.process(DT);
+            assert!(chord.events().is_empty(), "released before the last tone");
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// src/dx_envelope.rs
+//! Yamaha DX-style rate/level envelope generator.
+//!
+//! Four rates (R1–R4) and four levels (L1–L4), all 0–99. On note-on the
+//! envelope moves from wherever it is to L1 at R1, then to L2 at R2 and L3
+//! at R3, and holds L3 until note-off; it then moves to L4 at R4 and stays
+//! there. A fresh envelope starts at L4, as on the DX7.
+//!
+//! Levels move linearly in a log (dB) domain, 0.75 dB per level step, so
+//! decays come out exponential like the original. Level 0 is silence.
+
+use crate::envelope_trait::EnvelopeTrait;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum DxStage {
+    /// Moving towards `levels[i]` at `rates[i]` (0–2 after note-on, 3 after note-off).
+    Segment(usize),
+    /// Holding L3 while the key is down.
+    Sustain,
+    /// Reached L4 after note-off.
+    Done,
+}
+
+#[derive(Clone, Debug)]
+pub struct DxEnvelope {
+    rates:  [u8; 4],
+    levels: [u8; 4],
+    stage:  DxStage,
+    level:  f32, // current position in 0–99 level units
+    output: f32, // amplitude 0.0–1.0
+}
+
+impl DxEnvelope {
+    /// Seconds for a full-scale (0 ↔ 99) move at rate 0; rate 99 is ~3 ms.
+    const SLOWEST: f32 = 38.0;
+
+    pub fn new(rates: [u8; 4], levels: [u8; 4]) -> Self {
+        let levels = levels.map(|l| l.min(99));
+        let start = levels[3] as f32;
+        Self {
+            rates: rates.map(|r| r.min(99)),
+            levels,
+            stage: DxStage::Done,
+            level: start,
+            output: Self::amplitude(start),
+        }
+    }
+
+    /// Change rates and levels without restarting the envelope. A held or
+    /// finished envelope moves to its new L3 or L4 at R3 or R4.
+    pub fn set_params(&mut self, rates: [u8; 4], levels: [u8; 4]) {
+        self.rates = rates.map(|r| r.min(99));
+        self.levels = levels.map(|l| l.min(99));
+        self.stage = match self.stage {
+            DxStage::Sustain => DxStage::Segment(2),
+            DxStage::Done => DxStage::Segment(3),
+            stage => stage,
+        };
+    }
+
+    pub fn rates(&self) -> [u8; 4] {
+        self.rates
+    }
+
+    pub fn levels(&self) -> [u8; 4] {
+        self.levels
+    }
+
+    /// Level units per second for a 0–99 rate.
+    fn speed(rate: u8) -> f32 {
+        99.0 / (Self::SLOWEST * 2.0f32.powf(-(rate as f32) / 7.26))
+    }
+
+    /// 0–99 level → amplitude, 0.75 dB per step, 99 = unity and 0 = silent.
+    fn amplitude(level: f32) -> f32 {
+        if level <= 0.0 {
+            0.0
+        } else {
+            10.0f32.powf((level - 99.0) * 0.75 / 20.0)
+        }
+    }
+
+    pub fn note_on(&mut self) {
+        self.stage = DxStage::Segment(0);
+    }
+
+    pub fn note_off(&mut self) {
+        self.stage = DxStage::Segment(3);
+    }
+
+    pub fn process(&mut self, dt: f32) -> f32 {
+        if let DxStage::Segment(i) = self.stage {
+            let target = self.levels[i] as f32;
+            let step = Self::speed(self.rates[i]) * dt;
+            let diff = target - self.level;
+            if diff.abs() <= step {
+                self.level = target;
+                self.stage = match i {
+                    0 | 1 => DxStage::Segment(i + 1),
+                    2 => DxStage::Sustain,
+                    _ => DxStage::Done,
+                };
+            } else {
+                self.level += step.copysign(diff);
+            }
+            self.output = Self::amplitude(self.level);
+        }
+        self.output
+    }
+
+    /// Whether the envelope is holding L3 with the key down.
+    pub fn is_sustaining(&self) -> bool {
+        self.stage == DxStage::Sustain
+    }
+}
+
+impl EnvelopeTrait for DxEnvelope {
+    fn note_on(&mut self)           { self.note_on() }
+    fn note_off(&mut self)          { self.note_off() }
+    fn process(&mut self, dt: f32) -> f32 { self.process(dt) }
+    fn get_level(&self)     -> f32 { self.output }
+    fn is_finished(&self)   -> bool { self.stage == DxStage::Done && self.output == 0.0 }
+    fn set_dx_params(&mut self, rates: [u8; 4], levels: [u8; 4]) -> bool {
+        self.set_params(rates, levels);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 48_000.0;
+
+    fn run(env: &mut DxEnvelope, seconds: f32) -> f32 {
+        let mut v = env.get_level();
+        for _ in 0..(seconds / DT) as usize {
+            v = env.process(DT);
+        }
+        v
+    }
+
+    #[test]
+    fn walks_segments_holds_l3_then_releases_to_l4() {
+        let mut env = DxEnvelope::new([99, 99, 99, 99], [99, 80, 90, 0]);
+        assert_eq!(env.get_level(), 0.0, "starts at L4");
+        env.note_on();
+        run(&mut env, 0.05);
+        assert!(env.is_sustaining());
+        assert!((env.get_level() - DxEnvelope::amplitude(90.0)).abs() < 1e-6);
+        assert_eq!(run(&mut env, 1.0), DxEnvelope::amplitude(90.0), "holds L3");
+        env.note_off();
+        assert_eq!(run(&mut env, 0.05), 0.0);
+    }
+
+    #[test]
+    fn rate_sets_segment_time() {
+        // A full-scale decay at rate 50 vs the speed table
+        let mut env = DxEnvelope::new([99, 50, 99, 99], [99, 0, 0, 0]);
+        env.note_on();
+        while env.stage == DxStage::Segment(0) {
+            env.process(DT); // reach L1
+        }
+        let expected = 99.0 / DxEnvelope::speed(50);
+        let mut t = 0.0;
+        while env.stage == DxStage::Segment(1) && t < 60.0 {
+            env.process(DT);
+            t += DT;
+        }
+        assert!((t - expected).abs() < expected * 0.01, "{}s vs {}s", t, expected);
+        // decays fall in dB: half way through in time is -37 dB, not half amplitude
+        assert!(DxEnvelope::amplitude(49.5) < 0.02);
+    }
+
+    #[test]
+    fn param_changes_move_from_the_current_level() {
+        let mut env = DxEnvelope::new([99, 99, 99, 99], [99, 99, 99, 0]);
+        env.note_on();
+        let held = run(&mut env, 0.05);
+        env.set_params([99, 99, 20, 99], [99, 99, 80, 0]);
+        assert!((env.process(DT) - held).abs() < 1e-3, "no jump when L3 changes");
+        assert!(run(&mut env, 0.5) < held, "heads for the new L3");
+        run(&mut env, 30.0);
+        assert!(env.is_sustaining());
+        assert!((env.get_level() - DxEnvelope::amplitude(80.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn retrigger_starts_from_current_level() {
+        let mut env = DxEnvelope::new([10, 99, 99, 99], [99, 99, 99, 0]);
+        env.note_on();
+        let mid = run(&mut env, 1.0);
+        assert!(mid > 0.0 && mid < 1.0);
+        env.note_on();
+        assert!(env.process(DT) >= mid, "no reset to L4 on retrigger");
+    }
+}
@@ -0,0 +1,249 @@
+// src/effects/chain.rs
+//! Order of the insert effects after the synth's amp section.
+//!
+//! The chain only names effects (`EffectSlot`s) in a fixed-size array; the
+//! effects themselves live in `Effects`. Adding, removing, reordering and
+//! bypassing slots is plain array shuffling, so the host can rearrange the
+//! chain between audio blocks without allocating.
+
+/// Most effects one chain can run.
+pub const MAX_SLOTS: usize = 8;
+
+/// Most extra effects `Effects` can hold besides its built-in three.
+pub const MAX_INSERTS: usize = 8;
+
+/// An effect the chain can run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EffectSlot {
+    Chorus,
+    Delay,
+    Reverb,
+    /// The n-th effect added with `Effects::add_insert`.
+    Insert(usize),
+}
+
+impl EffectSlot {
+    /// 0 chorus, 1 delay, 2 reverb, 3 + n insert n.
+    pub fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(EffectSlot::Chorus),
+            1 => Some(EffectSlot::Delay),
+            2 => Some(EffectSlot::Reverb),
+            n if ((n - 3) as usize) < MAX_INSERTS => Some(EffectSlot::Insert((n - 3) as usize)),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> u32 {
+        match self {
+            EffectSlot::Chorus => 0,
+            EffectSlot::Delay => 1,
+            EffectSlot::Reverb => 2,
+            EffectSlot::Insert(n) => 3 + n as u32,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EffectChain {
+    slots: [(EffectSlot, bool); MAX_SLOTS], // effect and whether it is switched on
+    len: usize,
+}
+
+impl Default for EffectChain {
+    /// Chorus → delay → reverb: time modulation before the time-based
+    /// effects, and the longest tail last.
+    fn default() -> Self {
+        let mut chain = Self::empty();
+        for slot in [EffectSlot::Chorus, EffectSlot::Delay, EffectSlot::Reverb] {
+            chain.insert(chain.len(), slot);
+        }
+        chain
+    }
+}
+
+impl EffectChain {
+    pub fn empty() -> Self {
+        Self { slots: [(EffectSlot::Chorus, true); MAX_SLOTS], len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The effects in processing order, each with its on/off switch.
+    pub fn slots(&self) -> &[(EffectSlot, bool)] {
+        &self.slots[..self.len]
+    }
+
+    pub fn position(&self, slot: EffectSlot) -> Option<usize> {
+        self.slots().iter().position(|&(s, _)| s == slot)
+    }
+
+    /// Insert `slot` (switched on) before position `index`, or at the end
+    /// if `index` is past it. Each effect runs at most once, so this fails
+    /// when `slot` is already in the chain or the chain is full.
+    pub fn insert(&mut self, index: usize, slot: EffectSlot) -> bool {
+        if self.len == MAX_SLOTS || self.position(slot).is_some() {
+            return false;
+        }
+        let index = index.min(self.len);
+        self.slots.copy_within(index..self.len, index + 1);
+        self.slots[index] = (slot, true);
+        self.len += 1;
+        true
+    }
+
+    /// Take the effect at `index` out of the chain.
+    pub fn remove(&mut self, index: usize) -> Option<EffectSlot> {
+        if index >= self.len {
+            return None;
+        }
+        let (slot, _) = self.slots[index];
+        self.slots.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        Some(slot)
+    }
+
+    /// Move the effect at `from` so it ends up at `to` (clamped to the
+    /// last position), shifting the ones between.
+    pub fn move_slot(&mut self, from: usize, to: usize) {
+        if from >= self.len {
+            return;
+        }
+        let to = to.min(self.len - 1);
+        if from < to {
+            self.slots[from..=to].rotate_left(1);
+        } else {
+            self.slots[to..=from].rotate_right(1);
+        }
+    }
+
+    /// Replace the whole chain with `slots` in order, skipping repeats and
+    /// anything past `MAX_SLOTS`.
+    pub fn set_order(&mut self, slots: impl IntoIterator<Item = EffectSlot>) {
+        let old = *self;
+        self.len = 0;
+        for slot in slots {
+            if self.insert(self.len, slot) {
+                // A slot that was already in the chain keeps its switch
+                self.slots[self.len - 1].1 = old.is_enabled(slot);
+            }
+        }
+    }
+
+    /// Switch an effect in the chain on or off (bypass) without moving it.
+    pub fn set_enabled(&mut self, slot: EffectSlot, on: bool) {
+        if let Some(i) = self.position(slot) {
+            self.slots[i].1 = on;
+        }
+    }
+
+    /// Whether `slot` is switched on; effects not in the chain count as on
+    /// so they come back live when inserted.
+    pub fn is_enabled(&self, slot: EffectSlot) -> bool {
+        self.position(slot).is_none_or(|i| self.slots[i].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Effects;
+    use super::super::effect::{Effect, ParamDescriptor};
+    use EffectSlot::*;
+
+    /// Hard clip at a settable level, with made-up lookahead.
+    struct Clip(f32);
+
+    impl Effect for Clip {
+        fn name(&self) -> &'static str { "clip" }
+        fn process(&mut self, l: f32, r: f32, _dt: f32) -> (f32, f32) {
+            (l.clamp(-self.0, self.0), r.clamp(-self.0, self.0))
+        }
+        fn reset(&mut self) {}
+        fn latency(&self) -> usize { 64 }
+        fn params(&self) -> &'static [ParamDescriptor] {
+            const PARAMS: [ParamDescriptor; 1] = [ParamDescriptor::new("level", 0.0, 1.0, 1.0, "")];
+            &PARAMS
+        }
+        fn set_param(&mut self, index: usize, value: f32) {
+            if index == 0 { self.0 = value }
+        }
+        fn param(&self, index: usize) -> f32 {
+            if index == 0 { self.0 } else { 0.0 }
+        }
+    }
+
+    fn order(chain: &EffectChain) -> Vec<EffectSlot> {
+        chain.slots().iter().map(|&(s, _)| s).collect()
+    }
+
+    #[test]
+    fn insert_remove_and_move() {
+        let mut chain = EffectChain::default();
+        assert_eq!(order(&chain), vec![Chorus, Delay, Reverb]);
+        assert!(!chain.insert(0, Delay), "each effect at most once");
+        assert!(chain.insert(1, Insert(0)));
+        assert_eq!(order(&chain), vec![Chorus, Insert(0), Delay, Reverb]);
+
+        chain.move_slot(3, 0);
+        assert_eq!(order(&chain), vec![Reverb, Chorus, Insert(0), Delay]);
+        chain.move_slot(0, 99);
+        assert_eq!(order(&chain), vec![Chorus, Insert(0), Delay, Reverb]);
+
+        assert_eq!(chain.remove(1), Some(Insert(0)));
+        assert_eq!(chain.remove(3), None);
+        assert_eq!(order(&chain), vec![Chorus, Delay, Reverb]);
+    }
+
+    #[test]
+    fn set_order_keeps_bypass_switches() {
+        let mut chain = EffectChain::default();
+        chain.set_enabled(Delay, false);
+        chain.set_order([Reverb, Delay, Reverb, Chorus]);
+        assert_eq!(order(&chain), vec![Reverb, Delay, Chorus]);
+        assert!(!chain.is_enabled(Delay));
+        assert!(chain.is_enabled(Reverb));
+
+        assert_eq!(EffectSlot::from_id(4), Some(Insert(1)));
+        assert_eq!(EffectSlot::from_id(3 + MAX_INSERTS as u32), None);
+        assert_eq!(Insert(1).id(), 4);
+    }
+
+    #[test]
+    fn effects_run_in_chain_order() {
+        let dt = 1.0 / 48_000.0;
+        let mut fx = Effects::new(48_000.0);
+        fx.delay.set_mix(1.0);
+        fx.delay.set_delay_ms(1.0); // 48 samples
+        let clip = fx.add_insert(Box::new(Clip(0.5))).unwrap();
+        assert_eq!(clip, Insert(0));
+        fx.chain.set_order([clip, Delay]);
+        assert_eq!(fx.latency(), 64);
+        for _ in 0..4800 {
+            fx.process(0.0, 0.0, dt); // past the mix ramp
+        }
+
+        // An impulse, its echo and the echo's echo
+        let run = |fx: &mut Effects| {
+            fx.reset();
+            let out: Vec<f32> = (0..=96).map(|i| fx.process(if i == 0 { 1.0 } else { 0.0 }, 0.0, dt).0).collect();
+            [out[0], out[48], out[96]]
+        };
+        let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3);
+        assert!(close(run(&mut fx), [0.5, 0.5, 0.25]), "clipped, then echoed");
+        fx.chain.move_slot(0, 1);
+        assert!(close(run(&mut fx), [0.5, 0.5, 0.5]), "echoes clipped on the way out");
+        fx.effect_mut(clip).unwrap().set_param(0, 0.25);
+        assert!(close(run(&mut fx), [0.25, 0.25, 0.25]));
+
+        fx.chain.set_enabled(clip, false);
+        assert_eq!(fx.latency(), 0);
+        assert!(close(run(&mut fx), [1.0, 1.0, 0.5]), "bypassed");
+    }
+}
@@ -1,4 +1,22 @@
 use crate::filter::{Filter, FilterType};
+use crate::smoother::Smoother;
+use super::effect::{Effect, ParamDescriptor};
+
+const PARAMS: [ParamDescriptor; 6] = [
+    ParamDescriptor::new("depth",       0.0, 1.0,    0.0, ""),
+    ParamDescriptor::new("speed",       0.0, 10.0,   1.0, "Hz"),
+    ParamDescriptor::new("hpf_cutoff",  20.0, 2000.0, 20.0, "Hz"),
+    ParamDescriptor::new("width",       0.0, 1.0,    0.5, ""),
+    ParamDescriptor::new("delay",       0.0, 200.0,  7.0, "ms"),
+    ParamDescriptor::new("reverb_send", 0.0, 1.0,    0.0, ""),
+];
+
+/// Modulation offsets added to the user settings, reset every control block.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChorusMod {
+    pub depth: f32,
+    pub speed: f32,
+}
 
 /// Stereo chorus effect
 pub struct Chorus {
@@ -20,13 +38,21 @@ pub struct Chorus {
     /// Delay buffer (mono) for simplicity
     buffer: Vec<f32>,
     write_idx: usize,
+    /// LFO / mod-matrix offsets
+    pub modulation: ChorusMod,
+    depth_smoother: Smoother,
 }
 
 impl Chorus {
     /// Create a Chorus with reasonable defaults
     pub fn new(sample_rate: f32) -> Self {
-        // maximum delay: base + depth ~ 100 ms
-        let max_delay_samples = (sample_rate * 0.2) as usize;
+        let mut chorus = Self::unallocated(sample_rate);
+        chorus.allocate();
+        chorus
+    }
+
+    /// Settings only, no delay line yet: `allocate` before processing.
+    pub fn unallocated(sample_rate: f32) -> Self {
         Self {
             sample_rate,
             depth: 0.0,
@@ -43,11 +69,31 @@ impl Chorus {
             delay_ms: 7.0,
             reverb_send: 0.0,
             lfo_phase: 0.0,
-            buffer: vec![0.0; max_delay_samples],
+            buffer: Vec::new(),
             write_idx: 0,
+            modulation: ChorusMod::default(),
+            depth_smoother: Smoother::new(0.0, 0.005),
         }
     }
 
+    /// Size the delay line (a no-op once it is).
+    pub fn allocate(&mut self) {
+        if self.buffer.is_empty() {
+            // maximum delay: base + depth ~ 100 ms
+            self.buffer = vec![0.0; (self.sample_rate * 0.2) as usize];
+            self.write_idx = 0;
+        }
+    }
+
+    /// Free the delay line, keeping the settings.
+    pub fn release_buffers(&mut self) {
+        self.buffer = Vec::new();
+    }
+
+    pub fn is_allocated(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
     /// Set the chorus mix depth (0.0–1.0)
     pub fn set_depth(&mut self, d: f32) { self.depth = d.clamp(0.0, 1.0); }
     /// Set the LFO speed in Hz
@@ -71,20 +117,40 @@ impl Chorus {
 
     /// Process one stereo sample, returns (left, right)
     pub fn process(&mut self, input_l: f32, input_r: f32, dt: f32) -> (f32, f32) {
+        // Additive mix — dry stays at full level, wet adds on top.
+        // Avoids volume dips from phase cancellation in a crossfade approach.
+        // Width is linear, so widening dry and wet separately is the same as
+        // widening the mix.
+        let (wet_l, wet_r) = self.process_wet(input_l, input_r, dt);
+        let (dry_l, dry_r) = self.widen(input_l, input_r);
+        (dry_l + wet_l, dry_r + wet_r)
+    }
+
+    /// Stereo width: scale the difference from center.
+    fn widen(&self, l: f32, r: f32) -> (f32, f32) {
+        let center = 0.5 * (l + r);
+        (center + (l - center) * self.width, center + (r - center) * self.width)
+    }
+
+    /// Process one stereo sample and return only the chorus voices (an
+    /// effect-send return).
+    pub fn process_wet(&mut self, input_l: f32, input_r: f32, dt: f32) -> (f32, f32) {
         // mono sum
         let input = 0.5 * (input_l + input_r);
         
         // write into delay buffer
         self.buffer[self.write_idx] = input;
         
-        // advance LFO phase
-        self.lfo_phase = (self.lfo_phase + self.speed * dt) % 1.0;
+        // advance LFO phase (speed modulation only changes the rate, so no smoothing)
+        let speed = (self.speed + self.modulation.speed).max(0.0);
+        self.lfo_phase = (self.lfo_phase + speed * dt) % 1.0;
+        let depth = self.depth_smoother.next((self.depth + self.modulation.depth).clamp(0.0, 1.0), dt);
         let lfo = (2.0 * std::f32::consts::PI * self.lfo_phase).sin();
         let lfo2 = (2.0 * std::f32::consts::PI * (self.lfo_phase + 0.5)).sin();
         
         // compute variable delays (samples)
         let base = self.delay_ms * 0.001 * self.sample_rate;
-        let var = self.depth * 0.005 * self.sample_rate; // depth ~ ±5 ms
+        let var = depth * 0.005 * self.sample_rate; // depth ~ ±5 ms
         let buf_max = (self.buffer.len() - 2) as f32;
         let d1 = (base + var * lfo).clamp(1.0, buf_max);
         let d2 = (base + var * lfo2).clamp(1.0, buf_max);
@@ -110,19 +176,47 @@ impl Chorus {
         let wet1 = self.hpf.process(wet1, dt);
         let wet2 = self.hpf.process(wet2, dt);
 
-        // Additive mix — dry stays at full level, wet adds on top.
-        // Avoids volume dips from phase cancellation in a crossfade approach.
-        let mixed_l = input_l + wet1 * self.depth * 0.5;
-        let mixed_r = input_r + wet2 * self.depth * 0.5;
-
-        // stereo width: scale difference from center
-        let center_l = 0.5 * (mixed_l + mixed_r);
-        let diff_l = mixed_l - center_l;
-        let diff_r = mixed_r - center_l;
-        let out_l = center_l + diff_l * self.width;
-        let out_r = center_l + diff_r * self.width;
-
-        // (reverb_send can be tapped off separately if desired)
-        (out_l, out_r)
+        self.widen(wet1 * depth * 0.5, wet2 * depth * 0.5)
+    }
+}
+
+impl Effect for Chorus {
+    fn name(&self) -> &'static str { "chorus" }
+
+    fn process(&mut self, l: f32, r: f32, dt: f32) -> (f32, f32) {
+        Chorus::process(self, l, r, dt)
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_idx = 0;
+        self.lfo_phase = 0.0;
+        self.hpf.reset();
+    }
+
+    fn params(&self) -> &'static [ParamDescriptor] { &PARAMS }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_depth(value),
+            1 => self.set_speed(value),
+            2 => self.set_hpf_cutoff(value),
+            3 => self.set_width(value),
+            4 => self.set_delay_ms(value),
+            5 => self.set_reverb_send(value),
+            _ => {}
+        }
+    }
+
+    fn param(&self, index: usize) -> f32 {
+        match index {
+            0 => self.depth,
+            1 => self.speed,
+            2 => self.hpf.cutoff(),
+            3 => self.width,
+            4 => self.delay_ms,
+            5 => self.reverb_send,
+            _ => 0.0,
+        }
     }
 }
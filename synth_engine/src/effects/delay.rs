@@ -1,5 +1,22 @@
 use std::collections::VecDeque;
 
+use crate::smoother::Smoother;
+use super::effect::{Effect, ParamDescriptor};
+
+const PARAMS: [ParamDescriptor; 3] = [
+    ParamDescriptor::new("time",     0.0, 2000.0, 500.0, "ms"),
+    ParamDescriptor::new("feedback", 0.0, 0.99,   0.5,   ""),
+    ParamDescriptor::new("mix",      0.0, 1.0,    0.0,   ""),
+];
+
+/// Modulation offsets added to the user settings, reset every control block.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DelayMod {
+    pub time_ms: f32,
+    pub feedback: f32,
+    pub mix: f32,
+}
+
 /// Simple stereo delay effect with feedback and modulation
 pub struct Delay {
     sample_rate: f32,
@@ -11,25 +28,57 @@ pub struct Delay {
     current_delay_ms: f32,     // smoothed delay time used for reading
     feedback: f32,
     mix: f32,
+    pub modulation: DelayMod,
+    feedback_smoother: Smoother,
+    mix_smoother: Smoother,
 }
 
 impl Delay {
     pub fn new(sample_rate: f32) -> Self {
+        let mut delay = Self::unallocated(sample_rate);
+        delay.allocate();
+        delay
+    }
+
+    /// Settings only, no delay lines yet: `allocate` before processing.
+    pub fn unallocated(sample_rate: f32) -> Self {
         let max_delay_time = 2000.0; // 2 seconds max
         let max_delay_samples = (sample_rate * max_delay_time / 1000.0) as usize;
         Self {
             sample_rate,
-            buffer_l: VecDeque::from(vec![0.0; max_delay_samples]),
-            buffer_r: VecDeque::from(vec![0.0; max_delay_samples]),
+            buffer_l: VecDeque::new(),
+            buffer_r: VecDeque::new(),
             write_pos: 0,
             max_delay_samples,
             delay_time_ms: 500.0,
             current_delay_ms: 500.0,
             feedback: 0.5,
             mix: 0.0,
+            modulation: DelayMod::default(),
+            feedback_smoother: Smoother::new(0.5, 0.005),
+            mix_smoother: Smoother::new(0.0, 0.005),
+        }
+    }
+
+    /// Size the delay lines (a no-op once they are).
+    pub fn allocate(&mut self) {
+        if self.buffer_l.is_empty() {
+            self.buffer_l = VecDeque::from(vec![0.0; self.max_delay_samples]);
+            self.buffer_r = VecDeque::from(vec![0.0; self.max_delay_samples]);
+            self.write_pos = 0;
         }
     }
 
+    /// Free the delay lines, keeping the settings.
+    pub fn release_buffers(&mut self) {
+        self.buffer_l = VecDeque::new();
+        self.buffer_r = VecDeque::new();
+    }
+
+    pub fn is_allocated(&self) -> bool {
+        !self.buffer_l.is_empty()
+    }
+
     pub fn set_delay_ms(&mut self, ms: f32) {
         self.delay_time_ms = ms.clamp(0.0, self.max_delay_samples as f32 * 1000.0 / self.sample_rate);
     }
@@ -56,13 +105,25 @@ impl Delay {
         self.mix
     }
 
-    pub fn process(&mut self, input_l: f32, input_r: f32, _dt: f32) -> (f32, f32) {
+    pub fn process(&mut self, input_l: f32, input_r: f32, dt: f32) -> (f32, f32) {
+        // Dry stays at full level, wet echoes add on top
+        let (wet_l, wet_r) = self.process_wet(input_l, input_r, dt);
+        (input_l + wet_l, input_r + wet_r)
+    }
+
+    /// Process one stereo sample and return only the echoes, scaled by the
+    /// mix (an effect-send return).
+    pub fn process_wet(&mut self, input_l: f32, input_r: f32, dt: f32) -> (f32, f32) {
         // Rate-limit delay time changes to at most 0.5 samples worth per audio sample.
         // Exponential smoothing causes burst pitch artifacts for large jumps (e.g. 10→1000ms).
         // A hard rate cap ensures the read head never moves faster than ~1.5x, giving a
         // smooth pitch glide (like tape being sped/slowed) with no discontinuities.
+        // Modulated delay time goes through the same glide, so LFOs give tape wobble.
         let max_per_step = 0.5 * 1000.0 / self.sample_rate;
-        let diff = self.delay_time_ms - self.current_delay_ms;
+        // keep two samples of headroom for the interpolated read
+        let max_ms = (self.max_delay_samples - 2) as f32 * 1000.0 / self.sample_rate;
+        let target_ms = (self.delay_time_ms + self.modulation.time_ms).clamp(0.0, max_ms);
+        let diff = target_ms - self.current_delay_ms;
         self.current_delay_ms += diff.clamp(-max_per_step, max_per_step);
 
         // Fractional delay: interpolate between two adjacent samples to eliminate
@@ -78,16 +139,84 @@ impl Delay {
         let delayed_l = self.buffer_l[pos0] * (1.0 - frac) + self.buffer_l[pos1] * frac;
         let delayed_r = self.buffer_r[pos0] * (1.0 - frac) + self.buffer_r[pos1] * frac;
 
-        // Each channel feeds back into itself
-        self.buffer_l[self.write_pos] = (input_l + delayed_l * self.feedback).clamp(-4.0, 4.0);
-        self.buffer_r[self.write_pos] = (input_r + delayed_r * self.feedback).clamp(-4.0, 4.0);
+        let feedback = self.feedback_smoother.next((self.feedback + self.modulation.feedback).clamp(0.0, 0.99), dt);
+        let mix = self.mix_smoother.next((self.mix + self.modulation.mix).clamp(0.0, 1.0), dt);
 
-        // Dry stays at full level, wet echoes add on top
-        let out_l = input_l + delayed_l * self.mix;
-        let out_r = input_r + delayed_r * self.mix;
+        // Each channel feeds back into itself
+        self.buffer_l[self.write_pos] = (input_l + delayed_l * feedback).clamp(-4.0, 4.0);
+        self.buffer_r[self.write_pos] = (input_r + delayed_r * feedback).clamp(-4.0, 4.0);
 
         self.write_pos = (self.write_pos + 1) % self.max_delay_samples;
 
-        (out_l, out_r)
+        (delayed_l * mix, delayed_r * mix)
+    }
+}
+
+impl Effect for Delay {
+    fn name(&self) -> &'static str { "delay" }
+
+    fn process(&mut self, l: f32, r: f32, dt: f32) -> (f32, f32) {
+        Delay::process(self, l, r, dt)
+    }
+
+    fn reset(&mut self) {
+        self.buffer_l.iter_mut().for_each(|s| *s = 0.0);
+        self.buffer_r.iter_mut().for_each(|s| *s = 0.0);
+        self.current_delay_ms = self.delay_time_ms;
+    }
+
+    fn params(&self) -> &'static [ParamDescriptor] { &PARAMS }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_delay_ms(value),
+            1 => self.set_feedback(value),
+            2 => self.set_mix(value),
+            _ => {}
+        }
     }
-}
\ No newline at end of file
+
+    fn param(&self, index: usize) -> f32 {
+        match index {
+            0 => self.delay_time_ms,
+            1 => self.feedback,
+            2 => self.mix,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_time_modulation_glides_and_does_not_accumulate() {
+        let sr = 48_000.0;
+        let mut d = Delay::new(sr);
+        let max_per_step = 0.5 * 1000.0 / sr;
+        d.modulation.time_ms = 100.0;
+        for _ in 0..10 {
+            d.process(0.0, 0.0, 1.0 / sr);
+        }
+        assert!((d.current_delay_ms - (500.0 + 10.0 * max_per_step)).abs() < 1e-3);
+        assert_eq!(d.get_delay_ms(), 500.0);
+
+        d.modulation = DelayMod::default();
+        for _ in 0..100 {
+            d.process(0.0, 0.0, 1.0 / sr);
+        }
+        assert!((d.current_delay_ms - 500.0).abs() < 1e-3);
+
+        // Pushing past the buffer is clamped instead of reading out of range
+        d.modulation.time_ms = 1e6;
+        d.current_delay_ms = 2000.0;
+        let (l, r) = d.process(1.0, 1.0, 1.0 / sr);
+        assert!(l.is_finite() && r.is_finite());
+        let max_ms = (d.max_delay_samples - 2) as f32 * 1000.0 / sr;
+        for _ in 0..10 {
+            d.process(0.0, 0.0, 1.0 / sr);
+        }
+        assert!(d.current_delay_ms <= max_ms, "{} ms past the {} ms clamp", d.current_delay_ms, max_ms);
+    }
+}
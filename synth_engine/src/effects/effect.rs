@@ -0,0 +1,47 @@
+// src/effects/effect.rs
+//! Common interface of the stereo effects, so a chain can run them in any
+//! order and a UI can list their parameters.
+
+use serde::Serialize;
+
+/// One parameter of an effect, for hosts building controls.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct ParamDescriptor {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    /// Display unit ("ms", "Hz", or "" for a plain amount).
+    pub unit: &'static str,
+}
+
+impl ParamDescriptor {
+    pub const fn new(name: &'static str, min: f32, max: f32, default: f32, unit: &'static str) -> Self {
+        Self { name, min, max, default, unit }
+    }
+}
+
+pub trait Effect: Send {
+    fn name(&self) -> &'static str;
+
+    /// Process one stereo frame.
+    fn process(&mut self, l: f32, r: f32, dt: f32) -> (f32, f32);
+
+    /// Clear buffers and tails (transport stop, patch change).
+    fn reset(&mut self);
+
+    /// Delay the effect adds to the dry signal, in samples.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Parameters in index order for `set_param` / `param`.
+    fn params(&self) -> &'static [ParamDescriptor];
+
+    /// Set parameter `index` (in the units of its descriptor); unknown
+    /// indices are ignored.
+    fn set_param(&mut self, index: usize, value: f32);
+
+    /// Current value of parameter `index` (0 if unknown).
+    fn param(&self, index: usize) -> f32;
+}
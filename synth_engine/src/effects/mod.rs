@@ -3,32 +3,151 @@
 pub mod delay;
 pub mod chorus;
 pub mod reverb;
+pub mod effect;
+pub mod chain;
 
-use delay::Delay;
-use reverb::Reverb;
-use chorus::Chorus;
+use delay::{Delay, DelayMod};
+use reverb::{Reverb, ReverbMod};
+use chorus::{Chorus, ChorusMod};
+use chain::{EffectChain, EffectSlot, MAX_INSERTS};
+use effect::Effect;
+use crate::mod_matrix::EffectParam;
 
 pub struct Effects {
     pub delay: Delay,
     pub reverb: Reverb,
     pub chorus: Chorus,
+    /// Order and on/off switches of the insert chain run by `process`.
+    pub chain: EffectChain,
+    inserts: Vec<Box<dyn Effect>>, // capacity MAX_INSERTS, never grows
 }
 
 impl Effects {
     pub fn new(sample_rate: f32) -> Self {
+        let mut effects = Self::new_dry(sample_rate);
+        effects.allocate();
+        effects
+    }
+
+    /// Effects with their settings but without delay lines (about 900 KB at
+    /// 48 kHz, mostly the delay), for synths that only ever render dry.
+    /// `allocate` sizes them before anything runs through.
+    pub fn new_dry(sample_rate: f32) -> Self {
         Self {
-            delay: Delay::new(sample_rate),
-            reverb: Reverb::new(sample_rate),
-            chorus: Chorus::new(sample_rate),
+            delay: Delay::unallocated(sample_rate),
+            reverb: Reverb::unallocated(sample_rate),
+            chorus: Chorus::unallocated(sample_rate),
+            chain: EffectChain::default(),
+            inserts: Vec::with_capacity(MAX_INSERTS),
+        }
+    }
+
+    /// Size the built-in effects' delay lines (a no-op once they are).
+    pub fn allocate(&mut self) {
+        self.chorus.allocate();
+        self.delay.allocate();
+        self.reverb.allocate();
+    }
+
+    /// Free the built-in effects' delay lines, keeping their settings.
+    pub fn release_buffers(&mut self) {
+        self.chorus.release_buffers();
+        self.delay.release_buffers();
+        self.reverb.release_buffers();
+    }
+
+    pub fn is_allocated(&self) -> bool {
+        self.chorus.is_allocated() && self.delay.is_allocated() && self.reverb.is_allocated()
+    }
+
+    /// Hand over another effect to run in the chain, returning its slot
+    /// (`None` when `MAX_INSERTS` are already added). It joins the pool
+    /// only; place it with `chain.insert`. Added effects stay for the
+    /// life of the `Effects` — take them out of the chain to drop them
+    /// from the signal path.
+    pub fn add_insert(&mut self, effect: Box<dyn Effect>) -> Option<EffectSlot> {
+        if self.inserts.len() == MAX_INSERTS {
+            return None;
+        }
+        self.inserts.push(effect);
+        Some(EffectSlot::Insert(self.inserts.len() - 1))
+    }
+
+    pub fn effect(&self, slot: EffectSlot) -> Option<&dyn Effect> {
+        match slot {
+            EffectSlot::Chorus => Some(&self.chorus),
+            EffectSlot::Delay => Some(&self.delay),
+            EffectSlot::Reverb => Some(&self.reverb),
+            EffectSlot::Insert(n) => self.inserts.get(n).map(|e| e.as_ref()),
+        }
+    }
+
+    pub fn effect_mut(&mut self, slot: EffectSlot) -> Option<&mut (dyn Effect + 'static)> {
+        match slot {
+            EffectSlot::Chorus => Some(&mut self.chorus),
+            EffectSlot::Delay => Some(&mut self.delay),
+            EffectSlot::Reverb => Some(&mut self.reverb),
+            EffectSlot::Insert(n) => self.inserts.get_mut(n).map(|e| e.as_mut()),
+        }
+    }
+
+    /// Total latency of the switched-on effects in the chain, in samples.
+    pub fn latency(&self) -> usize {
+        self.chain.slots().iter()
+            .filter(|&&(_, on)| on)
+            .filter_map(|&(slot, _)| self.effect(slot))
+            .map(|e| e.latency())
+            .sum()
+    }
+
+    /// Clear every effect's buffers and tails.
+    pub fn reset(&mut self) {
+        Effect::reset(&mut self.chorus);
+        Effect::reset(&mut self.delay);
+        Effect::reset(&mut self.reverb);
+        for insert in &mut self.inserts {
+            insert.reset();
+        }
+    }
+
+    /// Add a modulation offset to one effect parameter (in its own units).
+    pub fn apply_mod(&mut self, param: EffectParam, value: f32) {
+        match param {
+            EffectParam::ChorusDepth   => self.chorus.modulation.depth   += value,
+            EffectParam::ChorusSpeed   => self.chorus.modulation.speed   += value,
+            EffectParam::DelayTime     => self.delay.modulation.time_ms  += value,
+            EffectParam::DelayFeedback => self.delay.modulation.feedback += value,
+            EffectParam::DelayMix      => self.delay.modulation.mix      += value,
+            EffectParam::ReverbDecay   => self.reverb.modulation.decay   += value,
+            EffectParam::ReverbMix     => self.reverb.modulation.mix     += value,
+        }
+    }
+
+    /// Drop all modulation offsets (start of every control block).
+    pub fn clear_modulation(&mut self) {
+        self.chorus.modulation = ChorusMod::default();
+        self.delay.modulation = DelayMod::default();
+        self.reverb.modulation = ReverbMod::default();
+    }
+
+    /// Run one stereo frame through the chain, in order, skipping the
+    /// switched-off effects.
+    pub fn process(&mut self, mut l: f32, mut r: f32, dt: f32) -> (f32, f32) {
+        let chain = self.chain;
+        for &(slot, on) in chain.slots() {
+            if let (true, Some(effect)) = (on, self.effect_mut(slot)) {
+                (l, r) = effect.process(l, r, dt);
+            }
         }
+        (l, r)
     }
 
-    pub fn process(&mut self, l: f32, r: f32, dt: f32) -> (f32, f32) {
-        // 1. Chorus first (time-modulation before time-based effects)
-        let (l, r) = self.chorus.process(l, r, dt);
-        // 2. Delay
-        let (l, r) = self.delay.process(l, r, dt);
-        // 3. Reverb last (longest tail, should be at end of chain)
-        self.reverb.process(l, r, dt)
+    /// Effects as send buses: each effect gets its own input and only the
+    /// wet returns come back, summed (the mixes set the return levels).
+    pub fn process_sends(&mut self, chorus: (f32, f32), delay: (f32, f32), reverb: (f32, f32), dt: f32) -> (f32, f32) {
+        let (cl, cr) = self.chorus.process_wet(chorus.0, chorus.1, dt);
+        let (dl, dr) = self.delay.process_wet(delay.0, delay.1, dt);
+        let (rl, rr) = self.reverb.process_wet(reverb.0, reverb.1, dt);
+        (cl + dl + rl, cr + dr + rr)
     }
 }
@@ -1,6 +1,23 @@
+use crate::smoother::Smoother;
+use super::effect::{Effect, ParamDescriptor};
+
+const PARAMS: [ParamDescriptor; 3] = [
+    ParamDescriptor::new("decay",   0.0, 0.99, 0.75, ""),
+    ParamDescriptor::new("damping", 0.0, 0.9,  0.5,  ""),
+    ParamDescriptor::new("mix",     0.0, 1.0,  0.0,  ""),
+];
+
+/// Modulation offsets added to the user settings, reset every control block.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ReverbMod {
+    pub decay: f32,
+    pub mix: f32,
+}
+
 /// A Schroeder-style reverb with stereo spread.
 /// Uses two sets of parallel comb filters (L/R with offset delays) into series all-pass filters.
 pub struct Reverb {
+    sample_rate: f32,
     // Left comb filters
     comb_buffers_l: Vec<Vec<f32>>,
     comb_pos_l: Vec<usize>,
@@ -16,51 +33,77 @@ pub struct Reverb {
     ap_feedback: f32,
     // wet/dry mix
     mix: f32,
+    // LFO / mod-matrix offsets, smoothed per sample
+    pub modulation: ReverbMod,
+    decay_smoother: Smoother,
+    mix_smoother: Smoother,
+}
+
+/// Comb delays (ms), left and right — primes for minimal resonance, offset for stereo.
+const COMB_MS: [[f32; 4]; 2] = [[50.0, 56.0, 61.0, 68.0], [52.0, 58.0, 63.0, 70.0]];
+/// All-pass delays (ms).
+const AP_MS: [f32; 2] = [6.0, 8.0];
+
+/// Zeroed delay lines of `ms` lengths, with their read positions.
+fn delay_lines(sample_rate: f32, ms: &[f32]) -> (Vec<Vec<f32>>, Vec<usize>) {
+    let buffers = ms.iter()
+        .map(|&ms| vec![0.0; ((sample_rate * ms / 1000.0) as usize).max(1)])
+        .collect();
+    (buffers, vec![0; ms.len()])
 }
 
 impl Reverb {
     pub fn new(sample_rate: f32) -> Self {
-        // Comb delays (ms) — primes for minimal resonance
-        let comb_ms_l = [50.0, 56.0, 61.0, 68.0];
-        let comb_ms_r = [52.0, 58.0, 63.0, 70.0]; // offset for stereo
-
-        let make_combs = |ms_arr: &[f32]| -> (Vec<Vec<f32>>, Vec<usize>) {
-            let delays: Vec<usize> = ms_arr.iter()
-                .map(|&ms| (sample_rate * ms / 1000.0) as usize)
-                .collect();
-            let buffers = delays.iter().map(|&d| vec![0.0; d.max(1)]).collect();
-            let pos = vec![0; delays.len()];
-            (buffers, pos)
-        };
-
-        let (comb_buffers_l, comb_pos_l) = make_combs(&comb_ms_l);
-        let (comb_buffers_r, comb_pos_r) = make_combs(&comb_ms_r);
-
-        // All-pass delays
-        let ap_ms = [6.0, 8.0];
-        let make_aps = |ms_arr: &[f32]| -> (Vec<Vec<f32>>, Vec<usize>) {
-            let delays: Vec<usize> = ms_arr.iter()
-                .map(|&ms| (sample_rate * ms / 1000.0) as usize)
-                .collect();
-            let buffers = delays.iter().map(|&d| vec![0.0; d.max(1)]).collect();
-            let pos = vec![0; delays.len()];
-            (buffers, pos)
-        };
-
-        let (ap_buffers_l, ap_pos_l) = make_aps(&ap_ms);
-        let (ap_buffers_r, ap_pos_r) = make_aps(&ap_ms);
+        let mut reverb = Self::unallocated(sample_rate);
+        reverb.allocate();
+        reverb
+    }
 
+    /// Settings only, no delay lines yet: `allocate` before processing.
+    pub fn unallocated(sample_rate: f32) -> Self {
         Self {
-            comb_buffers_l, comb_pos_l,
-            comb_buffers_r, comb_pos_r,
+            sample_rate,
+            comb_buffers_l: Vec::new(), comb_pos_l: Vec::new(),
+            comb_buffers_r: Vec::new(), comb_pos_r: Vec::new(),
             comb_feedback: 0.75,
-            ap_buffers_l, ap_pos_l,
-            ap_buffers_r, ap_pos_r,
+            ap_buffers_l: Vec::new(), ap_pos_l: Vec::new(),
+            ap_buffers_r: Vec::new(), ap_pos_r: Vec::new(),
             ap_feedback: 0.5,
             mix: 0.0,
+            modulation: ReverbMod::default(),
+            decay_smoother: Smoother::new(0.75, 0.005),
+            mix_smoother: Smoother::new(0.0, 0.005),
         }
     }
 
+    /// Size the delay lines (a no-op once they are).
+    pub fn allocate(&mut self) {
+        if self.is_allocated() {
+            return;
+        }
+        (self.comb_buffers_l, self.comb_pos_l) = delay_lines(self.sample_rate, &COMB_MS[0]);
+        (self.comb_buffers_r, self.comb_pos_r) = delay_lines(self.sample_rate, &COMB_MS[1]);
+        (self.ap_buffers_l, self.ap_pos_l) = delay_lines(self.sample_rate, &AP_MS);
+        (self.ap_buffers_r, self.ap_pos_r) = delay_lines(self.sample_rate, &AP_MS);
+    }
+
+    /// Free the delay lines, keeping the settings.
+    pub fn release_buffers(&mut self) {
+        for (buffers, pos) in [
+            (&mut self.comb_buffers_l, &mut self.comb_pos_l),
+            (&mut self.comb_buffers_r, &mut self.comb_pos_r),
+            (&mut self.ap_buffers_l, &mut self.ap_pos_l),
+            (&mut self.ap_buffers_r, &mut self.ap_pos_r),
+        ] {
+            *buffers = Vec::new();
+            *pos = Vec::new();
+        }
+    }
+
+    pub fn is_allocated(&self) -> bool {
+        !self.comb_buffers_l.is_empty()
+    }
+
     pub fn set_decay(&mut self, decay: f32) {
         self.comb_feedback = decay.clamp(0.0, 0.99);
     }
@@ -116,20 +159,73 @@ impl Reverb {
     }
 
     /// Process one stereo frame
-    pub fn process(&mut self, input_l: f32, input_r: f32, _dt: f32) -> (f32, f32) {
+    pub fn process(&mut self, input_l: f32, input_r: f32, dt: f32) -> (f32, f32) {
+        let (wet_l, wet_r, mix) = self.run(input_l, input_r, dt);
+        let dry_l = input_l * (1.0 - mix);
+        let dry_r = input_r * (1.0 - mix);
+        (dry_l + wet_l, dry_r + wet_r)
+    }
+
+    /// Process one stereo frame and return only the reverb, scaled by the
+    /// mix (an effect-send return).
+    pub fn process_wet(&mut self, input_l: f32, input_r: f32, dt: f32) -> (f32, f32) {
+        let (wet_l, wet_r, _) = self.run(input_l, input_r, dt);
+        (wet_l, wet_r)
+    }
+
+    /// Wet output scaled by the mix, and the mix used.
+    fn run(&mut self, input_l: f32, input_r: f32, dt: f32) -> (f32, f32, f32) {
+        let decay = self.decay_smoother.next((self.comb_feedback + self.modulation.decay).clamp(0.0, 0.99), dt);
+        let mix = self.mix_smoother.next((self.mix + self.modulation.mix).clamp(0.0, 1.0), dt);
         let wet_l = Self::process_channel(
             input_l,
-            &mut self.comb_buffers_l, &mut self.comb_pos_l, self.comb_feedback,
+            &mut self.comb_buffers_l, &mut self.comb_pos_l, decay,
             &mut self.ap_buffers_l, &mut self.ap_pos_l, self.ap_feedback,
         );
         let wet_r = Self::process_channel(
             input_r,
-            &mut self.comb_buffers_r, &mut self.comb_pos_r, self.comb_feedback,
+            &mut self.comb_buffers_r, &mut self.comb_pos_r, decay,
             &mut self.ap_buffers_r, &mut self.ap_pos_r, self.ap_feedback,
         );
 
-        let dry_l = input_l * (1.0 - self.mix);
-        let dry_r = input_r * (1.0 - self.mix);
-        (dry_l + wet_l * self.mix, dry_r + wet_r * self.mix)
+        (wet_l * mix, wet_r * mix, mix)
+    }
+}
+
+impl Effect for Reverb {
+    fn name(&self) -> &'static str { "reverb" }
+
+    fn process(&mut self, l: f32, r: f32, dt: f32) -> (f32, f32) {
+        Reverb::process(self, l, r, dt)
+    }
+
+    fn reset(&mut self) {
+        let buffers = self.comb_buffers_l.iter_mut()
+            .chain(&mut self.comb_buffers_r)
+            .chain(&mut self.ap_buffers_l)
+            .chain(&mut self.ap_buffers_r);
+        for buf in buffers {
+            buf.fill(0.0);
+        }
+    }
+
+    fn params(&self) -> &'static [ParamDescriptor] { &PARAMS }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_decay(value),
+            1 => self.set_damping(value),
+            2 => self.set_mix(value),
+            _ => {}
+        }
+    }
+
+    fn param(&self, index: usize) -> f32 {
+        match index {
+            0 => self.comb_feedback,
+            1 => self.ap_feedback,
+            2 => self.mix,
+            _ => 0.0,
+        }
     }
 }
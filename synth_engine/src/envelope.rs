@@ -9,6 +9,231 @@ pub enum EnvelopeState {
     Release,
 }
 
+/// Shape of one envelope stage, from -1.0 (logarithmic) through 0.0 (linear)
+/// to 1.0 (exponential). Exponential attacks start slowly; exponential decays
+/// and releases drop fast and tail off, which is what makes FM plucks pluck.
+///
+/// A stage moves a linear position `u` at the stage's rate and outputs
+/// `u^k` of its span, so stage times do not depend on the curve.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Curve {
+    amount:   f32,
+    exponent: f32,
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self { amount: 0.0, exponent: 1.0 }
+    }
+}
+
+impl Curve {
+    pub fn new(amount: f32) -> Self {
+        let amount = amount.clamp(-1.0, 1.0);
+        Self { amount, exponent: 4.0f32.powf(amount) }
+    }
+
+    pub fn amount(&self) -> f32 {
+        self.amount
+    }
+
+    pub fn is_linear(&self) -> bool {
+        self.exponent == 1.0
+    }
+}
+
+/// Attack, decay and release curves, built from the user amounts plus a
+/// modulation offset shared by all three stages.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct StageCurves {
+    pub attack:  Curve,
+    pub decay:   Curve,
+    pub release: Curve,
+    amounts: [f32; 3],
+    offset:  f32,
+}
+
+impl StageCurves {
+    /// User amounts, each -1.0 (log) .. 0.0 (linear) .. 1.0 (exp).
+    pub fn set(&mut self, attack: f32, decay: f32, release: f32) {
+        self.amounts = [attack, decay, release].map(|a| a.clamp(-1.0, 1.0));
+        self.rebuild();
+    }
+
+    /// Offset added to every stage's amount. Returns whether the curves
+    /// changed, in which case a running stage must re-derive its position.
+    pub fn set_offset(&mut self, offset: f32) -> bool {
+        if offset == self.offset {
+            return false;
+        }
+        self.offset = offset;
+        let before = (self.attack, self.decay, self.release);
+        self.rebuild();
+        before != (self.attack, self.decay, self.release)
+    }
+
+    /// User amounts as set, without the offset.
+    pub fn amounts(&self) -> [f32; 3] {
+        self.amounts
+    }
+
+    fn rebuild(&mut self) {
+        let [a, d, r] = self.amounts;
+        self.attack  = Curve::new(a + self.offset);
+        self.decay   = Curve::new(d + self.offset);
+        self.release = Curve::new(r + self.offset);
+    }
+}
+
+/// Linear position through a curved stage. Tracking `u` directly (rather than
+/// re-deriving it from the level each sample) keeps flat curve tails moving
+/// where f32 level steps would round to nothing.
+#[derive(Copy, Clone, Debug)]
+pub struct StageRamp {
+    u: f32,
+    level: f32, // level last returned; NaN = derive `u` from the level
+}
+
+impl Default for StageRamp {
+    fn default() -> Self {
+        Self { u: 0.0, level: f32::NAN }
+    }
+}
+
+impl StageRamp {
+    /// Move `level` along `curve` between `bottom` and `top` by `du` of linear
+    /// stage position (a fraction of the span) and return the new level. If
+    /// the level was changed from outside since the last call, the position is
+    /// re-derived from it.
+    #[inline]
+    pub fn advance(&mut self, curve: &Curve, level: f32, bottom: f32, top: f32, du: f32) -> f32 {
+        let span = top - bottom;
+        if curve.is_linear() {
+            return level + du * span;
+        }
+        let u = if level == self.level {
+            self.u
+        } else {
+            ((level - bottom) / span).max(0.0).powf(1.0 / curve.exponent)
+        };
+        self.u = (u + du).max(0.0);
+        self.level = bottom + self.u.powf(curve.exponent) * span;
+        self.level
+    }
+}
+
+/// What `note_on` does to an envelope that is already running.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum RetriggerMode {
+    /// Restart the attack from zero (hard, can click).
+    #[default]
+    Reset,
+    /// Restart the attack from the current level.
+    FromCurrent,
+    /// Keep going while a note is held; only retrigger (from the current
+    /// level) once released or idle.
+    Legato,
+}
+
+impl RetriggerMode {
+    /// 0 reset, 1 from current level, 2 legato.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => RetriggerMode::FromCurrent,
+            2 => RetriggerMode::Legato,
+            _ => RetriggerMode::Reset,
+        }
+    }
+}
+
+/// Envelope stages a loop can run between.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum LoopStage {
+    Attack,
+    Decay,
+    Release,
+}
+
+impl LoopStage {
+    /// 0 attack, 1 decay, 2 release.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            0 => LoopStage::Attack,
+            1 => LoopStage::Decay,
+            _ => LoopStage::Release,
+        }
+    }
+}
+
+/// Envelope looping, which turns an envelope into a note-synced modulator.
+///
+/// A loop restarts its first stage from the current level. A loop that
+/// starts at Decay climbs back to full level through the attack stage, so it
+/// never jumps. Looping through Release while the key is held skips the
+/// sustain hold. A loop needs two stages or more: on its own an attack ends
+/// at full level and a release at zero, so single-stage loops play as `Off`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum LoopMode {
+    #[default]
+    Off,
+    /// Attack → decay → attack … while the key is held, pulsing between
+    /// sustain and full level.
+    AttackDecay,
+    /// Loop from the first stage to the last (inclusive) while the key is held.
+    Stages(LoopStage, LoopStage),
+    /// Attack → decay → release → attack … from note-on, ignoring note-off.
+    Cycle,
+}
+
+impl LoopMode {
+    /// 0 off, 1 attack–decay, 2 stages `first`..=`last` (0 attack, 1 decay,
+    /// 2 release), 3 cycle.
+    pub fn from_ids(mode: u32, first: u32, last: u32) -> Self {
+        match mode {
+            1 => LoopMode::AttackDecay,
+            2 => LoopMode::Stages(LoopStage::from_id(first), LoopStage::from_id(last)).normalized(),
+            3 => LoopMode::Cycle,
+            _ => LoopMode::Off,
+        }
+    }
+
+    /// Put `Stages` in order and turn single-stage loops off.
+    pub fn normalized(self) -> Self {
+        match self {
+            LoopMode::Stages(a, b) if a == b => LoopMode::Off,
+            LoopMode::Stages(a, b) if a > b => LoopMode::Stages(b, a),
+            mode => mode,
+        }
+    }
+
+    fn range(self) -> Option<(LoopStage, LoopStage)> {
+        match self {
+            LoopMode::Off            => None,
+            LoopMode::AttackDecay    => Some((LoopStage::Attack, LoopStage::Decay)),
+            LoopMode::Stages(a, b)   => Some((a, b)),
+            LoopMode::Cycle          => Some((LoopStage::Attack, LoopStage::Release)),
+        }
+    }
+
+    /// Stage to enter once `finished` completes, if the loop takes over from
+    /// the normal sequence. Only asked while looping is live (key held, or `Cycle`).
+    pub fn after(self, finished: LoopStage) -> Option<LoopStage> {
+        let (first, last) = self.range()?;
+        if finished == last {
+            Some(first)
+        } else if finished == LoopStage::Decay && last == LoopStage::Release {
+            Some(LoopStage::Release)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the loop keeps running after note-off.
+    pub fn ignores_note_off(self) -> bool {
+        self == LoopMode::Cycle
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Envelope {
     /// Attack time in seconds (0 ⇒ instantaneous)
@@ -21,17 +246,21 @@ pub struct Envelope {
     pub release: f32,
     level:      f32,           // current output level
     state:      EnvelopeState, // current ADSR phase
+    curves:        StageCurves, // release shapes the fall from full level, entered wherever the level is
+    ramp:          StageRamp,   // position in the current stage's curve
+    retrigger:     RetriggerMode,
+    loop_mode:     LoopMode,
+    gate:          bool,       // key held since the last note-on
 }
 
 impl Envelope {
     /// The maximum time (in seconds) that a non-infinite knob (1..=126) can map to.
     pub const MAX_TIME: f32 = 10.0;
 
-    /// Build an Envelope from Digitone-style 0–127 knobs:
+    /// Map a Digitone-style 0–127 knob to seconds:
     /// - 0 ⇒ 0.0s (instant)
     /// - 1–126 ⇒ linear 0..MAX_TIME
     /// - 127 ⇒ ∞ (hold forever)
-    /// Map a Digitone-style 0–127 knob to seconds.
     pub fn map_time(v: u8) -> f32 {
         match v {
             0           => 0.0,
@@ -53,23 +282,87 @@ impl Envelope {
             release: Self::map_time(rel),
             level:   0.0,
             state:   EnvelopeState::Idle,
+            curves:        StageCurves::default(),
+            ramp:          StageRamp::default(),
+            retrigger:     RetriggerMode::default(),
+            loop_mode:     LoopMode::default(),
+            gate:          false,
         }
     }
 
+    /// Stage shapes, each -1.0 (log) .. 0.0 (linear) .. 1.0 (exp).
+    pub fn set_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        self.curves.set(attack, decay, release);
+        self.ramp = StageRamp::default();
+    }
+
+    /// Modulation added to all three curve amounts (`AmpEnvCurve`,
+    /// `FilterEnvCurve`); the getters keep returning the user amounts.
+    pub fn set_curve_offset(&mut self, offset: f32) {
+        if self.curves.set_offset(offset) {
+            self.ramp = StageRamp::default();
+        }
+    }
+
+    pub fn attack_curve(&self)  -> f32 { self.curves.amounts()[0] }
+    pub fn decay_curve(&self)   -> f32 { self.curves.amounts()[1] }
+    pub fn release_curve(&self) -> f32 { self.curves.amounts()[2] }
+
+    pub fn set_retrigger(&mut self, mode: RetriggerMode) { self.retrigger = mode; }
+    pub fn retrigger(&self) -> RetriggerMode { self.retrigger }
+
+    pub fn set_loop_mode(&mut self, mode: LoopMode) { self.loop_mode = mode.normalized(); }
+    pub fn loop_mode(&self) -> LoopMode { self.loop_mode }
+
+    /// Where the loop goes after `finished`, if it is live.
+    fn loop_next(&self, finished: LoopStage) -> Option<LoopStage> {
+        if self.gate || self.loop_mode.ignores_note_off() {
+            self.loop_mode.after(finished)
+        } else {
+            None
+        }
+    }
+
+    fn enter(&mut self, stage: LoopStage) {
+        self.ramp = StageRamp::default();
+        self.state = match stage {
+            LoopStage::Attack  => EnvelopeState::Attack,
+            LoopStage::Decay if self.level >= 1.0 => EnvelopeState::Decay,
+            // Climb back to full level rather than jumping to it
+            LoopStage::Decay   => EnvelopeState::Attack,
+            LoopStage::Release => EnvelopeState::Release,
+        };
+    }
+
     /// Start the envelope. If attack is zero, jump immediately to peak.
+    /// A running envelope restarts according to its `RetriggerMode`.
     pub fn note_on(&mut self) {
+        let held = matches!(self.state,
+            EnvelopeState::Attack | EnvelopeState::Decay | EnvelopeState::Sustain);
+        self.gate = true;
+        if held && self.retrigger == RetriggerMode::Legato {
+            return;
+        }
+        self.ramp = StageRamp::default();
         if self.attack == 0.0 {
             // zero-length attack → instant click
             self.level = 1.0;
             self.state = EnvelopeState::Decay;
         } else {
             // normal attack ramp
-            self.level = 0.0;
+            if self.retrigger == RetriggerMode::Reset {
+                self.level = 0.0;
+            }
             self.state = EnvelopeState::Attack;
         }
     }
-    /// Begin release phase
+    /// Begin release phase (a `Cycle` loop keeps running)
     pub fn note_off(&mut self) {
+        self.gate = false;
+        if self.loop_mode.ignores_note_off() && self.state != EnvelopeState::Idle {
+            return;
+        }
+        self.ramp = StageRamp::default();
         self.state = EnvelopeState::Release;
     }
 
@@ -83,19 +376,29 @@ impl Envelope {
             EnvelopeState::Attack => {
                 // attack > 0 here
                 let atk = self.attack.max(1e-6);
-                self.level += dt / atk;
+                self.level = self.ramp.advance(&self.curves.attack, self.level, 0.0, 1.0, dt / atk);
                 if self.level >= 1.0 {
                     self.level = 1.0;
-                    self.state = EnvelopeState::Decay;
+                    let next = self.loop_next(LoopStage::Attack).unwrap_or(LoopStage::Decay);
+                    self.enter(next);
                 }
             }
 
             EnvelopeState::Decay => {
+                // Curve spans full level → sustain
                 let dec = self.decay.max(1e-6);
-                let next = self.level - dt / dec;
+                let span = 1.0 - self.sustain;
+                let next = if span > 1e-6 {
+                    self.ramp.advance(&self.curves.decay, self.level, self.sustain, 1.0, -dt / (dec * span))
+                } else {
+                    self.sustain
+                };
                 if next <= self.sustain {
                     self.level = self.sustain;
-                    self.state = EnvelopeState::Sustain;
+                    match self.loop_next(LoopStage::Decay) {
+                        Some(stage) => self.enter(stage),
+                        None => self.state = EnvelopeState::Sustain,
+                    }
                 } else {
                     self.level = next;
                 }
@@ -107,10 +410,13 @@ impl Envelope {
 
             EnvelopeState::Release => {
                 let rel = self.release.max(1e-6);
-                self.level -= dt / rel;
+                self.level = self.ramp.advance(&self.curves.release, self.level, 0.0, 1.0, -dt / rel);
                 if self.level <= 0.0 {
                     self.level = 0.0;
-                    self.state = EnvelopeState::Idle;
+                    match self.loop_next(LoopStage::Release) {
+                        Some(stage) => self.enter(stage),
+                        None => self.state = EnvelopeState::Idle,
+                    }
                 }
             }
         }
@@ -118,6 +424,14 @@ impl Envelope {
         self.level
     }
 
+    /// Drop straight to silence (idle), as after a finished release.
+    pub fn reset(&mut self) {
+        self.level = 0.0;
+        self.ramp = StageRamp::default();
+        self.state = EnvelopeState::Idle;
+        self.gate = false;
+    }
+
     /// Returns true when the envelope has finished releasing and is silent.
     pub fn is_idle(&self) -> bool {
         self.state == EnvelopeState::Idle
@@ -212,6 +526,161 @@ mod tests {
     }
     
 
+    #[test]
+    fn curved_stage_times_match_from_digitone() {
+        for &curve in &[-1.0f32, -0.5, 0.5, 1.0] {
+            let mut env = Envelope::from_digitone(40, 40, 64, 40);
+            env.set_curves(curve, curve, curve);
+            let (atk, sus) = (env.attack, env.sustain);
+            let dec = (1.0 - sus) * env.decay;
+            let rel = env.release; // from full level
+
+            // Exponential tails sit close to their target, so time each
+            // stage until the envelope moves on rather than by level.
+            let time_in = |env: &mut Envelope, state: EnvelopeState| {
+                let mut t = 0.0;
+                while env.state == state && t < 30.0 {
+                    env.process(DT);
+                    t += DT;
+                }
+                t
+            };
+            env.note_on();
+            let measured = time_in(&mut env, EnvelopeState::Attack);
+            assert!((measured - atk).abs() < atk * 0.05, "curve {} attack {:.3}s vs {:.3}s", curve, measured, atk);
+            let measured = time_in(&mut env, EnvelopeState::Decay);
+            assert!((measured - dec).abs() < dec * 0.05, "curve {} decay {:.3}s vs {:.3}s", curve, measured, dec);
+
+            env.level = 1.0;
+            env.note_off();
+            let measured = time_in(&mut env, EnvelopeState::Release);
+            assert!((measured - rel).abs() < rel * 0.05, "curve {} release {:.3}s vs {:.3}s", curve, measured, rel);
+        }
+    }
+
+    #[test]
+    fn curve_offset_reshapes_a_running_stage_without_a_jump() {
+        let mut env = Envelope::from_digitone(0, 63, 0, 10);
+        env.set_curves(0.0, 1.0, 0.0);
+        env.note_on();
+        let mut level = 0.0;
+        for _ in 0..1000 {
+            level = env.process(1e-3);
+        }
+        env.set_curve_offset(-1.0);
+        let next = env.process(1e-3);
+        assert!(next < level && level - next < 0.01, "{} → {}", level, next);
+        assert_eq!(env.decay_curve(), 1.0, "getters report the user amount");
+    }
+
+    #[test]
+    fn curve_shapes_the_stage() {
+        let halfway = |curve: f32| {
+            let mut env = Envelope::from_digitone(63, 0, 127, 0);
+            env.set_curves(curve, 0.0, 0.0);
+            env.note_on();
+            for _ in 0..(env.attack / 2.0 / DT) as usize {
+                env.process(DT);
+            }
+            env.get_level()
+        };
+        assert!((halfway(0.0) - 0.5).abs() < 0.01);
+        assert!(halfway(1.0) < 0.1, "exponential attack starts slowly");
+        assert!(halfway(-1.0) > 0.8, "logarithmic attack rises fast");
+    }
+
+    #[test]
+    fn retrigger_modes() {
+        let mut env = Envelope::from_digitone(63, 63, 64, 63);
+        let mut halfway_release = |mode| {
+            env.set_retrigger(mode);
+            env.note_on();
+            time_to_cross(&mut env, 0.6, 10.0);
+            env.note_off();
+            time_to_cross(&mut env, 0.3, 10.0);
+            env.note_on();
+            env.process(DT)
+        };
+        assert!(halfway_release(RetriggerMode::Reset) < 0.01, "reset restarts from zero");
+        assert!(halfway_release(RetriggerMode::FromCurrent) > 0.29, "no drop to zero");
+        assert!(halfway_release(RetriggerMode::Legato) > 0.29, "released: retrigger from current");
+
+        // Legato while held: the decay carries on
+        let mut env = Envelope::from_digitone(10, 63, 64, 63);
+        env.set_retrigger(RetriggerMode::Legato);
+        env.note_on();
+        time_to_cross(&mut env, 0.9, 10.0);
+        while env.state == EnvelopeState::Attack {
+            env.process(DT);
+        }
+        env.note_on();
+        assert_eq!(env.state, EnvelopeState::Decay);
+    }
+
+    #[test]
+    fn attack_decay_loop_pulses_while_held() {
+        let mut env = Envelope::from_digitone(6, 6, 64, 6);
+        env.set_loop_mode(LoopMode::AttackDecay);
+        env.note_on();
+        let (mut peaks, mut was_rising, mut prev) = (0, true, 0.0);
+        for _ in 0..(2.0 / DT) as usize {
+            let v = env.process(DT);
+            if was_rising && v < prev {
+                peaks += 1;
+            }
+            was_rising = v >= prev;
+            prev = v;
+        }
+        // 0.48 s attack then 0.24 s decay / 0.24 s re-attack per cycle
+        assert!((3..=5).contains(&peaks), "peaks {}", peaks);
+        assert!(prev >= env.sustain - 1e-6);
+
+        env.note_off();
+        time_to_cross(&mut env, 0.0, 5.0);
+        assert!(env.is_idle(), "loop stops once released");
+    }
+
+    #[test]
+    fn cycle_loop_runs_through_release_and_ignores_note_off() {
+        let mut env = Envelope::from_digitone(1, 1, 64, 1);
+        env.set_loop_mode(LoopMode::from_ids(3, 0, 0));
+        env.note_on();
+        env.note_off();
+        let mut hit_zero = false;
+        for _ in 0..(0.5 / DT) as usize {
+            hit_zero |= env.process(DT) == 0.0;
+        }
+        assert!(hit_zero && !env.is_idle());
+    }
+
+    #[test]
+    fn single_stage_loops_play_as_off() {
+        for stage in 0..3 {
+            assert_eq!(LoopMode::from_ids(2, stage, stage), LoopMode::Off);
+        }
+        assert_eq!(LoopMode::from_ids(2, 2, 1), LoopMode::Stages(LoopStage::Decay, LoopStage::Release));
+        let mut env = Envelope::from_digitone(6, 6, 64, 6);
+        env.set_loop_mode(LoopMode::Stages(LoopStage::Attack, LoopStage::Attack));
+        assert_eq!(env.loop_mode(), LoopMode::Off);
+    }
+
+    #[test]
+    fn decay_first_loop_ramps_back_to_full_level() {
+        let mut env = Envelope::from_digitone(6, 6, 64, 6);
+        env.set_loop_mode(LoopMode::from_ids(2, 1, 2));
+        env.note_on();
+        let (mut prev, mut largest_step, mut loops) = (0.0f32, 0.0f32, 0);
+        for _ in 0..(3.0 / DT) as usize {
+            let v = env.process(DT);
+            largest_step = largest_step.max(v - prev);
+            loops += (prev == 0.0 && v > 0.0) as u32;
+            prev = v;
+        }
+        assert!(loops >= 2, "looped {} times", loops);
+        // a 0.48 s attack rises ~4.3e-5 per sample
+        assert!(largest_step < 1e-4, "jumped by {}", largest_step);
+    }
+
     #[test]
     fn release_time_measured_matches_from_digitone() {
         let knob = 63u8;
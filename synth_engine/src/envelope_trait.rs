@@ -4,4 +4,17 @@ pub trait EnvelopeTrait: Send {
     fn note_off(&mut self);
     fn process(&mut self, delta_time: f32) -> f32;
     fn get_level(&self) -> f32;
+
+    /// Whether the envelope has run out after note-off and will stay silent,
+    /// so a voice whose carriers have all finished can stop. Envelopes that
+    /// leave voice lifetime to the amp envelope keep the default.
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// Take new DX rates and levels (0–99) in place, keeping the envelope's
+    /// position. Returns false for envelopes that aren't DX envelopes.
+    fn set_dx_params(&mut self, _rates: [u8; 4], _levels: [u8; 4]) -> bool {
+        false
+    }
 }
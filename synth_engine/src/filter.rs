@@ -1,5 +1,5 @@
 /// src/filter.rs — Biquad filter (Direct Form II Transposed)
-use crate::envelope::Envelope;
+use crate::envelope::{Envelope, LoopMode, RetriggerMode};
 use crate::envelope_trait::EnvelopeTrait;
 use std::f32::consts::PI;
 
@@ -92,10 +92,23 @@ impl Filter {
     pub fn set_decay(&mut self, v: f32)   { self.envelope.decay   = v; }
     pub fn set_sustain(&mut self, v: f32) { self.envelope.sustain = v; }
     pub fn set_release(&mut self, v: f32) { self.envelope.release = v; }
+    /// Envelope stage shapes, -1.0 (log) .. 1.0 (exp).
+    pub fn set_env_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        self.envelope.set_curves(attack, decay, release);
+    }
+    pub fn set_env_curve_offset(&mut self, offset: f32) { self.envelope.set_curve_offset(offset); }
+    pub fn set_env_retrigger(&mut self, mode: RetriggerMode) { self.envelope.set_retrigger(mode); }
+    pub fn set_env_loop(&mut self, mode: LoopMode) { self.envelope.set_loop_mode(mode); }
 
     pub fn note_on(&mut self)  { self.envelope.note_on(); self.coeffs_dirty = true; }
     pub fn note_off(&mut self) { self.envelope.note_off(); }
 
+    /// Clear the filter state (silence in, silence out).
+    pub fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+
     /// Process one sample — Direct Form II Transposed (stable at high Q)
     pub fn process(&mut self, input: f32, dt: f32) -> f32 {
         // Advance envelope; only recalc coefficients if something changed
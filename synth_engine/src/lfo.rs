@@ -1,37 +1,9 @@
-use js_sys::Math;
 use std::f32::consts::PI;
 
-/// All possible modulation destinations for an LFO.
-#[derive(Copy, Clone, Debug)]
-pub enum LfoDestination {
-    // FM synth (SYN1 / SYN2) parameters
-    ModDepthA,
-    ModDepthB,
-    RatioC,
-    RatioA,
-    RatioB,
-    Feedback,
-    Harm,
-    CarrierMix,
-    // Amp envelope parameters (AMP page)
-    AmpAttack,
-    AmpDecay,
-    AmpSustain,
-    AmpRelease,
-    // Amp section parameters
-    Overdrive,
-    Pan,
-    Volume,
-    // Filter envelope parameters (FLTR page)
-    FilterAttack,
-    FilterDecay,
-    FilterSustain,
-    FilterRelease,
-    // Multimode filter parameters
-    FilterCutoff,
-    FilterResonance,
-    FilterEnvAmount,
-}
+use crate::lfo_shape::{LfoCurve, LfoSteps};
+use crate::mod_matrix::ModDestination;
+use crate::rng::Rng;
+use crate::transport::{division_beats, Clock, NUM_DIVISIONS};
 
 /// Waveform shapes for the LFO.
 #[derive(Copy, Clone, Debug)]
@@ -43,38 +15,61 @@ pub enum Waveform {
     Exponential,
     Ramp,
     Random,
+    /// User breakpoint curve (`set_curve`).
+    Custom,
+    /// User step sequence (`steps_mut`).
+    Steps,
 }
 
 /// Trigger modes for the LFO.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum LfoMode {
+    /// Runs continuously, ignores notes.
     Free,
+    /// Restarts at `start_phase` on every note.
     Trigger,
+    /// Runs freely in the background; output is sampled and held at note-on.
     Hold,
+    /// Restarts on note-on, plays one cycle, then stops.
     One,
+    /// Restarts on note-on, plays half a cycle, then stops.
     Half,
 }
 
 
+#[derive(Clone)]
 pub struct Lfo {
     // Core parameters
     _sample_rate: f32,
     speed: f32,         // -64..63 bipolar speed
     multiplier: i32,    // integer multiplier
     fade: i32,          // -64..63 fade in/out
-    pub destination: LfoDestination,
+    pub destination: ModDestination,
     waveform: Waveform,
     start_phase: f32,   // 0.0..1.0 start phase offset
     mode: LfoMode,
     depth: f32,         // -1.0..1.0 modulation depth
+    speed_mod: f32,     // modulation added to speed (LfoSpeed destination)
+    depth_mod: f32,     // modulation added to depth (LfoDepth destination)
+    sync: bool,         // speed selects a tempo division instead of Hz
+    clock: Clock,       // tempo / song position for sync
 
     current:       f32,
+    shape_out:     f32, // waveform × fade, before depth (mod matrix source)
 
     // Internal state
-    phase: f32,         // continuous phase (cycles)
+    phase: f32,         // phase since trigger (cycles), start_phase added on output
     fade_env: f32,      // 0.0..1.0 fade envelope
     triggered: bool,    // note trigger state
     random_val: f32,    // current random value for Random waveform
+    rng: Rng,           // seeded source for the Random waveform
+    travelled: f32,     // cycles run since the last trigger (One/Half)
+    finished: bool,     // One/Half has reached its end point
+    held: f32,          // raw value latched at note-on (Hold)
+
+    // User shapes, part of the patch
+    curve: LfoCurve,
+    steps: LfoSteps,
 }
 
 impl Lfo {
@@ -88,39 +83,82 @@ impl Lfo {
             speed: 0.0,
             multiplier: 1,
             fade: 0,
-            destination: LfoDestination::ModDepthA,
+            destination: ModDestination::ModDepthA,
             waveform: Waveform::Triangle,
             start_phase: 0.0,
             mode: LfoMode::Free,
             depth: 0.0,
+            speed_mod: 0.0,
+            depth_mod: 0.0,
+            sync: false,
+            clock: Clock::default(),
             current: 0.0,
+            shape_out: 0.0,
 
             phase: 0.0,
             fade_env: 1.0,
             triggered: false,
             random_val: 0.0,
+            rng: Rng::new(0),
+            travelled: 0.0,
+            finished: false,
+            held: 0.0,
+
+            curve: LfoCurve::default(),
+            steps: LfoSteps::default(),
         }
     }
 
+    /// Restart the Random waveform's stream from `seed`.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng.reseed(seed);
+        self.random_val = 0.0;
+    }
+
     // —— Parameter setters ——
     pub fn set_speed(&mut self, v: f32)    { self.speed = v.clamp(-64.0, 63.0); }
     pub fn set_multiplier(&mut self, m: i32){ self.multiplier = m; }
     pub fn set_fade(&mut self, f: i32)     { self.fade = f; }
-    pub fn set_destination(&mut self, d: LfoDestination) { self.destination = d; }
+    pub fn set_destination(&mut self, d: ModDestination) { self.destination = d; }
     pub fn set_waveform(&mut self, w: Waveform)          { self.waveform = w; }
     pub fn set_start_phase(&mut self, p: f32) { self.start_phase = p.clamp(0.0, 1.0); }
     pub fn set_mode(&mut self, m: LfoMode)     { self.mode = m; }
     pub fn set_depth(&mut self, d: f32)        { self.depth = d; }
+    /// Modulation offsets on speed and depth, used from the next `process`
+    /// on; the user values and getters are left alone.
+    pub fn set_modulation(&mut self, speed: f32, depth: f32) {
+        self.speed_mod = speed;
+        self.depth_mod = depth;
+    }
+    pub fn set_curve(&mut self, c: LfoCurve)   { self.curve = c; }
+    /// Step sequence played by `Waveform::Steps`.
+    pub fn steps_mut(&mut self) -> &mut LfoSteps { &mut self.steps }
+    /// Tempo sync: |speed| picks a division (0 = 16 bars dotted … 63 = 1/64
+    /// triplet), its sign the direction, and the multiplier speeds it up.
+    pub fn set_sync(&mut self, on: bool)       { self.sync = on; }
+    /// Tempo and song position used in sync mode; set before each `process`.
+    pub fn set_clock(&mut self, clock: Clock)  { self.clock = clock; }
 
     pub fn current(&self) -> f32 {
         self.current
     }
 
-    /// Should be called on note-on to handle Trigger/Hold/One modes.
+    /// Last waveform value with fade applied but not depth, in [-1..1].
+    pub fn shape_value(&self) -> f32 {
+        self.shape_out
+    }
+
+    /// Should be called on note-on to handle Trigger/Hold/One/Half modes.
     pub fn note_on(&mut self) {
         self.triggered = true;
-        if matches!(self.mode, LfoMode::Trigger | LfoMode::One | LfoMode::Half) {
-            self.phase = self.start_phase;
+        match self.mode {
+            LfoMode::Free => {}
+            LfoMode::Hold => self.held = self.shape(self.output_phase()),
+            LfoMode::Trigger | LfoMode::One | LfoMode::Half => {
+                self.phase = 0.0;
+                self.travelled = 0.0;
+                self.finished = false;
+            }
         }
         self.fade_env = if self.fade < 0 { 0.0 } else { 1.0 };
     }
@@ -130,6 +168,11 @@ impl Lfo {
         self.triggered = false;
     }
 
+    /// Whether a note is currently holding this LFO.
+    pub fn is_triggered(&self) -> bool {
+        self.triggered
+    }
+
     /// Advance LFO by dt seconds and return current modulation value in [-1..1].
     pub fn process(&mut self, dt: f32) -> f32 {
         // 1) handle fade envelope
@@ -147,30 +190,98 @@ impl Lfo {
 
         // 2) advance phase — speed mapped to 0..MAX_FREQ Hz (with multiplier)
         // Symmetric normalization: -64..63 → -1..~1 (use 64 for both directions)
-        let speed_norm = (self.speed / 64.0).clamp(-1.0, 1.0);      // -1..1
-        let freq = speed_norm.abs() * Self::MAX_FREQ                 // 0..MAX_FREQ
-                 * (self.multiplier.max(1) as f32);                  // scaled by multiplier
-        let delta = freq * dt * speed_norm.signum();                 // allow backwards
-        self.phase += delta;
+        let speed = self.effective_speed();
+        let speed_norm = (speed / 64.0).clamp(-1.0, 1.0);           // -1..1
+        let freq = if self.sync {
+            self.clock.bpm / 60.0 / self.cycle_beats()               // cycles per second
+        } else {
+            speed_norm.abs() * Self::MAX_FREQ                        // 0..MAX_FREQ
+                * (self.multiplier.max(1) as f32)                    // scaled by multiplier
+        };
+        let mut delta = freq * dt;
+        let direction = if self.sync && speed < 0.0 { -1.0 } else { speed_norm.signum() };
+
+        // Background-running synced LFOs lock their phase to the song position
+        if self.sync && matches!(self.mode, LfoMode::Free | LfoMode::Hold) {
+            if let Some(pos) = self.clock.position {
+                let cycles = (pos / self.cycle_beats() as f64).fract() as f32;
+                let locked = if direction < 0.0 { (1.0 - cycles).fract() } else { cycles };
+                delta = (locked - self.phase) * direction;
+                if delta < 0.0 {
+                    delta += 1.0; // crossed a cycle boundary
+                }
+            }
+        }
+
+        // One/Half stop once they have covered their span since the trigger
+        if !self.finished {
+            if let Some(span) = self.one_shot_span() {
+                if self.travelled + delta >= span {
+                    delta = span - self.travelled;
+                    self.finished = true;
+                }
+                self.travelled += delta;
+            }
+        } else {
+            delta = 0.0;
+        }
+        self.phase += delta * direction;                             // allow backwards
 
         // wrap phase
         if self.phase < 0.0 || self.phase >= 1.0 {
             self.phase = (self.phase % 1.0 + 1.0) % 1.0;
             // random waveform picks new value each cycle
             if let Waveform::Random = self.waveform {
-                let r = Math::random();
-                self.random_val = (r as f32) * 2.0 - 1.0;
-            }
-            // handle One-shot modes
-            if matches!(self.mode, LfoMode::One) { self.depth = 0.0; }
-            if matches!(self.mode, LfoMode::Half) && (self.phase - self.start_phase).abs() > 0.5 {
-                self.depth = 0.0;
+                self.random_val = self.rng.next_bipolar();
             }
         }
 
         // 3) generate raw wave value in [-1..1]
-        let ph = (self.phase + self.start_phase).fract();
-        let raw = match self.waveform {
+        let raw = if self.mode == LfoMode::Hold {
+            self.held
+        } else {
+            self.shape(self.output_phase())
+        };
+
+        // 4) apply depth and fade
+        self.shape_out = raw * self.fade_env;
+        let v = self.shape_out * (self.depth + self.depth_mod);
+        self.current = v;
+        v
+    }
+
+    /// Speed with modulation applied, in the -64..63 knob range.
+    fn effective_speed(&self) -> f32 {
+        (self.speed + self.speed_mod).clamp(-64.0, 63.0)
+    }
+
+    /// Cycle length in beats for sync mode, from speed and multiplier.
+    fn cycle_beats(&self) -> f32 {
+        let idx = (self.effective_speed().abs() / 64.0 * NUM_DIVISIONS as f32) as usize;
+        division_beats(idx) / self.multiplier.max(1) as f32
+    }
+
+    /// Cycles a one-shot mode runs before stopping.
+    fn one_shot_span(&self) -> Option<f32> {
+        match self.mode {
+            LfoMode::One  => Some(1.0),
+            LfoMode::Half => Some(0.5),
+            _ => None,
+        }
+    }
+
+    /// Phase actually read from the waveform (trigger phase + start offset).
+    /// A finished One-shot sits exactly one cycle on, so it holds its start value.
+    fn output_phase(&self) -> f32 {
+        if self.finished && self.mode == LfoMode::One {
+            return self.start_phase;
+        }
+        (self.phase + self.start_phase).fract()
+    }
+
+    /// Raw waveform value in [-1..1] at phase `ph`.
+    fn shape(&self, ph: f32) -> f32 {
+        match self.waveform {
             Waveform::Triangle    => 2.0 * (2.0 * ph - 1.0).abs() - 1.0,
             Waveform::Sine        => (2.0 * PI * ph).sin(),
             Waveform::Square      => if ph < 0.5 { 1.0 } else { -1.0 },
@@ -184,20 +295,166 @@ impl Lfo {
             }
             Waveform::Ramp        => 1.0 - 2.0 * ph,
             Waveform::Random      => self.random_val,
-        };
-
-        // 4) apply depth and fade
-        let v = raw * self.depth * self.fade_env;
-        self.current = v;
-        v
+            Waveform::Custom      => self.curve.value_at(ph),
+            Waveform::Steps       => self.steps.value_at(ph),
+        }
     }
 
     pub fn speed(&self) -> f32           { self.speed }
     pub fn multiplier(&self) -> i32      { self.multiplier }
     pub fn fade(&self) -> i32            { self.fade }
-    pub fn destination(&self) -> LfoDestination { self.destination }
+    pub fn destination(&self) -> ModDestination { self.destination }
     pub fn waveform(&self) -> Waveform   { self.waveform }
     pub fn start_phase(&self) -> f32     { self.start_phase }
     pub fn mode(&self) -> LfoMode        { self.mode }
     pub fn depth(&self) -> f32           { self.depth }
+    pub fn sync(&self) -> bool           { self.sync }
+    pub fn curve(&self) -> &LfoCurve     { &self.curve }
+    pub fn steps(&self) -> &LfoSteps     { &self.steps }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 1000.0;
+
+    /// A 1 Hz LFO at full depth.
+    fn lfo(mode: LfoMode, waveform: Waveform) -> Lfo {
+        let mut l = Lfo::new(48_000.0);
+        l.set_speed(64.0 / Lfo::MAX_FREQ); // 1 Hz
+        l.set_depth(1.0);
+        l.set_mode(mode);
+        l.set_waveform(waveform);
+        l
+    }
+
+    fn run(l: &mut Lfo, seconds: f32) -> f32 {
+        let mut v = 0.0;
+        for _ in 0..(seconds / DT).round() as usize {
+            v = l.process(DT);
+        }
+        v
+    }
+
+    #[test]
+    fn trigger_restarts_at_start_phase() {
+        let mut l = lfo(LfoMode::Trigger, Waveform::Sawtooth);
+        l.set_start_phase(0.25);
+        run(&mut l, 0.6);
+        l.note_on();
+        let v = l.process(0.0);
+        assert!((v - (2.0 * 0.25 - 1.0)).abs() < 1e-4, "restart value {}", v);
+    }
+
+    #[test]
+    fn free_ignores_note_on() {
+        let mut l = lfo(LfoMode::Free, Waveform::Sawtooth);
+        let before = run(&mut l, 0.3);
+        l.note_on();
+        let after = l.process(0.0);
+        assert!((before - after).abs() < 1e-4);
+    }
+
+    #[test]
+    fn hold_latches_value_at_note_on() {
+        let mut l = lfo(LfoMode::Hold, Waveform::Sawtooth);
+        run(&mut l, 0.3); // saw at phase 0.3 = -0.4
+        l.note_on();
+        let held = run(&mut l, 0.4);
+        assert!((held + 0.4).abs() < 0.01, "held {}", held);
+        assert_eq!(run(&mut l, 0.2), held, "hold output moved");
+    }
+
+    #[test]
+    fn one_shot_stops_after_one_cycle_and_keeps_depth() {
+        let mut l = lfo(LfoMode::One, Waveform::Sine);
+        l.note_on();
+        let mid = run(&mut l, 0.25);
+        assert!((mid - 1.0).abs() < 0.01, "quarter cycle should peak, got {}", mid);
+        let end = run(&mut l, 2.0);
+        assert!(end.abs() < 0.01, "one-shot should rest at its start value, got {}", end);
+        assert_eq!(l.depth(), 1.0, "one-shot must not clobber depth");
+
+        // and runs again on the next note
+        l.note_on();
+        let again = run(&mut l, 0.25);
+        assert!((again - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn half_shot_stops_after_half_cycle() {
+        let mut l = lfo(LfoMode::Half, Waveform::Sawtooth);
+        l.note_on();
+        let end = run(&mut l, 3.0);
+        assert!(end.abs() < 0.01, "saw half-way point is 0, got {}", end);
+        assert_eq!(run(&mut l, 1.0), end);
+    }
+
+    #[test]
+    fn step_sequence_plays_once_in_one_shot_mode() {
+        let mut l = lfo(LfoMode::One, Waveform::Steps);
+        l.steps_mut().set_values(&[1.0, 0.0, -1.0, 0.5]);
+        l.note_on();
+        assert_eq!(run(&mut l, 0.1), 1.0);
+        assert_eq!(run(&mut l, 0.5), -1.0); // t = 0.6 → step 2
+        // finished: rests on the first step until the next note
+        assert_eq!(run(&mut l, 2.0), 1.0);
+    }
+
+    #[test]
+    fn custom_curve_drives_output() {
+        let mut l = lfo(LfoMode::Trigger, Waveform::Custom);
+        l.set_curve(LfoCurve::from_flat(&[0.0, 0.0, 0.0, 0.5, 1.0, 0.0]));
+        l.note_on();
+        let v = run(&mut l, 0.25);
+        assert!((v - 0.5).abs() < 0.01, "value {}", v);
+    }
+
+    /// A saw synced to quarter notes.
+    fn quarter_note_lfo() -> Lfo {
+        let idx = (0..NUM_DIVISIONS).position(|i| division_beats(i) == 1.0).unwrap();
+        let mut l = lfo(LfoMode::Free, Waveform::Sawtooth);
+        l.set_sync(true);
+        l.set_speed((idx as f32 + 0.5) * 64.0 / NUM_DIVISIONS as f32);
+        l
+    }
+
+    #[test]
+    fn speed_and_depth_modulation_stack_on_the_knobs() {
+        let mut l = lfo(LfoMode::Trigger, Waveform::Sawtooth);
+        l.set_modulation(64.0 / Lfo::MAX_FREQ, -0.5); // 2 Hz at half depth
+        l.note_on();
+        let v = run(&mut l, 0.125); // a quarter of a 2 Hz cycle
+        assert!((v - 0.5 * (2.0 * 0.25 - 1.0)).abs() < 0.01, "value {}", v);
+        assert_eq!(l.speed(), 64.0 / Lfo::MAX_FREQ);
+        assert_eq!(l.depth(), 1.0);
+    }
+
+    #[test]
+    fn sync_runs_at_tempo_when_stopped() {
+        let mut l = quarter_note_lfo();
+        l.set_clock(Clock { bpm: 120.0, position: None }); // 2 beats per second
+        let v = run(&mut l, 0.125); // quarter of a beat
+        assert!((v - (2.0 * 0.25 - 1.0)).abs() < 0.01, "value {}", v);
+    }
+
+    #[test]
+    fn sync_locks_phase_to_song_position() {
+        // One LFO runs from bar 1, the other jumps straight to beat 5.25:
+        // both must agree, so modulation stays in time across renders.
+        let mut a = quarter_note_lfo();
+        let mut pos = 0.0f64;
+        for _ in 0..2625 {
+            pos += DT as f64 * 2.0; // 120 bpm
+            a.set_clock(Clock { bpm: 120.0, position: Some(pos) });
+            a.process(DT);
+        }
+        let mut b = quarter_note_lfo();
+        b.set_clock(Clock { bpm: 120.0, position: Some(pos) });
+        let vb = b.process(DT);
+        assert!((a.current() - vb).abs() < 1e-3, "{} vs {}", a.current(), vb);
+        assert!((vb - (2.0 * 0.25 - 1.0)).abs() < 1e-3, "value {}", vb);
+    }
 }
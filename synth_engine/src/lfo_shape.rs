@@ -0,0 +1,223 @@
+// src/lfo_shape.rs
+//! User-defined LFO shapes.
+//!
+//! `LfoCurve` is a breakpoint curve: points at phases 0..1 joined by curved
+//! segments, wrapping from the last point back to the first. `LfoSteps` is a
+//! step sequence (up to 32 steps) with optional glide into the next step.
+//! Both are read by phase, so every LFO mode and speed works with them.
+
+/// Maximum number of breakpoints in a curve.
+pub const MAX_POINTS: usize = 32;
+/// Maximum number of steps in a step sequence.
+pub const MAX_STEPS: usize = 32;
+
+/// One point of a breakpoint curve.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    /// Position in the cycle, 0.0..1.0.
+    pub phase: f32,
+    /// Output at this point, -1.0..1.0.
+    pub value: f32,
+    /// Bend of the segment leaving this point, -1.0..1.0
+    /// (0 linear, > 0 moves early and eases in, < 0 starts slowly).
+    pub curve: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LfoCurve {
+    points: Vec<Breakpoint>, // sorted by phase, never empty
+}
+
+impl Default for LfoCurve {
+    /// A linear triangle: -1 → 1 → -1.
+    fn default() -> Self {
+        Self {
+            points: vec![
+                Breakpoint { phase: 0.0, value: -1.0, curve: 0.0 },
+                Breakpoint { phase: 0.5, value: 1.0, curve: 0.0 },
+            ],
+        }
+    }
+}
+
+impl LfoCurve {
+    /// Build a curve from points in any order. Values are clamped, extra
+    /// points beyond `MAX_POINTS` dropped; no points gives the default curve.
+    pub fn new(points: &[Breakpoint]) -> Self {
+        let mut points: Vec<Breakpoint> = points
+            .iter()
+            .take(MAX_POINTS)
+            .map(|p| Breakpoint {
+                phase: p.phase.clamp(0.0, 1.0),
+                value: p.value.clamp(-1.0, 1.0),
+                curve: p.curve.clamp(-1.0, 1.0),
+            })
+            .collect();
+        if points.is_empty() {
+            return Self::default();
+        }
+        points.sort_by(|a, b| a.phase.total_cmp(&b.phase));
+        Self { points }
+    }
+
+    /// Curve from flat `[phase, value, curve, phase, value, curve, …]` data
+    /// (the layout used by the JS API); a trailing partial point is ignored.
+    pub fn from_flat(data: &[f32]) -> Self {
+        let points: Vec<Breakpoint> = data
+            .chunks_exact(3)
+            .map(|c| Breakpoint { phase: c[0], value: c[1], curve: c[2] })
+            .collect();
+        Self::new(&points)
+    }
+
+    /// Inverse of `from_flat`, for saving the patch.
+    pub fn to_flat(&self) -> Vec<f32> {
+        self.points.iter().flat_map(|p| [p.phase, p.value, p.curve]).collect()
+    }
+
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points
+    }
+
+    /// Curve output at phase `ph` (0.0..1.0).
+    pub fn value_at(&self, ph: f32) -> f32 {
+        let n = self.points.len();
+        // Segment that starts at or before `ph`; before the first point we
+        // are still on the wrap-around segment from the last point.
+        let i = match self.points.iter().rposition(|p| p.phase <= ph) {
+            Some(i) => i,
+            None => n - 1,
+        };
+        let from = self.points[i];
+        let (to, to_phase) = if i + 1 < n {
+            (self.points[i + 1], self.points[i + 1].phase)
+        } else {
+            (self.points[0], self.points[0].phase + 1.0)
+        };
+        // Unwrap `ph` onto the wrap-around segment when it lies before the first point
+        let ph = if ph < from.phase { ph + 1.0 } else { ph };
+        let span = to_phase - from.phase;
+        if span <= 0.0 {
+            return from.value;
+        }
+        let t = bend((ph - from.phase) / span, from.curve);
+        from.value + (to.value - from.value) * t
+    }
+}
+
+/// Warp 0..1 progress `t` by `curve` (-1..1) with a power law.
+fn bend(t: f32, curve: f32) -> f32 {
+    if curve == 0.0 {
+        t
+    } else {
+        t.clamp(0.0, 1.0).powf(4.0f32.powf(-curve))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LfoSteps {
+    values: [f32; MAX_STEPS],
+    len: usize,
+    glide: f32, // fraction of each step spent sliding into the next
+}
+
+impl Default for LfoSteps {
+    /// 16 steps alternating full on / off, a straight 1/16 gate at one cycle per bar.
+    fn default() -> Self {
+        let mut values = [0.0; MAX_STEPS];
+        for (i, v) in values.iter_mut().enumerate() {
+            *v = if i % 2 == 0 { 1.0 } else { -1.0 };
+        }
+        Self { values, len: 16, glide: 0.0 }
+    }
+}
+
+impl LfoSteps {
+    /// Replace the sequence; its length (1..=32) is taken from `values`.
+    pub fn set_values(&mut self, values: &[f32]) {
+        if values.is_empty() {
+            return;
+        }
+        self.len = values.len().min(MAX_STEPS);
+        for (dst, &v) in self.values.iter_mut().zip(values) {
+            *dst = v.clamp(-1.0, 1.0);
+        }
+    }
+
+    pub fn set_step(&mut self, index: usize, value: f32) {
+        if index < MAX_STEPS {
+            self.values[index] = value.clamp(-1.0, 1.0);
+        }
+    }
+
+    /// Number of steps played per cycle (1..=32).
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len.clamp(1, MAX_STEPS);
+    }
+
+    /// Glide 0.0 (hard steps) .. 1.0 (slide for the whole step).
+    pub fn set_glide(&mut self, glide: f32) {
+        self.glide = glide.clamp(0.0, 1.0);
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values[..self.len]
+    }
+
+    pub fn glide(&self) -> f32 {
+        self.glide
+    }
+
+    /// Sequence output at phase `ph` (0.0..1.0).
+    pub fn value_at(&self, ph: f32) -> f32 {
+        let pos = ph.clamp(0.0, 1.0) * self.len as f32;
+        let idx = (pos as usize).min(self.len - 1);
+        let current = self.values[idx];
+        let glide_from = 1.0 - self.glide;
+        let frac = pos - idx as f32;
+        if self.glide > 0.0 && frac > glide_from {
+            let next = self.values[(idx + 1) % self.len];
+            let t = (frac - glide_from) / self.glide;
+            current + (next - current) * t
+        } else {
+            current
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_interpolates_and_wraps() {
+        let c = LfoCurve::from_flat(&[0.5, 1.0, 0.0, 0.25, 0.0, 0.0]);
+        assert_eq!(c.points()[0].phase, 0.25, "points are sorted");
+        assert!((c.value_at(0.375) - 0.5).abs() < 1e-6);
+        // wrap segment runs 0.5 → 1.25 from 1.0 back to 0.0
+        assert!((c.value_at(0.875) - 0.5).abs() < 1e-6);
+        assert!((c.value_at(0.0) - (1.0 - 0.5 / 0.75)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn curvature_bends_segments() {
+        let flat = |curve| LfoCurve::from_flat(&[0.0, 0.0, curve, 1.0, 1.0, 0.0]);
+        let mid = |c: LfoCurve| c.value_at(0.5);
+        assert!((mid(flat(0.0)) - 0.5).abs() < 1e-6);
+        assert!(mid(flat(1.0)) > 0.8);
+        assert!(mid(flat(-1.0)) < 0.1);
+    }
+
+    #[test]
+    fn steps_hold_and_glide() {
+        let mut s = LfoSteps::default();
+        s.set_values(&[0.0, 1.0, -1.0, 0.5]);
+        assert_eq!(s.values().len(), 4);
+        assert_eq!(s.value_at(0.3), 1.0);
+        assert_eq!(s.value_at(0.99), 0.5);
+        s.set_glide(0.5);
+        assert_eq!(s.value_at(0.25 + 0.1), 1.0); // first half of step holds
+        let sliding = s.value_at(0.25 + 0.1875); // 3/4 through step 1
+        assert!((sliding - 0.0).abs() < 1e-6, "halfway from 1 to -1, got {}", sliding);
+    }
+}
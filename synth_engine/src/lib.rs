@@ -8,6 +8,14 @@ pub mod oscillator;
 pub mod envelope;
 pub mod envelope_trait;
 pub mod mod_envelope;
+pub mod dx_envelope;
+pub mod pitch_envelope;
+pub mod portamento;
+pub mod arpeggiator;
+pub mod sequencer;
+pub mod chord;
+pub mod multi;
+pub mod zone;
 pub mod operator;
 pub mod algorithm;
 pub mod noop_envelope;
@@ -16,6 +24,14 @@ pub mod filter;
 pub mod synth;
 pub mod effects;
 pub mod lfo;
+pub mod lfo_shape;
+pub mod mod_matrix;
+pub mod wavetable;
+pub mod oversampler;
+pub mod rng;
+pub mod noise;
+pub mod smoother;
+pub mod transport;
 
 // Make the `Synth` type available at the crate root
 pub use synth::Synth;
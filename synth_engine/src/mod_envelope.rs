@@ -1,13 +1,15 @@
 // mod_envelope.rs
 
+use crate::envelope::{LoopMode, LoopStage, RetriggerMode, StageCurves, StageRamp};
 use crate::envelope_trait::EnvelopeTrait;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ModEnvelopeState {
     Idle,
     Attack,
     Decay,
     Sustain, // Holds the "end" level.
+    Release,
 }
 
 #[derive(Clone)]
@@ -15,8 +17,14 @@ pub struct ModEnvelope {
     pub attack: f32,  // Attack time in seconds
     pub decay: f32,   // Decay time in seconds
     pub end: f32,     // End level (normalized amplitude, 0.0 to 1.0)
+    pub release: f32, // Release time in seconds (from full level, 0 = snap to zero)
     pub level: f32,   // Current level (0.0 to 1.0)
     pub state: ModEnvelopeState,
+    curves: StageCurves,
+    ramp: StageRamp,
+    retrigger: RetriggerMode,
+    loop_mode: LoopMode,
+    gate: bool, // key held since the last note-on
 }
 
 impl ModEnvelope {
@@ -40,20 +48,96 @@ impl ModEnvelope {
             attack: Self::map_time(attack_val),
             decay: Self::map_time(decay_val),
             end: end_val as f32 / 127.0,
+            release: 0.0,
             level: 0.0,
             state: ModEnvelopeState::Idle,
+            curves: StageCurves::default(),
+            ramp: StageRamp::default(),
+            retrigger: RetriggerMode::default(),
+            loop_mode: LoopMode::default(),
+            gate: false,
+        }
+    }
+
+    /// Update attack, decay and end (0-127) without restarting the envelope.
+    pub fn set_values(&mut self, attack_val: u32, decay_val: u32, end_val: u32) {
+        self.attack = Self::map_time(attack_val);
+        self.decay = Self::map_time(decay_val);
+        self.end = end_val as f32 / 127.0;
+    }
+
+    /// Release time knob (0-127, 0 = drop to zero at note-off, 127 = hold).
+    pub fn set_release(&mut self, release_val: u32) {
+        self.release = Self::map_time(release_val);
+    }
+
+    pub fn set_retrigger(&mut self, mode: RetriggerMode) { self.retrigger = mode; }
+    pub fn retrigger(&self) -> RetriggerMode { self.retrigger }
+
+    /// Loop stages (see `LoopMode`) for rhythmic, note-synced FM depth.
+    pub fn set_loop_mode(&mut self, mode: LoopMode) { self.loop_mode = mode.normalized(); }
+    pub fn loop_mode(&self) -> LoopMode { self.loop_mode }
+
+    fn loop_next(&self, finished: LoopStage) -> Option<LoopStage> {
+        if self.gate || self.loop_mode.ignores_note_off() {
+            self.loop_mode.after(finished)
+        } else {
+            None
         }
     }
 
-    /// Trigger the envelope (start the attack phase).
+    fn enter(&mut self, stage: LoopStage) {
+        self.ramp = StageRamp::default();
+        self.state = match stage {
+            LoopStage::Attack  => ModEnvelopeState::Attack,
+            LoopStage::Decay if self.level >= 1.0 => ModEnvelopeState::Decay,
+            // Climb back to full level rather than jumping to it
+            LoopStage::Decay   => ModEnvelopeState::Attack,
+            LoopStage::Release => ModEnvelopeState::Release,
+        };
+    }
+
+    /// Stage shapes, each -1.0 (log) .. 0.0 (linear) .. 1.0 (exp).
+    pub fn set_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        self.curves.set(attack, decay, release);
+        self.ramp = StageRamp::default();
+    }
+
+    /// Modulation added to all three curve amounts (`OperatorParam::EnvCurve`).
+    pub fn set_curve_offset(&mut self, offset: f32) {
+        if self.curves.set_offset(offset) {
+            self.ramp = StageRamp::default();
+        }
+    }
+
+    /// Trigger the envelope (start the attack phase) according to the retrigger mode.
     pub fn note_on(&mut self) {
+        let held = matches!(self.state,
+            ModEnvelopeState::Attack | ModEnvelopeState::Decay | ModEnvelopeState::Sustain);
+        self.gate = true;
+        if held && self.retrigger == RetriggerMode::Legato {
+            return;
+        }
+        if self.retrigger == RetriggerMode::Reset {
+            self.level = 0.0;
+        }
+        self.ramp = StageRamp::default();
         self.state = ModEnvelopeState::Attack;
     }
 
-    /// Trigger note off. For modulator envelopes, we simply reset the state.
+    /// Start the release stage; with no release time the level drops to zero.
     pub fn note_off(&mut self) {
-        self.state = ModEnvelopeState::Idle;
-        self.level = 0.0;
+        self.gate = false;
+        if self.loop_mode.ignores_note_off() && self.state != ModEnvelopeState::Idle {
+            return;
+        }
+        self.ramp = StageRamp::default();
+        if self.release > 0.0 {
+            self.state = ModEnvelopeState::Release;
+        } else {
+            self.state = ModEnvelopeState::Idle;
+            self.level = 0.0;
+        }
     }
 
     /// Process the envelope over a time step (delta_time in seconds) and update the level.
@@ -64,30 +148,53 @@ impl ModEnvelope {
             }
             ModEnvelopeState::Attack => {
                 if self.attack > 0.0 && self.attack.is_finite() {
-                    self.level += delta_time / self.attack;
+                    self.level = self.ramp.advance(&self.curves.attack, self.level, 0.0, 1.0, delta_time / self.attack);
                 } else if self.attack == 0.0 {
                     self.level = 1.0;
                 }
                 // attack == INFINITY: level stays where it is (hold)
                 if self.level >= 1.0 {
                     self.level = 1.0;
-                    self.state = ModEnvelopeState::Decay;
+                    let next = self.loop_next(LoopStage::Attack).unwrap_or(LoopStage::Decay);
+                    self.enter(next);
                 }
             }
             ModEnvelopeState::Decay => {
-                if self.decay > 0.0 && self.decay.is_finite() {
-                    self.level -= delta_time / self.decay;
+                let span = 1.0 - self.end;
+                if span <= 1e-6 {
+                    self.level = self.end;
+                } else if self.decay > 0.0 && self.decay.is_finite() {
+                    // Curve spans full level → end level
+                    self.level = self.ramp.advance(&self.curves.decay, self.level, self.end, 1.0, -delta_time / (self.decay * span));
                 } else if self.decay == 0.0 {
                     self.level = self.end;
                 }
                 if self.level <= self.end {
                     self.level = self.end;
-                    self.state = ModEnvelopeState::Sustain;
+                    match self.loop_next(LoopStage::Decay) {
+                        Some(stage) => self.enter(stage),
+                        None => self.state = ModEnvelopeState::Sustain,
+                    }
                 }
             }
             ModEnvelopeState::Sustain => {
                 // Hold the level at the "end" value.
             }
+            ModEnvelopeState::Release => {
+                // release == INFINITY: hold where the note was released
+                if self.release == 0.0 {
+                    self.level = 0.0;
+                } else if self.release.is_finite() {
+                    self.level = self.ramp.advance(&self.curves.release, self.level, 0.0, 1.0, -delta_time / self.release);
+                }
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    match self.loop_next(LoopStage::Release) {
+                        Some(stage) => self.enter(stage),
+                        None => self.state = ModEnvelopeState::Idle,
+                    }
+                }
+            }
         }
         self.level
     }
@@ -99,3 +206,64 @@ impl EnvelopeTrait for ModEnvelope {
     fn process(&mut self, delta_time: f32) -> f32 { self.process(delta_time) }
     fn get_level(&self) -> f32 { self.level }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 48_000.0;
+
+    fn run(env: &mut ModEnvelope, seconds: f32) -> f32 {
+        let mut v = env.level;
+        for _ in 0..(seconds / DT) as usize {
+            v = env.process(DT);
+        }
+        v
+    }
+
+    #[test]
+    fn release_fades_from_the_released_level() {
+        let mut env = ModEnvelope::new_from_values(0, 0, 127);
+        env.set_release(63); // 5 s from full level
+        env.note_on();
+        assert_eq!(run(&mut env, 0.01), 1.0);
+        env.note_off();
+        let half = run(&mut env, 2.5);
+        assert!((half - 0.5).abs() < 0.01, "half way through release: {}", half);
+        assert_eq!(run(&mut env, 3.0), 0.0);
+        assert_eq!(env.state, ModEnvelopeState::Idle);
+    }
+
+    #[test]
+    fn zero_release_keeps_old_snap_to_zero() {
+        let mut env = ModEnvelope::new_from_values(0, 0, 127);
+        env.note_on();
+        run(&mut env, 0.01);
+        env.note_off();
+        assert_eq!(env.level, 0.0);
+    }
+
+    #[test]
+    fn cycle_loop_keeps_pulsing_after_note_off() {
+        let mut env = ModEnvelope::new_from_values(3, 3, 0);
+        env.set_loop_mode(LoopMode::Cycle);
+        env.note_on();
+        run(&mut env, 0.1);
+        env.note_off();
+        let peak = (0..(1.0 / DT) as usize).map(|_| env.process(DT)).fold(0.0f32, f32::max);
+        assert_eq!(peak, 1.0, "still cycling after release");
+    }
+
+    #[test]
+    fn from_current_retrigger_does_not_drop_depth() {
+        let mut env = ModEnvelope::new_from_values(20, 0, 127);
+        env.set_release(63);
+        env.set_retrigger(RetriggerMode::FromCurrent);
+        env.note_on();
+        run(&mut env, 2.0);
+        env.note_off();
+        let released = run(&mut env, 0.5);
+        env.note_on();
+        assert!(env.process(DT) >= released);
+    }
+}
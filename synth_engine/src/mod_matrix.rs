@@ -0,0 +1,444 @@
+// src/mod_matrix.rs
+//! Generic modulation matrix.
+//!
+//! Each slot reads a `ModSource`, scales it by a bipolar amount (and
+//! optionally by a second "via" source) and adds the result to a
+//! `ModDestination`. The matrix is evaluated per voice at control rate
+//! (every `CONTROL_BLOCK` frames); voice destinations get each voice's own
+//! value, synth-wide destinations follow the most recently played voice (same
+//! policy as per-voice LFOs). LFO1/LFO2 route to the same destinations.
+
+/// Number of slots in the matrix.
+pub const NUM_SLOTS: usize = 16;
+
+/// Modulation sources. Unipolar sources are 0..1, bipolar ones -1..1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModSource {
+    /// LFO1 shape (bipolar, after fade, before the LFO's own depth).
+    Lfo1,
+    /// LFO2 shape (bipolar).
+    Lfo2,
+    /// Operator mod envelope 0-3 (unipolar).
+    ModEnv(usize),
+    /// Note-on velocity (unipolar).
+    Velocity,
+    /// Key position relative to middle C, ±1 at ±5 octaves (bipolar).
+    Key,
+    /// Channel aftertouch (unipolar).
+    Aftertouch,
+    /// Mod wheel (unipolar).
+    ModWheel,
+    /// Random value drawn at each note-on (bipolar).
+    RandomPerNote,
+}
+
+impl ModSource {
+    pub const COUNT: usize = 11;
+
+    /// JS id → source. 0 means "no source".
+    /// 1 LFO1, 2 LFO2, 3–6 mod env C/A/B1/B2, 7 velocity, 8 key,
+    /// 9 aftertouch, 10 mod wheel, 11 random per note.
+    pub fn from_id(id: u32) -> Option<Self> {
+        Some(match id {
+            1 => ModSource::Lfo1,
+            2 => ModSource::Lfo2,
+            3..=6 => ModSource::ModEnv((id - 3) as usize),
+            7 => ModSource::Velocity,
+            8 => ModSource::Key,
+            9 => ModSource::Aftertouch,
+            10 => ModSource::ModWheel,
+            11 => ModSource::RandomPerNote,
+            _ => return None,
+        })
+    }
+
+    fn index(self) -> usize {
+        match self {
+            ModSource::Lfo1 => 0,
+            ModSource::Lfo2 => 1,
+            ModSource::ModEnv(op) => 2 + op.min(3),
+            ModSource::Velocity => 6,
+            ModSource::Key => 7,
+            ModSource::Aftertouch => 8,
+            ModSource::ModWheel => 9,
+            ModSource::RandomPerNote => 10,
+        }
+    }
+}
+
+/// Current value of every source for one voice.
+#[derive(Clone, Debug, Default)]
+pub struct ModSourceValues {
+    values: [f32; ModSource::COUNT],
+}
+
+impl ModSourceValues {
+    pub fn set(&mut self, source: ModSource, value: f32) {
+        self.values[source.index()] = value;
+    }
+
+    pub fn get(&self, source: ModSource) -> f32 {
+        self.values[source.index()]
+    }
+}
+
+/// Everything a modulation source (an LFO or a matrix slot) can be routed to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModDestination {
+    // FM
+    ModDepthA,
+    ModDepthB,
+    RatioC,
+    RatioA,
+    RatioB,
+    Feedback,
+    Harm,
+    CarrierMix,
+    // Amp envelope
+    AmpAttack,
+    AmpDecay,
+    AmpSustain,
+    AmpRelease,
+    // Amp section
+    Overdrive,
+    Pan,
+    Volume,
+    // Filter envelope
+    FilterAttack,
+    FilterDecay,
+    FilterSustain,
+    FilterRelease,
+    // Filter
+    FilterCutoff,
+    FilterResonance,
+    FilterEnvAmount,
+    // Wavetable operators
+    WavetablePosition,
+    /// Voice pitch in semitones (vibrato, drops).
+    Pitch,
+    /// Speed of LFO 0/1, in its -64..63 speed units.
+    LfoSpeed(usize),
+    /// Depth of LFO 0/1.
+    LfoDepth(usize),
+    /// Glide time in seconds, read when a note starts.
+    Portamento,
+    /// Shape of all three amp envelope stages (log ↔ exp).
+    AmpEnvCurve,
+    /// Shape of all three filter envelope stages.
+    FilterEnvCurve,
+    /// Scales every operator's pitch envelope amount (+1 doubles it).
+    PitchEnvDepth,
+    // Individual operators (0 = C, 1 = A, 2 = B1, 3 = B2)
+    Operator(usize, OperatorParam),
+    /// One FM connection, as a `src * 4 + dst` cell of the depth matrix.
+    Connection(usize),
+    // Global effects
+    Effect(EffectParam),
+    /// The global detune spread, in its 0-127 knob units.
+    Detune,
+}
+
+/// Effect parameters that can be modulated.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EffectParam {
+    ChorusDepth,
+    ChorusSpeed,
+    /// Delay time in ms, applied through the delay's rate-limited glide.
+    DelayTime,
+    DelayFeedback,
+    DelayMix,
+    ReverbDecay,
+    ReverbMix,
+}
+
+/// Per-operator modulation targets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OperatorParam {
+    Level,
+    Ratio,
+    Detune,
+    Feedback,
+    Harm,
+    /// Wavetable frame position (scans the table on wavetable operators).
+    Morph,
+    /// Shape of the operator's mod envelope stages.
+    EnvCurve,
+    /// The operator's pitch envelope amount.
+    PitchEnv,
+}
+
+impl ModDestination {
+    /// JS id → destination, in the order of the enum (0 ModDepthA … 23 Pitch),
+    /// then 24/25 LFO1 speed/depth, 26/27 LFO2 speed/depth, 28 portamento,
+    /// 29 amp env curve, 30 filter env curve, 31 pitch env depth.
+    /// Operator destinations are `32 + op * 8 + param` (param 0 level, 1 ratio,
+    /// 2 detune, 3 feedback, 4 harm, 5 morph, 6 mod env curve, 7 pitch env
+    /// amount); connections are `64 + src * 4 + dst`.
+    /// Effects are 80 chorus depth, 81 chorus speed, 82 delay time,
+    /// 83 delay feedback, 84 delay mix, 85 reverb decay, 86 reverb mix;
+    /// 87 is the global detune.
+    pub fn from_id(id: u32) -> Option<Self> {
+        Some(match id {
+            0  => ModDestination::ModDepthA,
+            1  => ModDestination::ModDepthB,
+            2  => ModDestination::RatioC,
+            3  => ModDestination::RatioA,
+            4  => ModDestination::RatioB,
+            5  => ModDestination::Feedback,
+            6  => ModDestination::Harm,
+            7  => ModDestination::CarrierMix,
+            8  => ModDestination::AmpAttack,
+            9  => ModDestination::AmpDecay,
+            10 => ModDestination::AmpSustain,
+            11 => ModDestination::AmpRelease,
+            12 => ModDestination::Overdrive,
+            13 => ModDestination::Pan,
+            14 => ModDestination::Volume,
+            15 => ModDestination::FilterAttack,
+            16 => ModDestination::FilterDecay,
+            17 => ModDestination::FilterSustain,
+            18 => ModDestination::FilterRelease,
+            19 => ModDestination::FilterCutoff,
+            20 => ModDestination::FilterResonance,
+            21 => ModDestination::FilterEnvAmount,
+            22 => ModDestination::WavetablePosition,
+            23 => ModDestination::Pitch,
+            24 | 26 => ModDestination::LfoSpeed(((id - 24) / 2) as usize),
+            25 | 27 => ModDestination::LfoDepth(((id - 25) / 2) as usize),
+            28 => ModDestination::Portamento,
+            29 => ModDestination::AmpEnvCurve,
+            30 => ModDestination::FilterEnvCurve,
+            31 => ModDestination::PitchEnvDepth,
+            32..=63 => {
+                let op = ((id - 32) / 8) as usize;
+                let param = match (id - 32) % 8 {
+                    0 => OperatorParam::Level,
+                    1 => OperatorParam::Ratio,
+                    2 => OperatorParam::Detune,
+                    3 => OperatorParam::Feedback,
+                    4 => OperatorParam::Harm,
+                    5 => OperatorParam::Morph,
+                    6 => OperatorParam::EnvCurve,
+                    _ => OperatorParam::PitchEnv,
+                };
+                ModDestination::Operator(op, param)
+            }
+            64..=79 => ModDestination::Connection((id - 64) as usize),
+            80 => ModDestination::Effect(EffectParam::ChorusDepth),
+            81 => ModDestination::Effect(EffectParam::ChorusSpeed),
+            82 => ModDestination::Effect(EffectParam::DelayTime),
+            83 => ModDestination::Effect(EffectParam::DelayFeedback),
+            84 => ModDestination::Effect(EffectParam::DelayMix),
+            85 => ModDestination::Effect(EffectParam::ReverbDecay),
+            86 => ModDestination::Effect(EffectParam::ReverbMix),
+            87 => ModDestination::Detune,
+            _  => return None,
+        })
+    }
+
+    /// Parameter change produced by a matrix slot at amount ±1 with a
+    /// full-scale source, in the destination's own units.
+    pub fn range(self) -> f32 {
+        match self {
+            ModDestination::ModDepthA | ModDestination::ModDepthB => 127.0,
+            ModDestination::RatioC | ModDestination::RatioA | ModDestination::RatioB => 4.0,
+            ModDestination::Feedback => 127.0,
+            ModDestination::Harm => 26.0,
+            ModDestination::CarrierMix => 1.0,
+            ModDestination::AmpAttack | ModDestination::AmpDecay | ModDestination::AmpRelease => 2.0,
+            ModDestination::AmpSustain => 1.0,
+            ModDestination::Overdrive => 127.0,
+            ModDestination::Pan => 64.0,
+            ModDestination::Volume => 127.0,
+            ModDestination::FilterAttack | ModDestination::FilterDecay | ModDestination::FilterRelease => 2.0,
+            ModDestination::FilterSustain => 1.0,
+            ModDestination::FilterCutoff => 10_000.0,
+            ModDestination::FilterResonance => 5.0,
+            ModDestination::FilterEnvAmount => 10_000.0,
+            ModDestination::WavetablePosition => 1.0,
+            ModDestination::Pitch => 12.0,
+            ModDestination::LfoSpeed(_) => 64.0,
+            ModDestination::LfoDepth(_) => 1.0,
+            ModDestination::Portamento => 2.0, // s
+            ModDestination::AmpEnvCurve | ModDestination::FilterEnvCurve => 1.0,
+            ModDestination::PitchEnvDepth => 1.0,
+            ModDestination::Operator(_, param) => match param {
+                OperatorParam::Level    => 127.0,
+                OperatorParam::Ratio    => 4.0,
+                OperatorParam::Detune   => 100.0,
+                OperatorParam::Feedback => 127.0,
+                OperatorParam::Harm     => 26.0,
+                OperatorParam::Morph    => 1.0,
+                OperatorParam::EnvCurve => 1.0,
+                OperatorParam::PitchEnv => 1.0,
+            },
+            ModDestination::Connection(_) => 127.0,
+            ModDestination::Detune => 127.0,
+            ModDestination::Effect(param) => match param {
+                EffectParam::ChorusDepth   => 1.0,
+                EffectParam::ChorusSpeed   => 5.0,   // Hz
+                EffectParam::DelayTime     => 500.0, // ms
+                EffectParam::DelayFeedback => 0.99,
+                EffectParam::DelayMix      => 1.0,
+                EffectParam::ReverbDecay   => 0.99,
+                EffectParam::ReverbMix     => 1.0,
+            },
+        }
+    }
+
+    /// Destinations that live on each voice rather than on the synth.
+    /// LFO speed/depth count as voice-level: per-voice LFOs take their own
+    /// voice's value, the global LFOs the most recent note's.
+    pub fn is_voice_level(self) -> bool {
+        matches!(self,
+            ModDestination::ModDepthA | ModDestination::ModDepthB |
+            ModDestination::RatioA | ModDestination::RatioB | ModDestination::RatioC |
+            ModDestination::Feedback | ModDestination::Harm |
+            ModDestination::AmpAttack | ModDestination::AmpDecay |
+            ModDestination::AmpSustain | ModDestination::AmpRelease |
+            ModDestination::WavetablePosition | ModDestination::Pitch |
+            ModDestination::LfoSpeed(_) | ModDestination::LfoDepth(_) |
+            ModDestination::Portamento | ModDestination::AmpEnvCurve |
+            ModDestination::PitchEnvDepth |
+            ModDestination::Detune |
+            ModDestination::Operator(..) | ModDestination::Connection(_))
+    }
+}
+
+/// One routing: `source × amount × via → destination`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ModSlot {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    /// Bipolar amount, -1..1.
+    pub amount: f32,
+    /// Optional scaler (e.g. mod wheel controlling LFO vibrato depth).
+    pub via: Option<ModSource>,
+}
+
+impl ModSlot {
+    /// Offset this slot adds to its destination, in destination units.
+    #[inline]
+    pub fn value(&self, sources: &ModSourceValues) -> f32 {
+        let via = self.via.map_or(1.0, |v| sources.get(v));
+        sources.get(self.source) * self.amount * via * self.destination.range()
+    }
+}
+
+/// Fixed-size matrix of optional slots.
+#[derive(Clone, Debug, Default)]
+pub struct ModMatrix {
+    slots: [Option<ModSlot>; NUM_SLOTS],
+}
+
+impl ModMatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fill or replace slot `idx`. Out-of-range indices are ignored.
+    pub fn set_slot(&mut self, idx: usize, slot: ModSlot) {
+        if let Some(s) = self.slots.get_mut(idx) {
+            *s = Some(ModSlot { amount: slot.amount.clamp(-1.0, 1.0), ..slot });
+        }
+    }
+
+    pub fn clear_slot(&mut self, idx: usize) {
+        if let Some(s) = self.slots.get_mut(idx) {
+            *s = None;
+        }
+    }
+
+    pub fn slot(&self, idx: usize) -> Option<&ModSlot> {
+        self.slots.get(idx).and_then(|s| s.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(|s| s.is_none())
+    }
+
+    /// Evaluate every active slot, calling `apply(destination, offset)`.
+    pub fn evaluate(&self, sources: &ModSourceValues, mut apply: impl FnMut(ModDestination, f32)) {
+        for slot in self.slots.iter().flatten() {
+            let v = slot.value(sources);
+            if v != 0.0 {
+                apply(slot.destination, v);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(source: ModSource, destination: ModDestination, amount: f32) -> ModSlot {
+        ModSlot { source, destination, amount, via: None }
+    }
+
+    #[test]
+    fn slot_scales_source_by_amount_and_range() {
+        let mut src = ModSourceValues::default();
+        src.set(ModSource::Velocity, 0.5);
+        let s = slot(ModSource::Velocity, ModDestination::Volume, -1.0);
+        assert_eq!(s.value(&src), -0.5 * 127.0);
+    }
+
+    #[test]
+    fn via_source_scales_slot() {
+        let mut src = ModSourceValues::default();
+        src.set(ModSource::Lfo1, 1.0);
+        src.set(ModSource::ModWheel, 0.0);
+        let s = ModSlot { via: Some(ModSource::ModWheel), ..slot(ModSource::Lfo1, ModDestination::Pitch, 1.0) };
+        assert_eq!(s.value(&src), 0.0);
+        src.set(ModSource::ModWheel, 0.25);
+        assert_eq!(s.value(&src), 0.25 * 12.0);
+    }
+
+    #[test]
+    fn evaluate_sums_slots_per_destination() {
+        let mut m = ModMatrix::new();
+        m.set_slot(0, slot(ModSource::Key, ModDestination::FilterCutoff, 0.5));
+        m.set_slot(3, slot(ModSource::Key, ModDestination::FilterCutoff, 0.5));
+        m.set_slot(NUM_SLOTS, slot(ModSource::Key, ModDestination::Pan, 1.0)); // ignored
+        let mut src = ModSourceValues::default();
+        src.set(ModSource::Key, 1.0);
+
+        let mut cutoff = 0.0;
+        m.evaluate(&src, |d, v| {
+            assert_eq!(d, ModDestination::FilterCutoff);
+            cutoff += v;
+        });
+        assert_eq!(cutoff, 10_000.0);
+    }
+
+    #[test]
+    fn amount_is_clamped_and_slots_clear() {
+        let mut m = ModMatrix::new();
+        m.set_slot(1, slot(ModSource::ModWheel, ModDestination::Volume, 3.0));
+        assert_eq!(m.slot(1).unwrap().amount, 1.0);
+        m.clear_slot(1);
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn ids_map_to_destinations() {
+        assert_eq!(ModDestination::from_id(19), Some(ModDestination::FilterCutoff));
+        assert_eq!(ModDestination::from_id(23), Some(ModDestination::Pitch));
+        assert_eq!(ModDestination::from_id(32 + 2 * 8 + 3),
+                   Some(ModDestination::Operator(2, OperatorParam::Feedback)));
+        assert_eq!(ModDestination::from_id(68), Some(ModDestination::Connection(4))); // A → C
+        assert_eq!(ModDestination::from_id(26), Some(ModDestination::LfoSpeed(1)));
+        assert_eq!(ModDestination::from_id(25), Some(ModDestination::LfoDepth(0)));
+        assert_eq!(ModDestination::from_id(30), Some(ModDestination::FilterEnvCurve));
+        assert_eq!(ModDestination::from_id(32 + 6),
+                   Some(ModDestination::Operator(0, OperatorParam::EnvCurve)));
+        assert_eq!(ModDestination::from_id(32 + 3 * 8 + 7),
+                   Some(ModDestination::Operator(3, OperatorParam::PitchEnv)));
+        assert_eq!(ModDestination::from_id(82), Some(ModDestination::Effect(EffectParam::DelayTime)));
+        assert_eq!(ModDestination::from_id(87), Some(ModDestination::Detune));
+        assert_eq!(ModDestination::from_id(88), None);
+        assert_eq!(ModSource::from_id(0), None);
+        assert_eq!(ModSource::from_id(5), Some(ModSource::ModEnv(2)));
+    }
+}
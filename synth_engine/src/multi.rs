@@ -0,0 +1,536 @@
+// src/multi.rs
+//! Multi-timbral engine: several `Synth` parts into one mixer.
+//!
+//! Each part is a complete synth (its own voices and patch) listening on a
+//! MIDI channel, with its own mixer volume, pan and sends to a shared
+//! effects bus (chorus, delay, reverb). The parts render without their
+//! patch effects; the bus returns are added to the dry mix and the sum goes
+//! through the stereo master.
+//!
+//! Parts on the same channel make one instrument: give each a key range
+//! and velocity range (a `Zone`) to split the keyboard, or overlap the
+//! ranges to layer patches, each with its own transpose and level. (A
+//! single `Synth` can hold splits and layers too, see `Synth::add_layer`;
+//! parts add their own channel, mixer strip and sends.)
+//!
+//! A drum kit is the same thing with one pad per part: each part gets its
+//! own patch and a single key (or a range), key tracking off so the pad
+//! plays at its own tuning (middle C moved by the part's transpose), and
+//! the part's volume and pan as the pad's level and pan. Pads sharing a
+//! choke group cut each other off, like closed and open hi-hats.
+//!
+//! That is the whole kit model, by design: there is no per-note pad map, so
+//! a kit has at most `MAX_PARTS` (16) pads, one patch each, and every key
+//! a pad covers plays that patch at the same pitch. Pads are full parts
+//! rather than slots in a lighter kit engine so each one gets the synth's
+//! whole patch (and its own voices, mixer strip and sends); pitched
+//! percussion across the keyboard is a key-tracking part.
+//!
+//! JS edits a part's patch by taking its `Synth` out with `take_part`,
+//! calling the usual setters and handing it back with `set_part`. Both run
+//! on the audio thread between `process_sample_array` calls, so the part
+//! never misses a block.
+
+use wasm_bindgen::prelude::*;
+use js_sys::Float32Array;
+
+use crate::effects::Effects;
+use crate::rng::mix_seed;
+use crate::smoother::Smoother;
+use crate::synth::{pan_gains, Synth, BLOCK};
+use crate::zone::Zone;
+
+/// Most parts one engine can host.
+pub const MAX_PARTS: usize = 16;
+
+/// Highest choke group (0 = none).
+pub const MAX_CHOKE_GROUP: u8 = 16;
+
+/// Ramp for mixer volume and pan changes, in seconds.
+const MIXER_RAMP: f32 = 0.005;
+
+/// Which bus effect a send feeds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Send {
+    Chorus,
+    Delay,
+    Reverb,
+}
+
+impl Send {
+    /// 0 chorus, 1 delay, 2 reverb.
+    pub fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Send::Chorus),
+            1 => Some(Send::Delay),
+            2 => Some(Send::Reverb),
+            _ => None,
+        }
+    }
+}
+
+struct Part {
+    synth:   Option<Synth>, // None while JS holds it for editing
+    channel: u8,
+    zone:    Zone,
+    volume:  f32,      // 0–127
+    pan:     f32,      // -63..63
+    sends:   [f32; 3], // chorus, delay, reverb, 0–1
+    choke_group: u8,   // 0 = none
+    volume_smoother: Smoother,
+    pan_smoother:    Smoother,
+}
+
+impl Part {
+    fn new(sample_rate: f32, channel: u8) -> Self {
+        Self {
+            synth:   Some(Synth::new_dry(sample_rate)),
+            channel,
+            zone:    Zone::default(),
+            volume:  100.0,
+            pan:     0.0,
+            sends:   [0.0; 3],
+            choke_group: 0,
+            volume_smoother: Smoother::new(100.0, MIXER_RAMP),
+            pan_smoother:    Smoother::new(0.0, MIXER_RAMP),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub struct MultiSynth {
+    sample_rate: f32,
+    parts: Vec<Part>,
+    effects: Effects,
+    master_volume: f32, // 0–127
+    master_smoother: Smoother,
+}
+
+#[wasm_bindgen]
+impl MultiSynth {
+    /// `num_parts` parts (1–16), part n listening on MIDI channel n.
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: f32, num_parts: usize) -> MultiSynth {
+        let parts = (0..num_parts.clamp(1, MAX_PARTS))
+            .map(|i| Part::new(sample_rate, i as u8))
+            .collect();
+        let mut effects = Effects::new(sample_rate);
+        // Bus returns come back at full level; the sends set how much goes in
+        effects.delay.set_mix(1.0);
+        effects.reverb.set_mix(1.0);
+        MultiSynth {
+            sample_rate,
+            parts,
+            effects,
+            master_volume: 127.0,
+            master_smoother: Smoother::new(127.0, MIXER_RAMP),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn num_parts(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Install `synth` as the patch (and voices) of `part`. Parts play
+    /// through the shared bus, so the synth's own delay lines are dropped.
+    #[wasm_bindgen]
+    pub fn set_part(&mut self, part: usize, mut synth: Synth) {
+        if let Some(p) = self.parts.get_mut(part) {
+            synth.release_effect_buffers();
+            p.synth = Some(synth);
+        }
+    }
+
+    /// Take the synth of `part` out for editing; the part is silent until
+    /// it comes back through `set_part`.
+    #[wasm_bindgen]
+    pub fn take_part(&mut self, part: usize) -> Option<Synth> {
+        self.parts.get_mut(part).and_then(|p| p.synth.take())
+    }
+
+    /// MIDI channel (0-15) the part listens on. Parts may share a channel
+    /// to layer.
+    #[wasm_bindgen]
+    pub fn set_part_channel(&mut self, part: usize, channel: u8) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.channel = channel.min(15);
+        }
+    }
+
+    /// Keys (MIDI notes, inclusive) the part plays; split the keyboard
+    /// between parts on one channel, or overlap ranges to layer them.
+    #[wasm_bindgen]
+    pub fn set_part_key_range(&mut self, part: usize, low: u8, high: u8) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.zone.set_keys(low, high);
+        }
+    }
+
+    /// Velocities (0-127, inclusive) the part plays, for velocity layers.
+    #[wasm_bindgen]
+    pub fn set_part_velocity_range(&mut self, part: usize, low: u8, high: u8) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.zone.set_velocities(low, high);
+        }
+    }
+
+    /// Transpose the part's notes by `semitones` (-48 to 48).
+    #[wasm_bindgen]
+    pub fn set_part_transpose(&mut self, part: usize, semitones: f32) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.zone.set_transpose(semitones);
+        }
+    }
+
+    /// Whether the part's pitch follows the key (default on). Turn it off
+    /// for drum pads, which play middle C moved by the part's transpose.
+    #[wasm_bindgen]
+    pub fn set_part_key_tracking(&mut self, part: usize, on: bool) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.zone.key_track = on;
+        }
+    }
+
+    /// Choke group of the part (1-16, 0 = none). Starting a note on a part
+    /// cuts off every other part in its group.
+    #[wasm_bindgen]
+    pub fn set_part_choke_group(&mut self, part: usize, group: u8) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.choke_group = group.min(MAX_CHOKE_GROUP);
+        }
+    }
+
+    /// Mixer volume of the part (its layer or pad level), 0-127.
+    #[wasm_bindgen]
+    pub fn set_part_volume(&mut self, part: usize, volume: f32) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.volume = volume.clamp(0.0, 127.0);
+        }
+    }
+
+    /// Mixer pan of the part, -63 (left) to 63 (right).
+    #[wasm_bindgen]
+    pub fn set_part_pan(&mut self, part: usize, pan: f32) {
+        if let Some(p) = self.parts.get_mut(part) {
+            p.pan = pan.clamp(-63.0, 63.0);
+        }
+    }
+
+    /// Send level (0-1) from the part to a bus effect: 0 chorus, 1 delay, 2 reverb.
+    #[wasm_bindgen]
+    pub fn set_part_send(&mut self, part: usize, send: u32, level: f32) {
+        if let (Some(p), Some(send)) = (self.parts.get_mut(part), Send::from_id(send)) {
+            p.sends[send as usize] = level.clamp(0.0, 1.0);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.clamp(0.0, 127.0);
+    }
+
+    // ——— Notes ———
+
+    /// Start a note on every part listening on `channel` whose zone
+    /// covers its key and velocity, choking the rest of their choke groups.
+    #[wasm_bindgen]
+    pub fn note_on(&mut self, channel: u8, note_id: u32, freq: f32, velocity: f32) {
+        let mut hit = [false; MAX_PARTS];
+        for (i, part) in self.parts.iter_mut().enumerate().filter(|(_, p)| p.channel == channel) {
+            if let (Some(synth), Some(freq)) = (part.synth.as_mut(), part.zone.map(freq, velocity)) {
+                synth.note_on_velocity(note_id, freq, velocity);
+                hit[i] = true;
+            }
+        }
+        let mut choking = [false; MAX_CHOKE_GROUP as usize + 1];
+        for (part, _) in self.parts.iter().zip(hit).filter(|&(_, hit)| hit) {
+            choking[part.choke_group as usize] = true;
+        }
+        choking[0] = false; // no group
+        for (part, _) in self.parts.iter_mut().zip(hit).filter(|&(_, hit)| !hit) {
+            if let (true, Some(synth)) = (choking[part.choke_group as usize], part.synth.as_mut()) {
+                synth.choke();
+            }
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn note_off(&mut self, channel: u8, note_id: u32) {
+        for synth in self.synths_on(channel) {
+            synth.note_off(note_id);
+        }
+    }
+
+    // ——— Shared settings ———
+
+    /// Seed every part (each gets its own stream).
+    #[wasm_bindgen]
+    pub fn set_seed(&mut self, seed: u32) {
+        for (i, synth) in self.synths().enumerate() {
+            synth.set_seed(mix_seed(seed, i as u32));
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn set_tempo(&mut self, bpm: f32) {
+        for synth in self.synths() {
+            synth.set_tempo(bpm);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn set_playing(&mut self, playing: bool) {
+        for synth in self.synths() {
+            synth.set_playing(playing);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn set_song_position(&mut self, beats: f64) {
+        for synth in self.synths() {
+            synth.set_song_position(beats);
+        }
+    }
+
+    // ——— Effects bus ———
+
+    #[wasm_bindgen]
+    pub fn set_chorus_depth(&mut self, v: f32) { self.effects.chorus.set_depth(v); }
+    #[wasm_bindgen]
+    pub fn set_chorus_speed(&mut self, hz: f32) { self.effects.chorus.set_speed(hz); }
+    #[wasm_bindgen]
+    pub fn set_chorus_width(&mut self, w: f32) { self.effects.chorus.set_width(w); }
+    #[wasm_bindgen]
+    pub fn set_delay_ms(&mut self, ms: f32) { self.effects.delay.set_delay_ms(ms); }
+    #[wasm_bindgen]
+    pub fn set_delay_feedback(&mut self, fb: f32) { self.effects.delay.set_feedback(fb); }
+    /// Delay return level (0-1, default 1).
+    #[wasm_bindgen]
+    pub fn set_delay_return(&mut self, level: f32) { self.effects.delay.set_mix(level); }
+    #[wasm_bindgen]
+    pub fn set_reverb_decay(&mut self, d: f32) { self.effects.reverb.set_decay(d); }
+    #[wasm_bindgen]
+    pub fn set_reverb_damping(&mut self, d: f32) { self.effects.reverb.set_damping(d); }
+    /// Reverb return level (0-1, default 1).
+    #[wasm_bindgen]
+    pub fn set_reverb_return(&mut self, level: f32) { self.effects.reverb.set_mix(level); }
+
+    // ——— Audio rendering ———
+
+    #[wasm_bindgen]
+    pub fn process_sample_array(&mut self) -> Float32Array {
+        let mut out = [0.0f32; BLOCK * 2];
+        self.render_block(&mut out);
+        let array = Float32Array::new_with_length((BLOCK * 2) as u32);
+        array.copy_from(&out);
+        array
+    }
+}
+
+impl MultiSynth {
+    /// Mutable access to a part's synth, for native hosts and tests.
+    pub fn part_mut(&mut self, part: usize) -> Option<&mut Synth> {
+        self.parts.get_mut(part).and_then(|p| p.synth.as_mut())
+    }
+
+    fn synths(&mut self) -> impl Iterator<Item = &mut Synth> {
+        self.parts.iter_mut().filter_map(|p| p.synth.as_mut())
+    }
+
+    /// Synths on `channel`; parts outside a note's zone ignore its note-off.
+    fn synths_on(&mut self, channel: u8) -> impl Iterator<Item = &mut Synth> {
+        self.parts.iter_mut()
+            .filter(move |p| p.channel == channel)
+            .filter_map(|p| p.synth.as_mut())
+    }
+
+    /// Render one block of `BLOCK` interleaved stereo frames into `out`.
+    pub fn render_block(&mut self, out: &mut [f32]) {
+        assert_eq!(out.len(), BLOCK * 2, "render_block needs BLOCK * 2 samples");
+        let dt = 1.0 / self.sample_rate;
+
+        let mut dry = [0.0f32; BLOCK * 2];
+        let mut sends = [[0.0f32; BLOCK * 2]; 3];
+        let mut part_out = [0.0f32; BLOCK * 2];
+        for part in &mut self.parts {
+            let Some(synth) = part.synth.as_mut() else { continue };
+            synth.render_block_dry(&mut part_out);
+            for (i, frame) in part_out.chunks_exact(2).enumerate() {
+                // Equal-power pan and volume, as in the synth's amp section
+                let volume = part.volume_smoother.next(part.volume, dt) / 127.0;
+                let (pan_l, pan_r) = pan_gains(part.pan_smoother.next(part.pan, dt));
+                let l = frame[0] * pan_l * volume;
+                let r = frame[1] * pan_r * volume;
+                dry[2 * i] += l;
+                dry[2 * i + 1] += r;
+                for (bus, &level) in sends.iter_mut().zip(&part.sends) {
+                    bus[2 * i] += l * level;
+                    bus[2 * i + 1] += r * level;
+                }
+            }
+        }
+
+        for (i, frame) in out.chunks_exact_mut(2).enumerate() {
+            let (l, r) = (2 * i, 2 * i + 1);
+            let (wet_l, wet_r) = self.effects.process_sends(
+                (sends[0][l], sends[0][r]),
+                (sends[1][l], sends[1][r]),
+                (sends[2][l], sends[2][r]),
+                dt,
+            );
+            let master = self.master_smoother.next(self.master_volume, dt) / 127.0;
+            frame[0] = (dry[l] + wet_l) * master;
+            frame[1] = (dry[r] + wet_r) * master;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    fn peak(multi: &mut MultiSynth, blocks: usize) -> (f32, f32) {
+        let mut block = [0.0f32; BLOCK * 2];
+        let mut peak = (0.0f32, 0.0f32);
+        for _ in 0..blocks {
+            multi.render_block(&mut block);
+            for frame in block.chunks_exact(2) {
+                peak = (peak.0.max(frame[0].abs()), peak.1.max(frame[1].abs()));
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn channels_route_to_their_parts() {
+        let mut multi = MultiSynth::new(SR, 4);
+        multi.note_on(1, 60, 261.63, 127.0);
+        let held = |multi: &mut MultiSynth, part| {
+            multi.part_mut(part).unwrap().render_block_dry(&mut [0.0; BLOCK * 2]);
+            multi.part_mut(part).unwrap().held_notes()
+        };
+        assert_eq!(held(&mut multi, 1), 1);
+        assert_eq!(held(&mut multi, 0), 0);
+
+        multi.set_part_channel(3, 1); // layer part 3 with part 1
+        multi.note_on(1, 64, 329.63, 127.0);
+        assert_eq!(held(&mut multi, 3), 1);
+        multi.note_off(1, 64);
+        assert_eq!(held(&mut multi, 1), 1);
+    }
+
+    #[test]
+    fn mixer_pans_parts_and_take_part_silences() {
+        let mut multi = MultiSynth::new(SR, 2);
+        multi.set_part_pan(0, -63.0);
+        multi.note_on(0, 60, 261.63, 127.0);
+        peak(&mut multi, 2); // past the pan ramp from center
+        let (l, r) = peak(&mut multi, 8);
+        assert!(l > 0.01 && r < 1e-4, "hard left: {} / {}", l, r);
+
+        let synth = multi.take_part(0).unwrap();
+        peak(&mut multi, 2); // let the pan ramp and any tail pass
+        assert_eq!(peak(&mut multi, 2), (0.0, 0.0));
+        multi.set_part(0, synth);
+        assert!(peak(&mut multi, 2).0 > 0.01, "the part keeps its held note");
+    }
+
+    #[test]
+    fn sends_feed_the_shared_reverb() {
+        let tail = |send| {
+            let mut multi = MultiSynth::new(SR, 2);
+            multi.part_mut(1).unwrap().set_amp_env(0, 0, 127, 0); // instant release
+            multi.set_part_send(1, 2, send);
+            multi.note_on(1, 60, 261.63, 127.0);
+            peak(&mut multi, 20);
+            multi.note_off(1, 60);
+            peak(&mut multi, 8); // past the shortest release
+            peak(&mut multi, 4).0
+        };
+        assert!(tail(0.0) < 1e-6);
+        assert!(tail(1.0) > 1e-4, "reverb tail after the note");
+    }
+
+    #[test]
+    fn bass_left_pad_right_plus_layer() {
+        let mut multi = MultiSynth::new(SR, 3);
+        for part in 0..3 {
+            multi.set_part_channel(part, 0);
+        }
+        multi.set_part_key_range(0, 0, 59);   // bass
+        multi.set_part_key_range(1, 60, 127); // pad
+        multi.set_part_key_range(2, 72, 60);  // bell layer over the pad, reversed args
+        multi.set_part_transpose(0, -12.0);
+        let held = |multi: &mut MultiSynth| -> Vec<usize> {
+            (0..3).map(|p| multi.part_mut(p).unwrap().held_notes()).collect()
+        };
+        multi.note_on(0, 48, 130.81, 100.0);
+        assert_eq!(held(&mut multi), vec![1, 0, 0], "C3: bass only");
+        multi.note_on(0, 64, 329.63, 100.0);
+        assert_eq!(held(&mut multi), vec![1, 1, 1], "E4: pad and bell, range normalized to 60-72");
+        multi.note_off(0, 48);
+        multi.note_off(0, 64);
+        assert_eq!(held(&mut multi), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn full_kit_plays_sixteen_pads_at_their_tuning() {
+        let mut multi = MultiSynth::new(SR, 20);
+        assert_eq!(multi.num_parts(), MAX_PARTS, "sixteen pads at most");
+        for part in 0..MAX_PARTS {
+            multi.set_part_channel(part, 9);
+            multi.set_part_key_range(part, 35 + part as u8, 35 + part as u8); // GM kick to high tom
+            multi.set_part_key_tracking(part, false);
+            multi.set_part_transpose(part, part as f32 - 24.0);
+            multi.set_part_pan(part, part as f32 * 8.0 - 60.0);
+        }
+        for key in 35..35 + MAX_PARTS as u32 {
+            let freq = 440.0 * 2.0f32.powf((key as f32 - 69.0) / 12.0);
+            multi.note_on(9, key, freq, 127.0);
+        }
+        let (l, r) = peak(&mut multi, 4);
+        assert!(l > 0.01 && r > 0.01 && l.is_finite() && r.is_finite(), "{} / {}", l, r);
+        for part in 0..MAX_PARTS {
+            let synth = multi.part_mut(part).unwrap();
+            assert_eq!(synth.held_notes(), 1, "pad {} plays its own key only", part);
+            let tuning = 261.63 * 2.0f32.powf((part as f32 - 24.0) / 12.0);
+            let pitch = synth.held_frequencies()[0];
+            assert!((pitch / tuning - 1.0).abs() < 1e-3, "pad {}: {} Hz", part, pitch);
+        }
+        multi.note_on(9, 60, 261.63, 127.0); // no pad on middle C
+        assert_eq!((0..MAX_PARTS).map(|p| multi.part_mut(p).unwrap().held_notes()).sum::<usize>(), MAX_PARTS);
+    }
+
+    #[test]
+    fn kit_pads_choke_their_group() {
+        // 0 kick, 1 closed hat, 2 open hat, all on channel 9
+        let mut multi = MultiSynth::new(SR, 3);
+        for (part, key) in [36u8, 42, 46].into_iter().enumerate() {
+            multi.set_part_channel(part, 9);
+            multi.set_part_key_range(part, key, key);
+            multi.set_part_key_tracking(part, false);
+        }
+        multi.part_mut(2).unwrap().set_amp_env(0, 0, 127, 60); // long open-hat ring
+        multi.set_part_choke_group(1, 1);
+        multi.set_part_choke_group(2, 1);
+        let level = |multi: &mut MultiSynth, part| {
+            let mut block = [0.0f32; BLOCK * 2];
+            multi.part_mut(part).unwrap().render_block_dry(&mut block);
+            block.iter().fold(0.0f32, |m, s| m.max(s.abs()))
+        };
+
+        multi.note_on(9, 36, 65.41, 127.0);
+        multi.note_on(9, 46, 116.54, 127.0);
+        multi.note_off(9, 46);
+        assert!(level(&mut multi, 2) > 0.01, "open hat rings on after note-off");
+        multi.note_on(9, 42, 92.50, 127.0);
+        level(&mut multi, 2); // the choke fade
+        level(&mut multi, 2);
+        assert!(level(&mut multi, 2) < 1e-6, "closed hat chokes the open one"); // filter residue only
+        assert!(level(&mut multi, 0) > 0.01, "kick is in no group");
+        assert!(level(&mut multi, 1) > 0.01);
+    }
+}
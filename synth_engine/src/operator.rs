@@ -1,6 +1,60 @@
 use crate::oscillator::{Oscillator, WaveType};
 use crate::envelope_trait::EnvelopeTrait;
+use crate::wavetable::Wavetable;
+use crate::smoother::Smoother;
 use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Modulation offsets added on top of an operator's user values.
+/// Reset by the voice at the start of every block.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OperatorMod {
+    pub level: f32,    // 0-127 units
+    pub ratio: f32,    // added to the frequency ratio
+    pub detune: f32,   // cents
+    pub feedback: f32, // 0-127 units
+    pub harm: f32,     // −26…+26 units
+    pub morph: f32,    // wavetable position
+}
+
+/// Ramps each `OperatorMod` field to its block value across the control block.
+#[derive(Copy, Clone, Debug)]
+struct OperatorModRamp {
+    level: Smoother,
+    ratio: Smoother,
+    detune: Smoother,
+    feedback: Smoother,
+    harm: Smoother,
+    morph: Smoother,
+}
+
+impl OperatorModRamp {
+    fn new(sample_rate: f32) -> Self {
+        let ramp = Smoother::block_ramp(0.0, sample_rate);
+        Self { level: ramp, ratio: ramp, detune: ramp, feedback: ramp, harm: ramp, morph: ramp }
+    }
+
+    #[inline]
+    fn next(&mut self, target: &OperatorMod, dt: f32) -> OperatorMod {
+        OperatorMod {
+            level: self.level.next(target.level, dt),
+            ratio: self.ratio.next(target.ratio, dt),
+            detune: self.detune.next(target.detune, dt),
+            feedback: self.feedback.next(target.feedback, dt),
+            harm: self.harm.next(target.harm, dt),
+            morph: self.morph.next(target.morph, dt),
+        }
+    }
+
+    fn reset(&mut self, to: &OperatorMod) {
+        self.level.reset(to.level);
+        self.ratio.reset(to.ratio);
+        self.detune.reset(to.detune);
+        self.feedback.reset(to.feedback);
+        self.harm.reset(to.harm);
+        self.morph.reset(to.morph);
+    }
+}
 
 pub struct FMOperator {
     pub osc: Oscillator,
@@ -12,6 +66,13 @@ pub struct FMOperator {
     pub level: f32,            // Output level 0-127
     pub last_output: f32,
     pub is_modulator: bool,
+    pub wavetable_position: f32,   // Base frame position 0.0-1.0
+    pub wavetable_env_amount: f32, // Mod-envelope → position amount (-1.0..1.0)
+    pub modulation: OperatorMod,   // LFO / mod-matrix offsets for this block
+    mod_ramp: OperatorModRamp,     // Interpolates `modulation` across the block
+    ramped: OperatorMod,           // This sample's modulation offsets
+    level_smoother: Smoother,      // De-zippers set_level changes
+    ratio_smoother: Smoother,      // De-zippers ratio changes
 }
 
 impl FMOperator {
@@ -26,7 +87,14 @@ impl FMOperator {
             detune_cents: 0.0,
             level: 127.0,
             last_output: 0.0,
-            is_modulator: is_modulator,
+            is_modulator,
+            wavetable_position: 0.0,
+            wavetable_env_amount: 0.0,
+            modulation: OperatorMod::default(),
+            mod_ramp: OperatorModRamp::new(sample_rate),
+            ramped: OperatorMod::default(),
+            level_smoother: Smoother::new(127.0, 0.005),
+            ratio_smoother: Smoother::new(1.0, 0.002),
         }
     }
 
@@ -73,8 +141,9 @@ pub fn generate_sample_pm(
 
     /* 2. base pitch with detune ----------------------------------------- */
     // Convert cents to frequency multiplier: 2^(cents/1200)
-    let detune_multiplier = 2.0f32.powf(self.detune_cents / 1200.0);
-    let base_freq = self.osc.base_frequency * self.frequency_ratio * detune_multiplier;
+    let detune_multiplier = 2.0f32.powf((self.detune_cents + self.ramped.detune) / 1200.0);
+    let ratio = (self.ratio_smoother.next(self.frequency_ratio, delta_time) + self.ramped.ratio).clamp(0.25, 16.0);
+    let base_freq = self.osc.base_frequency * ratio * detune_multiplier;
     let phase_inc = base_freq * delta_time;  // cycles this sample
 
     /* 3. advance natural phase ------------------------------------------ */
@@ -84,13 +153,15 @@ pub fn generate_sample_pm(
     let mut sample = self.osc.compute_sample_with_offset(pm_input);
 
     /* 5. HARM wave-folder (Digitone style) ------------------------------ */
-    if self.harm != 0.0 {
-        sample = Self::fold(sample, Self::harm_gain(self.harm));
+    let harm = (self.harm + self.ramped.harm).clamp(-26.0, 26.0);
+    if harm != 0.0 {
+        sample = Self::fold(sample, Self::harm_gain(harm));
     }
 
     /* 6. store last output & apply envelope + level --------------------- */
     self.last_output = sample;
-    let level_multiplier = self.level / 127.0;  // 0-127 → 0.0-1.0
+    let level = (self.level_smoother.next(self.level, delta_time) + self.ramped.level).clamp(0.0, 127.0);
+    let level_multiplier = level / 127.0;  // 0-127 → 0.0-1.0
     sample * env_level * level_multiplier
 }
 
@@ -118,6 +189,20 @@ pub fn generate_sample(
         self.osc.set_wave(wave_type);
     }
 
+    /// Load a shared wavetable and switch this operator to play it.
+    pub fn set_wavetable(&mut self, wavetable: Arc<Wavetable>) {
+        self.osc.set_wavetable(Some(wavetable));
+        self.osc.set_wave(WaveType::Wavetable);
+    }
+
+    pub fn set_wavetable_position(&mut self, position: f32) {
+        self.wavetable_position = position.clamp(0.0, 1.0);
+    }
+
+    pub fn set_wavetable_env_amount(&mut self, amount: f32) {
+        self.wavetable_env_amount = amount.clamp(-1.0, 1.0);
+    }
+
     pub fn set_envelope(&mut self, envelope: Box<dyn EnvelopeTrait>) {
         self.envelope = envelope;
     }
@@ -126,6 +211,25 @@ pub fn generate_sample(
         self.feedback_amount = amount.clamp(0.0, 127.0);
     }
 
+    /// Feedback including this sample's modulation, 0-127.
+    #[inline]
+    pub fn effective_feedback(&self) -> f32 {
+        (self.feedback_amount + self.ramped.feedback).clamp(0.0, 127.0)
+    }
+
+    /// Wavetable position offset from this sample's modulation.
+    #[inline]
+    pub fn morph_offset(&self) -> f32 {
+        self.ramped.morph
+    }
+
+    /// Step the modulation offsets one sample towards this block's values.
+    /// The voice calls this once per sample, before reading or generating.
+    #[inline]
+    pub fn advance_modulation(&mut self, delta_time: f32) {
+        self.ramped = self.mod_ramp.next(&self.modulation, delta_time);
+    }
+
     pub fn set_detune_cents(&mut self, cents: f32) {
         self.detune_cents = cents.clamp(-100.0, 100.0);
     }
@@ -143,4 +247,19 @@ pub fn generate_sample(
     pub fn set_level(&mut self, level: f32) {
         self.level = level.clamp(0.0, 127.0);
     }
+
+    /// Ramp times (seconds) for level and ratio changes.
+    pub fn set_smoothing_times(&mut self, level: f32, ratio: f32) {
+        self.level_smoother.set_ramp_time(level);
+        self.ratio_smoother.set_ramp_time(ratio);
+    }
+
+    /// Jump the smoothed level, ratio and modulation to their current
+    /// values, so a new note doesn't start mid-ramp from the previous note.
+    pub fn reset_smoothing(&mut self) {
+        self.level_smoother.reset(self.level);
+        self.ratio_smoother.reset(self.frequency_ratio);
+        self.mod_ramp.reset(&self.modulation);
+        self.ramped = self.modulation;
+    }
 }
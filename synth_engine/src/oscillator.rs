@@ -1,4 +1,8 @@
 use std::f32::consts::TAU;
+use std::sync::Arc;
+
+use crate::noise::{NoiseColor, NoiseGenerator};
+use crate::wavetable::Wavetable;
 
 
 #[derive(Debug, Copy, Clone)] // Add Debug here
@@ -7,8 +11,46 @@ pub enum WaveType {
     Square,
     Saw,
     Triangle,
+    /// White noise from the oscillator's own seeded generator.
     Noise,
-    // add more if needed…
+    // ——— TX81Z / DX11 (OPZ) waveforms ———
+    /// W1: plain sine (kept separate so imported patches round-trip).
+    OpzW1,
+    /// W2: sine with its peaks sharpened (`sin·|sin|`).
+    OpzW2,
+    /// W3: half-sine — positive lobe only, silent second half.
+    OpzW3,
+    /// W4: positive lobe of W2, silent second half.
+    OpzW4,
+    /// W5: double-speed sine in the first half, silent second half.
+    OpzW5,
+    /// W6: double-speed W2 in the first half, silent second half.
+    OpzW6,
+    /// W7: double-speed abs-sine ("camel") in the first half, silent second half.
+    OpzW7,
+    /// W8: double-speed abs-W2 in the first half, silent second half.
+    OpzW8,
+    /// User wavetable loaded with `Oscillator::set_wavetable` (sine until one is loaded).
+    Wavetable,
+    /// Pink (−3 dB/oct) noise.
+    PinkNoise,
+    /// Brown (−6 dB/oct) noise.
+    BrownNoise,
+    /// Pitch-clocked, interpolated sample-and-hold noise.
+    DigitalNoise,
+}
+
+impl WaveType {
+    /// Noise colour for the noise shapes, `None` for periodic ones.
+    pub fn noise_color(self) -> Option<NoiseColor> {
+        match self {
+            WaveType::Noise        => Some(NoiseColor::White),
+            WaveType::PinkNoise    => Some(NoiseColor::Pink),
+            WaveType::BrownNoise   => Some(NoiseColor::Brown),
+            WaveType::DigitalNoise => Some(NoiseColor::Digital),
+            _ => None,
+        }
+    }
 }
 
 /// A phase‐accumulating oscillator whose phase is in cycles [0.0, 1.0).
@@ -24,6 +66,18 @@ pub struct Oscillator {
 
     /// Waveform shape
     pub wave: WaveType,
+
+    /// Shared user wavetable, read when `wave` is `WaveType::Wavetable`
+    wavetable: Option<Arc<Wavetable>>,
+
+    /// Frame scan position (0..1) across the wavetable
+    pub wavetable_position: f32,
+
+    /// Last phase increment, used to pick the wavetable mip level
+    last_phase_inc: f32,
+
+    /// Per-oscillator noise source (reseeded by the voice at note-on)
+    noise: NoiseGenerator,
 }
 
 impl Oscillator {
@@ -34,6 +88,10 @@ impl Oscillator {
             phase: 0.0,
             sample_rate: sr,
             wave,
+            wavetable: None,
+            wavetable_position: 0.0,
+            last_phase_inc: frequency / sr,
+            noise: NoiseGenerator::new(0),
         }
     }
 
@@ -44,25 +102,16 @@ impl Oscillator {
 
     /// Advance the phase by `phase_inc` cycles (where 1.0 = one full cycle).
     pub fn update_phase(&mut self, phase_inc: f32) {
+        self.last_phase_inc = phase_inc;
         self.phase = ((self.phase + phase_inc) % 1.0 + 1.0) % 1.0;
+        if let Some(color) = self.wave.noise_color() {
+            self.noise.advance(color, phase_inc);
+        }
     }
 
-    fn fast_rand() -> f32 {
-        // parameters from Numerical Recipes
-        const A: u32 = 1664525;
-        const C: u32 = 1013904223;
-    
-        thread_local! {
-            static SEED: std::cell::RefCell<u32> = std::cell::RefCell::new(0x1234_5678);
-        }
-    
-        SEED.with(|s| {
-            let mut seed = *s.borrow();
-            seed = seed.wrapping_mul(A).wrapping_add(C);
-            *s.borrow_mut() = seed;
-            // map 0…u32::MAX → -1.0…+1.0
-            (seed as f32 / std::u32::MAX as f32) * 2.0 - 1.0
-        })
+    /// Restart this oscillator's noise stream from `seed`.
+    pub fn seed_noise(&mut self, seed: u32) {
+        self.noise.reseed(seed);
     }
 
     /// Compute the current sample based on phase and waveform.
@@ -87,7 +136,51 @@ impl Oscillator {
 
             WaveType::Triangle =>          1.0 - 4.0 * (effective_phase - 0.5).abs(),
 
-            WaveType::Noise     => Self::fast_rand(),
+            WaveType::Noise        |
+            WaveType::PinkNoise    |
+            WaveType::BrownNoise   |
+            WaveType::DigitalNoise => self.noise.value(),
+
+            WaveType::OpzW1 |
+            WaveType::OpzW2 |
+            WaveType::OpzW3 |
+            WaveType::OpzW4 |
+            WaveType::OpzW5 |
+            WaveType::OpzW6 |
+            WaveType::OpzW7 |
+            WaveType::OpzW8 => Self::opz_wave(self.wave, effective_phase),
+
+            WaveType::Wavetable => match &self.wavetable {
+                Some(wt) => wt.sample(self.wavetable_position, effective_phase, self.last_phase_inc),
+                None     => angle.sin(),
+            },
+        }
+    }
+
+    /// Evaluate one of the eight OPZ waveforms at `phase` (cycles, 0..1).
+    ///
+    /// W5–W8 squeeze a full W1–W4 cycle into the first half and stay silent
+    /// for the second half, which is how the TX81Z builds its "alternating"
+    /// shapes. Everything is a pure function of phase, so PM works exactly as
+    /// it does for `Sine`.
+    fn opz_wave(wave: WaveType, phase: f32) -> f32 {
+        let sharp = |p: f32| {
+            let s = (p * TAU).sin();
+            s * s.abs()
+        };
+        let double = phase * 2.0;
+
+        match wave {
+            WaveType::OpzW1 => (phase * TAU).sin(),
+            WaveType::OpzW2 => sharp(phase),
+            _ if phase >= 0.5 => 0.0,
+            WaveType::OpzW3 => (phase * TAU).sin(),
+            WaveType::OpzW4 => sharp(phase),
+            WaveType::OpzW5 => (double * TAU).sin(),
+            WaveType::OpzW6 => sharp(double),
+            WaveType::OpzW7 => (double * TAU).sin().abs(),
+            WaveType::OpzW8 => sharp(double).abs(),
+            _ => 0.0,
         }
     }
 
@@ -103,4 +196,75 @@ impl Oscillator {
     pub fn set_wave(&mut self, wave: WaveType) {
         self.wave = wave;
     }
+
+    /// Attach a shared wavetable (or detach with `None`).
+    pub fn set_wavetable(&mut self, wavetable: Option<Arc<Wavetable>>) {
+        self.wavetable = wavetable;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPZ: [WaveType; 8] = [
+        WaveType::OpzW1, WaveType::OpzW2, WaveType::OpzW3, WaveType::OpzW4,
+        WaveType::OpzW5, WaveType::OpzW6, WaveType::OpzW7, WaveType::OpzW8,
+    ];
+
+    fn sample_at(wave: WaveType, phase: f32) -> f32 {
+        let mut osc = Oscillator::new(440.0, 48_000.0, wave);
+        osc.phase = phase;
+        osc.compute_sample()
+    }
+
+    #[test]
+    fn opz_w1_matches_sine() {
+        for i in 0..64 {
+            let ph = i as f32 / 64.0;
+            let diff = (sample_at(WaveType::OpzW1, ph) - sample_at(WaveType::Sine, ph)).abs();
+            assert!(diff < 1e-6, "W1 differs from sine at phase {}", ph);
+        }
+    }
+
+    #[test]
+    fn opz_waves_are_bounded() {
+        for &wave in &OPZ {
+            for i in 0..256 {
+                let v = sample_at(wave, i as f32 / 256.0);
+                assert!((-1.0..=1.0).contains(&v), "{:?} out of range: {}", wave, v);
+            }
+        }
+    }
+
+    #[test]
+    fn opz_half_waves_are_silent_in_second_half() {
+        for &wave in &OPZ[2..] {
+            for i in 0..32 {
+                let ph = 0.5 + i as f32 / 64.0;
+                assert_eq!(sample_at(wave, ph), 0.0, "{:?} not silent at phase {}", wave, ph);
+            }
+        }
+    }
+
+    #[test]
+    fn opz_w7_is_non_negative() {
+        for i in 0..256 {
+            assert!(sample_at(WaveType::OpzW7, i as f32 / 256.0) >= 0.0);
+        }
+    }
+
+    /// A phase offset must shift OPZ waves exactly like it shifts a sine.
+    #[test]
+    fn opz_waves_follow_phase_offset() {
+        for &wave in &OPZ {
+            let mut osc = Oscillator::new(440.0, 48_000.0, wave);
+            osc.phase = 0.1;
+            let shifted = osc.compute_sample_with_offset(0.25);
+            osc.phase = 0.35;
+            let direct = osc.compute_sample();
+            assert!((shifted - direct).abs() < 1e-5, "{:?} ignores PM offset", wave);
+        }
+    }
 }
@@ -0,0 +1,195 @@
+// src/oversampler.rs
+//! Oversampling support for the FM core.
+//!
+//! Voices and the overdrive stage run `factor` times per output sample; the
+//! result is brought back to the host rate by cascaded half-band FIR
+//! decimators (one per 2x stage).
+
+use std::f32::consts::PI;
+
+/// Taps per half-band stage (odd, and (N-1)/2 must be odd for a true half-band).
+const HALF_BAND_TAPS: usize = 47;
+
+/// Quality switch for the FM core.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Oversampling {
+    Off,
+    X2,
+    X4,
+}
+
+impl Oversampling {
+    /// Map the JS-facing factor (1/2/4) to a mode; anything else is `Off`.
+    pub fn from_factor(factor: u32) -> Self {
+        match factor {
+            2 => Oversampling::X2,
+            4 => Oversampling::X4,
+            _ => Oversampling::Off,
+        }
+    }
+
+    pub fn factor(self) -> usize {
+        match self {
+            Oversampling::Off => 1,
+            Oversampling::X2  => 2,
+            Oversampling::X4  => 4,
+        }
+    }
+}
+
+/// Windowed-sinc half-band low-pass (cutoff at a quarter of the input rate),
+/// Blackman-Harris window, normalised to unity DC gain.
+fn half_band_kernel<const N: usize>() -> [f32; N] {
+    let mut k = [0.0f32; N];
+    let mid = (N / 2) as f32;
+    let span = (N - 1) as f32;
+    for (i, c) in k.iter_mut().enumerate() {
+        let n = i as f32 - mid;
+        let sinc = if n == 0.0 { 0.5 } else { (PI * 0.5 * n).sin() / (PI * n) };
+        let x = 2.0 * PI * i as f32 / span;
+        let w = 0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos();
+        *c = sinc * w;
+    }
+    let sum: f32 = k.iter().sum();
+    k.iter_mut().for_each(|c| *c /= sum);
+    k
+}
+
+/// One 2:1 decimation stage.
+struct HalfBandDecimator {
+    kernel: [f32; HALF_BAND_TAPS],
+    /// History written twice so the convolution reads one contiguous slice.
+    history: [f32; HALF_BAND_TAPS * 2],
+    pos: usize,
+}
+
+impl HalfBandDecimator {
+    fn new() -> Self {
+        Self {
+            kernel: half_band_kernel(),
+            history: [0.0; HALF_BAND_TAPS * 2],
+            pos: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, x: f32) {
+        self.history[self.pos] = x;
+        self.history[self.pos + HALF_BAND_TAPS] = x;
+        self.pos = (self.pos + 1) % HALF_BAND_TAPS;
+    }
+
+    /// Consume two input samples, return one output sample.
+    #[inline]
+    fn process(&mut self, a: f32, b: f32) -> f32 {
+        self.push(a);
+        self.push(b);
+        let window = &self.history[self.pos..self.pos + HALF_BAND_TAPS];
+        // Every other tap (except the centre) is zero in a half-band kernel.
+        let mid = HALF_BAND_TAPS / 2;
+        let mut acc = self.kernel[mid] * window[mid];
+        for i in (0..HALF_BAND_TAPS).step_by(2) {
+            acc += self.kernel[i] * window[i];
+        }
+        acc
+    }
+
+    fn reset(&mut self) {
+        self.history = [0.0; HALF_BAND_TAPS * 2];
+        self.pos = 0;
+    }
+}
+
+/// Brings one channel from `factor`× rate back to the host rate.
+pub struct Decimator {
+    stage1: HalfBandDecimator, // 4x → 2x
+    stage2: HalfBandDecimator, // 2x → 1x
+}
+
+impl Default for Decimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decimator {
+    pub fn new() -> Self {
+        Self {
+            stage1: HalfBandDecimator::new(),
+            stage2: HalfBandDecimator::new(),
+        }
+    }
+
+    /// Decimate one block of `mode.factor()` oversampled samples.
+    #[inline]
+    pub fn process(&mut self, mode: Oversampling, input: &[f32]) -> f32 {
+        match mode {
+            Oversampling::Off => input[0],
+            Oversampling::X2  => self.stage2.process(input[0], input[1]),
+            Oversampling::X4  => {
+                let a = self.stage1.process(input[0], input[1]);
+                let b = self.stage1.process(input[2], input[3]);
+                self.stage2.process(a, b)
+            }
+        }
+    }
+
+    /// Clear filter history (call when switching modes).
+    pub fn reset(&mut self) {
+        self.stage1.reset();
+        self.stage2.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    /// Run a sine at `freq` (as a fraction of the *oversampled* rate) through
+    /// the decimator and return the steady-state output peak.
+    fn output_peak(mode: Oversampling, freq: f32) -> f32 {
+        let factor = mode.factor();
+        let mut dec = Decimator::new();
+        let mut n = 0usize;
+        let mut peak = 0.0f32;
+        for block in 0..4000 {
+            let mut buf = [0.0f32; 4];
+            for b in buf.iter_mut().take(factor) {
+                *b = (TAU * freq * n as f32).sin();
+                n += 1;
+            }
+            let y = dec.process(mode, &buf[..factor]);
+            if block > 200 {
+                peak = peak.max(y.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn passband_has_unity_gain() {
+        // 1 kHz at 48k×2 and 48k×4
+        for &mode in &[Oversampling::X2, Oversampling::X4] {
+            let f = 1000.0 / (48_000.0 * mode.factor() as f32);
+            let peak = output_peak(mode, f);
+            assert!((peak - 1.0).abs() < 0.01, "{:?} passband gain {}", mode, peak);
+        }
+    }
+
+    #[test]
+    fn content_above_host_nyquist_is_suppressed() {
+        // 36 kHz would fold back to 12 kHz at 48 kHz; it must be attenuated.
+        for &mode in &[Oversampling::X2, Oversampling::X4] {
+            let f = 36_000.0 / (48_000.0 * mode.factor() as f32);
+            let peak = output_peak(mode, f);
+            assert!(peak < 1e-3, "{:?} aliasing leak {}", mode, peak);
+        }
+    }
+
+    #[test]
+    fn off_is_passthrough() {
+        let mut dec = Decimator::new();
+        assert_eq!(dec.process(Oversampling::Off, &[0.42]), 0.42);
+    }
+}
@@ -0,0 +1,151 @@
+// src/pitch_envelope.rs
+//! Per-voice pitch envelope (PEG), in semitones.
+//!
+//! Four levels and four segment times, in the DX pitch-EG layout: the
+//! envelope rests at L4; note-on moves it to L1, L2 and then L3 (each over
+//! its own time), L3 is held while the key is down, and note-off returns it
+//! to L4. Segments are linear in semitones, i.e. exponential in Hz.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PegStage {
+    /// Moving to `levels[i]` over `times[i]` (0–2 after note-on, 3 after note-off).
+    Segment(usize),
+    /// Holding L3 while the key is down.
+    Hold,
+    /// Resting at L4.
+    Rest,
+}
+
+#[derive(Clone, Debug)]
+pub struct PitchEnvelope {
+    levels:  [f32; 4], // semitones
+    times:   [f32; 4], // seconds (0 = jump, ∞ = never arrive)
+    stage:   PegStage,
+    from:    f32,      // value when the current segment started
+    elapsed: f32,      // seconds into the current segment
+    value:   f32,      // current offset in semitones
+}
+
+impl Default for PitchEnvelope {
+    fn default() -> Self {
+        Self::new([0.0; 4], [0.0; 4])
+    }
+}
+
+impl PitchEnvelope {
+    /// Largest offset a level can ask for, in semitones.
+    pub const MAX_SEMITONES: f32 = 48.0;
+
+    pub fn new(levels: [f32; 4], times: [f32; 4]) -> Self {
+        let mut env = Self {
+            levels:  [0.0; 4],
+            times:   [0.0; 4],
+            stage:   PegStage::Rest,
+            from:    0.0,
+            elapsed: 0.0,
+            value:   0.0,
+        };
+        env.set_params(levels, times);
+        env.value = env.levels[3];
+        env
+    }
+
+    /// Change levels and times without restarting the envelope.
+    pub fn set_params(&mut self, levels: [f32; 4], times: [f32; 4]) {
+        self.levels = levels.map(|l| l.clamp(-Self::MAX_SEMITONES, Self::MAX_SEMITONES));
+        self.times = times.map(|t| t.max(0.0));
+    }
+
+    pub fn levels(&self) -> [f32; 4] {
+        self.levels
+    }
+
+    pub fn times(&self) -> [f32; 4] {
+        self.times
+    }
+
+    fn start_segment(&mut self, i: usize) {
+        self.stage = PegStage::Segment(i);
+        self.from = self.value;
+        self.elapsed = 0.0;
+    }
+
+    pub fn note_on(&mut self) {
+        self.start_segment(0);
+    }
+
+    pub fn note_off(&mut self) {
+        self.start_segment(3);
+    }
+
+    /// Advance by `dt` seconds and return the pitch offset in semitones.
+    pub fn process(&mut self, dt: f32) -> f32 {
+        let mut dt = dt;
+        loop {
+            match self.stage {
+                PegStage::Rest => { self.value = self.levels[3]; break; }
+                PegStage::Hold => { self.value = self.levels[2]; break; }
+                PegStage::Segment(i) => {
+                    let (target, time) = (self.levels[i], self.times[i]);
+                    self.elapsed += dt;
+                    if self.elapsed < time {
+                        self.value = self.from + (target - self.from) * (self.elapsed / time);
+                        break;
+                    }
+                    // Segment done: carry the overshoot into the next one,
+                    // so zero-time segments chain within one sample
+                    dt = self.elapsed - time;
+                    self.value = target;
+                    match i {
+                        0 | 1 => self.start_segment(i + 1),
+                        2     => self.stage = PegStage::Hold,
+                        _     => self.stage = PegStage::Rest,
+                    }
+                }
+            }
+        }
+        self.value
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 48_000.0;
+
+    fn run(env: &mut PitchEnvelope, seconds: f32) -> f32 {
+        let mut v = env.value();
+        for _ in 0..(seconds / DT).round() as usize {
+            v = env.process(DT);
+        }
+        v
+    }
+
+    #[test]
+    fn kick_drop_jumps_up_then_falls() {
+        // +24 instantly, down to 0 over 100 ms
+        let mut env = PitchEnvelope::new([24.0, 0.0, 0.0, 0.0], [0.0, 0.1, 0.0, 0.0]);
+        assert_eq!(env.value(), 0.0);
+        env.note_on();
+        assert!(env.process(DT) > 23.9);
+        let mid = run(&mut env, 0.05);
+        assert!((mid - 12.0).abs() < 0.1, "half way down: {}", mid);
+        assert_eq!(run(&mut env, 0.1), 0.0);
+    }
+
+    #[test]
+    fn holds_l3_then_returns_to_l4_on_release() {
+        let mut env = PitchEnvelope::new([-3.0, 2.0, 5.0, 0.0], [0.01, 0.01, 0.01, 0.2]);
+        env.note_on();
+        assert_eq!(run(&mut env, 0.5), 5.0);
+        env.note_off();
+        let mid = run(&mut env, 0.1);
+        assert!((mid - 2.5).abs() < 0.05, "release half way: {}", mid);
+        assert_eq!(run(&mut env, 0.2), 0.0);
+    }
+}
@@ -0,0 +1,216 @@
+// src/portamento.rs
+//! Pitch glide between notes.
+//!
+//! Glides run in the log (octave) domain, so every interval sounds like the
+//! same kind of slide. Constant-time glides take the set time whatever the
+//! interval; constant-rate glides take the set time per octave.
+
+/// When a new note glides from the previous one.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PortamentoMode {
+    /// Never glide.
+    Off,
+    /// Glide from the last note played, whether or not it is still held.
+    Always,
+    /// Glide only when another note is still held.
+    #[default]
+    Legato,
+}
+
+impl PortamentoMode {
+    /// 0 off, 1 always, 2 legato.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            0 => PortamentoMode::Off,
+            1 => PortamentoMode::Always,
+            _ => PortamentoMode::Legato,
+        }
+    }
+}
+
+/// How the glide time is applied.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum GlideType {
+    /// Every glide takes the glide time.
+    #[default]
+    ConstantTime,
+    /// The glide time is per octave, so wider intervals take longer.
+    ConstantRate,
+}
+
+impl GlideType {
+    /// 0 constant time, 1 constant rate.
+    pub fn from_id(id: u32) -> Self {
+        if id == 1 { GlideType::ConstantRate } else { GlideType::ConstantTime }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Portamento {
+    mode:      PortamentoMode,
+    glide:     GlideType,
+    time:      f32, // seconds (per glide, or per octave)
+    time_offset: f32, // modulation added to `time` (Portamento destination)
+    // f64 so per-sample steps don't round away at 4x oversampled rates
+    current:   f64, // log2 Hz
+    target:    f64, // log2 Hz
+    rate:      f64, // octaves per second for the glide in progress
+}
+
+impl Default for Portamento {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Portamento {
+    /// Longest glide time, for knob 127.
+    pub const MAX_TIME: f32 = 2.0;
+
+    pub fn new() -> Self {
+        let a4 = 440.0f64.log2();
+        Self {
+            mode:    PortamentoMode::default(),
+            glide:   GlideType::default(),
+            time:    0.0,
+            time_offset: 0.0,
+            current: a4,
+            target:  a4,
+            rate:    0.0,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: PortamentoMode) { self.mode = mode; }
+    pub fn set_glide_type(&mut self, glide: GlideType) { self.glide = glide; }
+
+    /// Glide time from a 0-127 knob (0 = instant, 127 = `MAX_TIME`).
+    pub fn set_time_knob(&mut self, knob: f32) {
+        self.time = (knob.clamp(0.0, 127.0) / 127.0) * Self::MAX_TIME;
+    }
+
+    /// Modulation added to the glide time (seconds) for notes started from
+    /// now on; a glide in progress keeps its rate.
+    pub fn set_time_offset(&mut self, seconds: f32) { self.time_offset = seconds; }
+
+    pub fn mode(&self) -> PortamentoMode { self.mode }
+    pub fn glide_type(&self) -> GlideType { self.glide }
+    pub fn time(&self) -> f32 { self.time }
+
+    /// Start a note at `freq`. `from` is the previous note's frequency (if
+    /// any) and `legato` whether another note is still held.
+    pub fn note_on(&mut self, freq: f32, from: Option<f32>, legato: bool) {
+        self.target = (freq.max(1e-3) as f64).log2();
+        let glide = match self.mode {
+            PortamentoMode::Off    => false,
+            PortamentoMode::Always => true,
+            PortamentoMode::Legato => legato,
+        };
+        let time = (self.time + self.time_offset).clamp(0.0, Self::MAX_TIME);
+        match from {
+            Some(from) if glide && time > 0.0 && from > 0.0 => {
+                self.current = (from as f64).log2();
+                let octaves = (self.target - self.current).abs();
+                self.rate = match self.glide {
+                    GlideType::ConstantTime => octaves / time as f64,
+                    GlideType::ConstantRate => 1.0 / time as f64,
+                };
+            }
+            _ => self.current = self.target,
+        }
+    }
+
+    /// Advance by `dt` seconds and return the current frequency in Hz.
+    #[inline]
+    pub fn process(&mut self, dt: f32) -> f32 {
+        if self.current != self.target {
+            let step = self.rate * dt as f64;
+            let diff = self.target - self.current;
+            self.current = if diff.abs() <= step { self.target } else { self.current + step.copysign(diff) };
+        }
+        self.current.exp2() as f32
+    }
+
+    pub fn is_gliding(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Frequency the glide is heading to.
+    pub fn target_frequency(&self) -> f32 {
+        self.target.exp2() as f32
+    }
+
+    pub fn current_frequency(&self) -> f32 {
+        self.current.exp2() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 1000.0;
+
+    fn glide_time(p: &mut Portamento) -> f32 {
+        let mut t = 0.0;
+        while p.is_gliding() && t < 10.0 {
+            p.process(DT);
+            t += DT;
+        }
+        t
+    }
+
+    #[test]
+    fn constant_time_ignores_interval() {
+        let mut p = Portamento::new();
+        p.set_mode(PortamentoMode::Always);
+        p.set_time_knob(127.0 / 4.0); // 0.5 s
+        p.note_on(440.0, Some(220.0), false);
+        assert!((glide_time(&mut p) - 0.5).abs() < 0.01);
+        p.note_on(440.0 * 8.0, Some(440.0), false);
+        assert!((glide_time(&mut p) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn constant_rate_scales_with_octaves_and_is_log_domain() {
+        let mut p = Portamento::new();
+        p.set_mode(PortamentoMode::Always);
+        p.set_glide_type(GlideType::ConstantRate);
+        p.set_time_knob(127.0 / 4.0); // 0.5 s per octave
+        p.note_on(880.0, Some(220.0), false);
+        // half way in time is one octave up: geometric, not 550 Hz
+        for _ in 0..500 {
+            p.process(DT);
+        }
+        assert!((p.current_frequency() - 440.0).abs() < 1.0, "{}", p.current_frequency());
+        assert!((glide_time(&mut p) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn time_offset_lengthens_the_next_glide() {
+        let mut p = Portamento::new();
+        p.set_mode(PortamentoMode::Always);
+        p.set_time_knob(127.0 / 4.0); // 0.5 s
+        p.set_time_offset(0.25);
+        p.note_on(440.0, Some(220.0), false);
+        assert!((glide_time(&mut p) - 0.75).abs() < 0.01);
+        p.set_time_offset(-1.0); // clamped to no glide
+        p.note_on(220.0, Some(440.0), false);
+        assert!(!p.is_gliding());
+    }
+
+    #[test]
+    fn modes_decide_when_to_glide() {
+        let mut p = Portamento::new();
+        p.set_time_knob(64.0);
+        p.note_on(440.0, Some(220.0), false);
+        assert!(!p.is_gliding(), "legato mode, nothing held");
+        p.note_on(440.0, Some(220.0), true);
+        assert!(p.is_gliding());
+        p.set_mode(PortamentoMode::Off);
+        p.note_on(440.0, Some(220.0), true);
+        assert!(!p.is_gliding());
+        p.set_mode(PortamentoMode::Always);
+        p.note_on(440.0, None, true);
+        assert!(!p.is_gliding(), "no previous note");
+    }
+}
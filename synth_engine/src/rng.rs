@@ -0,0 +1,66 @@
+// src/rng.rs
+//! Small seedable PRNG used by every random source in the engine.
+//!
+//! Deliberately not `thread_local` or time-seeded: each owner (operator noise,
+//! LFO random shapes, …) keeps its own `Rng`, so the same seed always gives the
+//! same stream regardless of how many other generators are running.
+
+/// Hash a 32-bit value (murmur3 finaliser). Used to turn small, related seeds
+/// (voice index, note counter, operator index) into unrelated streams.
+#[inline]
+pub fn hash_u32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^= x >> 16;
+    x
+}
+
+/// Combine two seeds into one.
+#[inline]
+pub fn mix_seed(a: u32, b: u32) -> u32 {
+    hash_u32(a ^ hash_u32(b).wrapping_add(0x9e37_79b9).wrapping_add(a << 6).wrapping_add(a >> 2))
+}
+
+/// xorshift32 generator.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        let mut rng = Rng { state: 1 };
+        rng.reseed(seed);
+        rng
+    }
+
+    /// Restart the stream from `seed`.
+    pub fn reseed(&mut self, seed: u32) {
+        // xorshift must never hold zero
+        self.state = hash_u32(seed).max(1);
+    }
+
+    #[inline]
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Uniform value in [0, 1).
+    #[inline]
+    pub fn next_unit(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Uniform value in [-1, 1).
+    #[inline]
+    pub fn next_bipolar(&mut self) -> f32 {
+        self.next_unit() * 2.0 - 1.0
+    }
+}
@@ -0,0 +1,569 @@
+// src/sequencer.rs
+//! Pattern step sequencer with parameter locks.
+//!
+//! A pattern has 16–64 steps. Each step can hold a trig (note, velocity,
+//! length in steps and micro-timing) and parameter locks: absolute values
+//! for engine parameters that hold from that step until the next step with
+//! a trig or locks of its own, after which the engine returns to the patch
+//! values. The sequencer runs off the engine transport while it plays, so
+//! the same song position always gives the same notes.
+
+use crate::mod_matrix::{ModDestination, OperatorParam};
+use crate::transport::{division_beats, Clock, NUM_DIVISIONS, SIXTEENTH};
+
+/// Note ids of sequencer notes carry this bit, plus the step in bits 8–15,
+/// so overlapping notes from different steps never collide with each other,
+/// host notes or the arpeggiator.
+const SEQ_ID: u32 = 0x4000_0000;
+
+pub const MIN_STEPS: usize = 16;
+pub const MAX_STEPS: usize = 64;
+
+/// Longest note, in steps.
+pub const MAX_LENGTH: f32 = 64.0;
+
+/// Engine parameters a step can lock.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SeqParam {
+    CarrierMix,
+    Volume,
+    Pan,
+    Overdrive,
+    FilterCutoff,
+    FilterResonance,
+    FilterEnvAmount,
+    RatioC,
+    RatioA,
+    RatioB1,
+    RatioB2,
+    Detune,
+    Feedback,
+    Harm,
+    Lfo1Speed,
+    Lfo1Depth,
+    Lfo2Speed,
+    Lfo2Depth,
+    /// One `mod_depth_matrix` cell, src * 4 + dst.
+    ModDepth(usize),
+}
+
+impl SeqParam {
+    /// Lockable parameter for a modulation destination id
+    /// (`ModDestination::from_id`): 2 ratio C, 3 ratio A, 5 feedback,
+    /// 6 harm, 7 carrier mix, 12 overdrive, 13 pan, 14 volume, 19 filter
+    /// cutoff, 20 resonance, 21 filter env amount, 24–27 LFO1/LFO2 speed and
+    /// depth, 49/57 ratio B1/B2 (the operators' ratio ids), 64–79 mod depth
+    /// matrix cells, 87 detune. Depth A/B (0/1) lock several cells, see `targets`.
+    pub fn from_id(id: u32) -> Option<Self> {
+        Some(match ModDestination::from_id(id)? {
+            ModDestination::RatioC          => SeqParam::RatioC,
+            ModDestination::RatioA          => SeqParam::RatioA,
+            ModDestination::Feedback        => SeqParam::Feedback,
+            ModDestination::Harm            => SeqParam::Harm,
+            ModDestination::CarrierMix      => SeqParam::CarrierMix,
+            ModDestination::Overdrive       => SeqParam::Overdrive,
+            ModDestination::Pan             => SeqParam::Pan,
+            ModDestination::Volume          => SeqParam::Volume,
+            ModDestination::FilterCutoff    => SeqParam::FilterCutoff,
+            ModDestination::FilterResonance => SeqParam::FilterResonance,
+            ModDestination::FilterEnvAmount => SeqParam::FilterEnvAmount,
+            ModDestination::LfoSpeed(0)     => SeqParam::Lfo1Speed,
+            ModDestination::LfoDepth(0)     => SeqParam::Lfo1Depth,
+            ModDestination::LfoSpeed(_)     => SeqParam::Lfo2Speed,
+            ModDestination::LfoDepth(_)     => SeqParam::Lfo2Depth,
+            ModDestination::Operator(2, OperatorParam::Ratio) => SeqParam::RatioB1,
+            ModDestination::Operator(3, OperatorParam::Ratio) => SeqParam::RatioB2,
+            ModDestination::Connection(cell) => SeqParam::ModDepth(cell),
+            ModDestination::Detune          => SeqParam::Detune,
+            _ => return None,
+        })
+    }
+
+    /// Everything a lock on destination id `id` sets. Depth A (0) and B (1)
+    /// lock every depth matrix cell the `ModDepthA/B` destinations move:
+    /// connections from C/A (cells 0–7) and from B1/B2 (cells 8–15).
+    pub fn targets(id: u32) -> impl Iterator<Item = Self> {
+        let cells = match ModDestination::from_id(id) {
+            Some(ModDestination::ModDepthA) => 0..8,
+            Some(ModDestination::ModDepthB) => 8..16,
+            _ => 0..0,
+        };
+        cells.map(SeqParam::ModDepth).chain(Self::from_id(id))
+    }
+
+    /// Inverse of `from_id`.
+    pub fn id(self) -> u32 {
+        match self {
+            SeqParam::RatioC          => 2,
+            SeqParam::RatioA          => 3,
+            SeqParam::Feedback        => 5,
+            SeqParam::Harm            => 6,
+            SeqParam::CarrierMix      => 7,
+            SeqParam::Overdrive       => 12,
+            SeqParam::Pan             => 13,
+            SeqParam::Volume          => 14,
+            SeqParam::FilterCutoff    => 19,
+            SeqParam::FilterResonance => 20,
+            SeqParam::FilterEnvAmount => 21,
+            SeqParam::Lfo1Speed       => 24,
+            SeqParam::Lfo1Depth       => 25,
+            SeqParam::Lfo2Speed       => 26,
+            SeqParam::Lfo2Depth       => 27,
+            SeqParam::RatioB1         => 32 + 2 * 8 + 1,
+            SeqParam::RatioB2         => 32 + 3 * 8 + 1,
+            SeqParam::ModDepth(cell)  => 64 + cell as u32,
+            SeqParam::Detune          => 87,
+        }
+    }
+}
+
+/// A note played by a step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Trig {
+    /// MIDI note number.
+    pub note: u8,
+    /// MIDI velocity, 0–127.
+    pub velocity: f32,
+    /// Length in steps.
+    pub length: f32,
+    /// Offset from the step, -0.5..0.5 of a step.
+    pub micro: f32,
+}
+
+impl Trig {
+    pub fn new(note: u8, velocity: f32, length: f32, micro: f32) -> Self {
+        Self {
+            note: note.min(127),
+            velocity: velocity.clamp(0.0, 127.0),
+            length: length.clamp(0.01, MAX_LENGTH),
+            micro: micro.clamp(-0.5, 0.49),
+        }
+    }
+
+    pub fn frequency(&self) -> f32 {
+        440.0 * 2.0f32.powf((self.note as f32 - 69.0) / 12.0)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Step {
+    pub trig: Option<Trig>,
+    pub locks: Vec<(SeqParam, f32)>,
+}
+
+impl Step {
+    /// Whether the step changes the locked parameters when it plays.
+    fn is_lock_point(&self) -> bool {
+        self.trig.is_some() || !self.locks.is_empty()
+    }
+
+    pub fn set_lock(&mut self, param: SeqParam, value: f32) {
+        match self.locks.iter_mut().find(|(p, _)| *p == param) {
+            Some(lock) => lock.1 = value,
+            None => self.locks.push((param, value)),
+        }
+    }
+
+    pub fn clear_lock(&mut self, param: SeqParam) {
+        self.locks.retain(|(p, _)| *p != param);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    steps: Vec<Step>, // always MAX_STEPS, the first `len` play
+    len:   usize,
+    rate:  usize,     // sync division index of one step
+}
+
+impl Default for Pattern {
+    /// 16 empty 1/16 steps.
+    fn default() -> Self {
+        Self { steps: vec![Step::default(); MAX_STEPS], len: MIN_STEPS, rate: SIXTEENTH }
+    }
+}
+
+impl Pattern {
+    pub fn set_num_steps(&mut self, len: usize) {
+        self.len = len.clamp(MIN_STEPS, MAX_STEPS);
+    }
+
+    pub fn set_rate(&mut self, rate: usize) {
+        self.rate = rate.min(NUM_DIVISIONS - 1);
+    }
+
+    pub fn num_steps(&self) -> usize { self.len }
+    pub fn rate(&self) -> usize { self.rate }
+
+    /// Step `index` (below `MAX_STEPS`); steps past the pattern length are
+    /// kept but don't play.
+    pub fn step_mut(&mut self, index: usize) -> Option<&mut Step> {
+        self.steps.get_mut(index)
+    }
+
+    pub fn step(&self, index: usize) -> Option<&Step> {
+        self.steps.get(index)
+    }
+
+    /// Pattern as flat data for the patch: `[len, rate]`, then for all
+    /// `MAX_STEPS` steps (those past the length too)
+    /// `[has trig, note, velocity, length, micro, lock count, (param id, value)…]`.
+    pub fn to_flat(&self) -> Vec<f32> {
+        let mut out = vec![self.len as f32, self.rate as f32];
+        for step in &self.steps {
+            let trig = step.trig.unwrap_or(Trig::new(60, 100.0, 1.0, 0.0));
+            out.extend_from_slice(&[
+                step.trig.is_some() as u8 as f32,
+                trig.note as f32,
+                trig.velocity,
+                trig.length,
+                trig.micro,
+                step.locks.len() as f32,
+            ]);
+            for &(param, value) in &step.locks {
+                out.extend_from_slice(&[param.id() as f32, value]);
+            }
+        }
+        out
+    }
+
+    /// Inverse of `to_flat`; truncated data keeps the steps read so far.
+    pub fn from_flat(data: &[f32]) -> Self {
+        let mut pattern = Self::default();
+        let mut it = data.iter().copied();
+        let (Some(len), Some(rate)) = (it.next(), it.next()) else { return pattern };
+        pattern.set_num_steps(len as usize);
+        pattern.set_rate(rate as usize);
+        for step in pattern.steps.iter_mut() {
+            let head: Vec<f32> = it.by_ref().take(6).collect();
+            let [on, note, velocity, length, micro, locks] = head[..] else { break };
+            if on != 0.0 {
+                step.trig = Some(Trig::new(note as u8, velocity, length, micro));
+            }
+            for _ in 0..locks as usize {
+                let (Some(id), Some(value)) = (it.next(), it.next()) else { break };
+                if let Some(param) = SeqParam::from_id(id as u32) {
+                    step.set_lock(param, value);
+                }
+            }
+        }
+        pattern
+    }
+}
+
+/// What the synth has to do for the sequencer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SeqEvent {
+    NoteOn { note_id: u32, freq: f32, velocity: f32 },
+    NoteOff { note_id: u32 },
+    /// Return every locked parameter to its patch value.
+    Unlock,
+    Lock(SeqParam, f32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Sequencer {
+    enabled: bool,
+    pattern: Pattern,
+
+    running:   bool,
+    bpm:       f32,
+    beats:     f64,                 // song position
+    next_note: u64,                 // next step whose trig is due
+    next_lock: u64,                 // next step whose locks are due
+    sounding:  Vec<(u32, f64)>,     // (note id, note-off beat position)
+    events:    Vec<SeqEvent>,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self {
+            enabled:   false,
+            pattern:   Pattern::default(),
+            running:   false,
+            bpm:       120.0,
+            beats:     0.0,
+            next_note: 0,
+            next_lock: 0,
+            sounding:  Vec::new(),
+            events:    Vec::new(),
+        }
+    }
+
+    /// Turning the sequencer off releases its notes and locks (read them
+    /// with `events`).
+    pub fn set_enabled(&mut self, on: bool) {
+        self.events.clear();
+        if !on {
+            self.stop();
+        }
+        self.enabled = on;
+    }
+
+    pub fn is_enabled(&self) -> bool { self.enabled }
+
+    pub fn pattern(&self) -> &Pattern { &self.pattern }
+
+    /// Edit the pattern; takes effect from the next step due.
+    pub fn pattern_mut(&mut self) -> &mut Pattern { &mut self.pattern }
+
+    pub fn set_pattern(&mut self, pattern: Pattern) {
+        self.pattern = pattern;
+        if self.running {
+            self.schedule_from(self.beats);
+        }
+    }
+
+    /// Step length as a sync division index (see `transport::division_beats`).
+    /// A running pattern carries on from the next step of the new grid.
+    pub fn set_rate(&mut self, rate: usize) {
+        self.pattern.set_rate(rate);
+        if self.running {
+            self.schedule_from(self.beats);
+        }
+    }
+
+    /// Events queued by the last `begin_block` or `process` call.
+    pub fn events(&self) -> &[SeqEvent] {
+        &self.events
+    }
+
+    /// Start a control block of `block_dt` seconds: follow the transport and
+    /// queue the locks of steps that start within the block, so a step's
+    /// locks are in place from its first sample. Call before the transport
+    /// advances.
+    pub fn begin_block(&mut self, clock: Clock, block_dt: f32) {
+        self.events.clear();
+        self.bpm = clock.bpm;
+        let Some(position) = clock.position.filter(|_| self.enabled) else {
+            self.stop();
+            return;
+        };
+        if !self.running || (position - self.beats).abs() > self.step_beats() {
+            // Started, or the host jumped: pick up from the new position
+            self.release_notes();
+            self.running = true;
+            self.schedule_from(position);
+        }
+        self.beats = position;
+
+        let block_end = position + block_dt as f64 * self.bpm as f64 / 60.0;
+        while self.step_time(self.next_lock) < block_end {
+            let step = &self.pattern.steps[self.step_index(self.next_lock)];
+            if step.is_lock_point() {
+                self.events.push(SeqEvent::Unlock);
+                self.events.extend(step.locks.iter().map(|&(p, v)| SeqEvent::Lock(p, v)));
+            }
+            self.next_lock += 1;
+        }
+    }
+
+    /// Advance by `dt` seconds, queueing the note-offs and note-ons that
+    /// fall in this sample.
+    pub fn process(&mut self, dt: f32) {
+        self.events.clear();
+        if !self.running {
+            return;
+        }
+        self.beats += dt as f64 * self.bpm as f64 / 60.0;
+        let beats = self.beats;
+        let events = &mut self.events;
+        self.sounding.retain(|&(note_id, off)| {
+            let done = beats >= off;
+            if done {
+                events.push(SeqEvent::NoteOff { note_id });
+            }
+            !done
+        });
+        while self.step_time(self.next_note) <= beats {
+            let index = self.step_index(self.next_note);
+            if let Some(trig) = self.pattern.steps[index].trig {
+                let note_id = SEQ_ID | ((index as u32) << 8) | trig.note as u32;
+                // Retriggering a still-sounding note restarts it
+                self.sounding.retain(|&(id, _)| id != note_id);
+                let off = self.step_time(self.next_note) + trig.length as f64 * self.step_beats();
+                self.sounding.push((note_id, off));
+                self.events.push(SeqEvent::NoteOn { note_id, freq: trig.frequency(), velocity: trig.velocity });
+            }
+            self.next_note += 1;
+        }
+    }
+
+    fn step_beats(&self) -> f64 {
+        division_beats(self.pattern.rate) as f64
+    }
+
+    fn step_index(&self, k: u64) -> usize {
+        (k % self.pattern.len as u64) as usize
+    }
+
+    /// Song position of grid step `k`, including its micro-timing.
+    fn step_time(&self, k: u64) -> f64 {
+        let micro = self.pattern.steps[self.step_index(k)].trig.map_or(0.0, |t| t.micro);
+        (k as f64 + micro as f64) * self.step_beats()
+    }
+
+    /// Aim at the first step (micro-timing included) at or after `beats`.
+    fn schedule_from(&mut self, beats: f64) {
+        let mut k = ((beats / self.step_beats()).floor() as u64).saturating_sub(1);
+        while self.step_time(k) < beats {
+            k += 1;
+        }
+        self.next_note = k;
+        self.next_lock = k;
+    }
+
+    fn release_notes(&mut self) {
+        for (note_id, _) in self.sounding.drain(..) {
+            self.events.push(SeqEvent::NoteOff { note_id });
+        }
+    }
+
+    fn stop(&mut self) {
+        if self.running {
+            self.release_notes();
+            self.events.push(SeqEvent::Unlock);
+            self.running = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+    const DT: f32 = 1.0 / SR;
+    const BLOCK: usize = 16;
+
+    /// Play from the song start for `seconds`, returning (sample, event) pairs.
+    fn run(seq: &mut Sequencer, seconds: f32) -> Vec<(usize, SeqEvent)> {
+        let mut out = Vec::new();
+        let mut position = 0.0f64;
+        for block in 0..(seconds * SR) as usize / BLOCK {
+            seq.begin_block(Clock { bpm: 120.0, position: Some(position) }, DT * BLOCK as f32);
+            out.extend(seq.events().iter().map(|&e| (block * BLOCK, e)));
+            for s in 0..BLOCK {
+                seq.process(DT);
+                out.extend(seq.events().iter().map(|&e| (block * BLOCK + s, e)));
+            }
+            position += (DT * BLOCK as f32) as f64 * 2.0;
+        }
+        out
+    }
+
+    fn sequencer(steps: &[(usize, Trig)]) -> Sequencer {
+        let mut seq = Sequencer::new();
+        seq.set_enabled(true);
+        for &(i, trig) in steps {
+            seq.pattern_mut().step_mut(i).unwrap().trig = Some(trig);
+        }
+        seq
+    }
+
+    #[test]
+    fn trigs_play_with_length_and_micro_timing() {
+        // 1/16 at 120 bpm = 6000 samples
+        let mut seq = sequencer(&[
+            (0, Trig::new(60, 100.0, 0.5, 0.0)),
+            (2, Trig::new(64, 100.0, 1.0, 0.25)),
+        ]);
+        let notes: Vec<(usize, SeqEvent)> = run(&mut seq, 0.45).into_iter()
+            .filter(|(_, e)| matches!(e, SeqEvent::NoteOn { .. } | SeqEvent::NoteOff { .. }))
+            .collect();
+        let times: Vec<usize> = notes.iter().map(|(s, _)| *s).collect();
+        assert!(matches!(notes[0].1, SeqEvent::NoteOn { freq, .. } if (freq - 261.63).abs() < 0.01));
+        // micro-timing shifts step 2 by a quarter step; 1 sample of f64 slack
+        assert_eq!(times[..2], [0, 3000]);
+        assert!((times[2] as i64 - 13500).abs() <= 1 && (times[3] as i64 - 19500).abs() <= 1, "{:?}", times);
+    }
+
+    #[test]
+    fn locks_hold_until_the_next_lock_point() {
+        let mut seq = sequencer(&[(0, Trig::new(60, 100.0, 1.0, 0.0)), (4, Trig::new(60, 100.0, 1.0, 0.0))]);
+        seq.pattern_mut().step_mut(0).unwrap().set_lock(SeqParam::FilterCutoff, 500.0);
+        seq.pattern_mut().step_mut(2).unwrap().set_lock(SeqParam::ModDepth(4), 90.0);
+        let locks: Vec<SeqEvent> = run(&mut seq, 0.55).into_iter()
+            .map(|(_, e)| e)
+            .filter(|e| matches!(e, SeqEvent::Lock(..) | SeqEvent::Unlock))
+            .collect();
+        assert_eq!(locks, vec![
+            SeqEvent::Unlock, SeqEvent::Lock(SeqParam::FilterCutoff, 500.0),
+            SeqEvent::Unlock, SeqEvent::Lock(SeqParam::ModDepth(4), 90.0),
+            SeqEvent::Unlock, // step 4 has a trig but no locks
+        ]);
+    }
+
+    #[test]
+    fn rate_change_mid_pattern_moves_to_the_new_grid() {
+        let mut seq = sequencer(&(0..16).map(|i| (i, Trig::new(60, 100.0, 0.5, 0.0))).collect::<Vec<_>>());
+        let mut note_ons = Vec::new();
+        let mut position = 0.0f64;
+        for block in 0..(0.55 * SR) as usize / BLOCK {
+            if block * BLOCK >= 9000 {
+                seq.set_rate(SIXTEENTH - 3); // 1/8 from a step and a half in
+            }
+            seq.begin_block(Clock { bpm: 120.0, position: Some(position) }, DT * BLOCK as f32);
+            for s in 0..BLOCK {
+                seq.process(DT);
+                if seq.events().iter().any(|e| matches!(e, SeqEvent::NoteOn { .. })) {
+                    note_ons.push(block * BLOCK + s);
+                }
+            }
+            position += (DT * BLOCK as f32) as f64 * 2.0;
+        }
+        // 1/16 = 6000 samples, 1/8 = 12000; the first 1/8 step after the
+        // change is at 12000, not at step 2 of the new grid (24000)
+        let expected = [0, 6000, 12000, 24000];
+        assert_eq!(note_ons.len(), expected.len(), "{:?}", note_ons);
+        for (&t, e) in note_ons.iter().zip(expected) {
+            assert!((t as i64 - e).abs() <= 1, "{:?}", note_ons);
+        }
+    }
+
+    #[test]
+    fn stops_with_the_transport() {
+        let mut seq = sequencer(&[(0, Trig::new(60, 100.0, 8.0, 0.0))]);
+        run(&mut seq, 0.1);
+        seq.begin_block(Clock { bpm: 120.0, position: None }, DT * BLOCK as f32);
+        assert_eq!(seq.events()[0], SeqEvent::NoteOff { note_id: SEQ_ID | 60 });
+        assert_eq!(seq.events()[1], SeqEvent::Unlock);
+    }
+
+    #[test]
+    fn lock_ids_are_modulation_destination_ids() {
+        for id in 0..128 {
+            if let Some(param) = SeqParam::from_id(id) {
+                assert_eq!(param.id(), id);
+            }
+        }
+        assert_eq!(SeqParam::from_id(19), Some(SeqParam::FilterCutoff));
+        assert_eq!(SeqParam::from_id(64 + 4), Some(SeqParam::ModDepth(4)));
+        assert_eq!(SeqParam::from_id(23), None); // pitch has no patch value
+        let depth_b: Vec<_> = SeqParam::targets(1).collect();
+        assert_eq!(depth_b, (8..16).map(SeqParam::ModDepth).collect::<Vec<_>>());
+        assert_eq!(SeqParam::targets(14).collect::<Vec<_>>(), [SeqParam::Volume]);
+    }
+
+    #[test]
+    fn pattern_round_trips_through_flat_data() {
+        let mut pattern = Pattern::default();
+        pattern.set_num_steps(32);
+        pattern.set_rate(SIXTEENTH + 3);
+        pattern.step_mut(3).unwrap().trig = Some(Trig::new(67, 80.0, 2.5, -0.1));
+        pattern.step_mut(3).unwrap().set_lock(SeqParam::RatioA, 3.5);
+        pattern.step_mut(9).unwrap().set_lock(SeqParam::Lfo1Depth, 0.25);
+        // Beyond the length: kept for when the pattern grows again
+        pattern.step_mut(40).unwrap().trig = Some(Trig::new(48, 90.0, 1.0, 0.0));
+        pattern.step_mut(63).unwrap().set_lock(SeqParam::Detune, 20.0);
+        let restored = Pattern::from_flat(&pattern.to_flat());
+        assert_eq!(restored, pattern);
+        assert_eq!(Pattern::from_flat(&[]), Pattern::default());
+    }
+}
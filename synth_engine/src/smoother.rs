@@ -0,0 +1,139 @@
+// src/smoother.rs
+//! Linear parameter smoothing.
+//!
+//! Setters and control-rate modulation hand the engine stepped values; a
+//! `Smoother` turns each step into a short linear ramp so cutoff, volume,
+//! operator level etc. move without zipper noise or clicks.
+//!
+//! Modulation offsets use a separate `block_ramp` smoother, which reaches
+//! each control block's value by the block's end. Keeping them apart from the
+//! user-value smoothers means a fast LFO is interpolated, not lag-filtered.
+
+use crate::synth::CONTROL_BLOCK;
+
+/// Ramps from its current value to the latest target over `ramp_time` seconds.
+#[derive(Copy, Clone, Debug)]
+pub struct Smoother {
+    current: f32,
+    target: f32,
+    step: f32,
+    remaining: u32,
+    ramp_time: f32,
+}
+
+impl Smoother {
+    /// Start settled at `value`, ramping over `ramp_time` seconds on changes.
+    pub fn new(value: f32, ramp_time: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+            ramp_time: ramp_time.max(0.0),
+        }
+    }
+
+    /// Ramp over one control block at `sample_rate`, for modulation offsets
+    /// that change once per block.
+    pub fn block_ramp(value: f32, sample_rate: f32) -> Self {
+        Self::new(value, CONTROL_BLOCK as f32 / sample_rate)
+    }
+
+    /// Ramp length in seconds (0 = follow targets instantly).
+    pub fn set_ramp_time(&mut self, seconds: f32) {
+        self.ramp_time = seconds.max(0.0);
+    }
+
+    pub fn ramp_time(&self) -> f32 {
+        self.ramp_time
+    }
+
+    /// Advance one sample of `dt` seconds towards `target` and return the
+    /// smoothed value. A new target restarts the ramp from the current value.
+    #[inline]
+    pub fn next(&mut self, target: f32, dt: f32) -> f32 {
+        if target != self.target {
+            self.target = target;
+            let samples = (self.ramp_time / dt).round() as u32;
+            if samples == 0 {
+                self.remaining = 0;
+                self.current = target;
+            } else {
+                self.remaining = samples;
+                self.step = (target - self.current) / samples as f32;
+            }
+        }
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.current = if self.remaining == 0 { self.target } else { self.current + self.step };
+        }
+        self.current
+    }
+
+    /// Jump straight to `value` (e.g. when a voice starts a new note).
+    pub fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.remaining = 0;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.current
+    }
+
+    pub fn is_smoothing(&self) -> bool {
+        self.remaining > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 1000.0;
+
+    #[test]
+    fn ramps_linearly_and_lands_on_target() {
+        let mut s = Smoother::new(0.0, 0.01); // 10 samples
+        let first = s.next(1.0, DT);
+        assert!((first - 0.1).abs() < 1e-6);
+        for _ in 0..8 {
+            s.next(1.0, DT);
+        }
+        assert!(s.is_smoothing());
+        assert_eq!(s.next(1.0, DT), 1.0);
+        assert!(!s.is_smoothing());
+    }
+
+    #[test]
+    fn retarget_continues_from_current_value() {
+        let mut s = Smoother::new(0.0, 0.01);
+        for _ in 0..5 {
+            s.next(1.0, DT);
+        }
+        let mid = s.value();
+        let after = s.next(0.0, DT);
+        assert!(after < mid && after > 0.0, "jumped from {} to {}", mid, after);
+    }
+
+    #[test]
+    fn block_ramp_lands_at_the_block_end_at_any_rate() {
+        for factor in [1, 4] {
+            let dt = 1.0 / (48_000.0 * factor as f32);
+            let mut s = Smoother::block_ramp(0.0, 48_000.0);
+            let ramp: Vec<f32> = (0..CONTROL_BLOCK * factor).map(|_| s.next(1.0, dt)).collect();
+            assert!(ramp[ramp.len() - 2] < 1.0, "{}x finished early", factor);
+            assert_eq!(ramp[ramp.len() - 1], 1.0);
+        }
+    }
+
+    #[test]
+    fn zero_ramp_and_reset_are_instant() {
+        let mut s = Smoother::new(0.0, 0.0);
+        assert_eq!(s.next(5.0, DT), 5.0);
+        s.set_ramp_time(1.0);
+        s.next(0.0, DT);
+        s.reset(3.0);
+        assert_eq!(s.next(3.0, DT), 3.0);
+    }
+}
@@ -1,21 +1,58 @@
 use wasm_bindgen::prelude::*;
 use js_sys::Float32Array;
+#[cfg(target_arch = "wasm32")]
 use web_sys::console;
 
 use serde::Serialize;
 use wasm_bindgen::JsValue;
 
 
-use crate::envelope::Envelope;
+use crate::envelope::{Envelope, LoopMode, RetriggerMode};
 use crate::envelope_trait::EnvelopeTrait;
 use crate::algorithm::{FMAlgorithm, get_algorithms};
 use crate::voice::FMVoice;
 use crate::filter::{Filter, FilterType};
 use crate::effects::Effects;
-use crate::lfo::{Lfo, LfoDestination, Waveform, LfoMode};
-
-const BLOCK: usize = 128;
+use crate::effects::chain::EffectSlot;
+use crate::lfo::{Lfo, Waveform, LfoMode};
+use crate::lfo_shape::LfoCurve;
+use crate::portamento::{GlideType, PortamentoMode};
+use crate::arpeggiator::{ArpEvent, ArpMode, Arpeggiator};
+use crate::chord::{ChordEvent, ChordMemory, ChordType, Scale, Voicing};
+use crate::sequencer::{Pattern, SeqEvent, SeqParam, Sequencer, Trig};
+use crate::mod_matrix::{ModDestination, ModMatrix, ModSlot, ModSource, ModSourceValues};
+use crate::wavetable::Wavetable;
+use crate::oversampler::{Decimator, Oversampling};
+use crate::rng::mix_seed;
+use crate::smoother::Smoother;
+use crate::transport::Transport;
+use crate::zone::Zone;
+use std::f32::consts::FRAC_PI_4;
+use std::sync::Arc;
+
+/// Samples per channel rendered by each `process_sample_array` call.
+pub const BLOCK: usize = 128;
+/// Frames between LFO / mod-matrix updates; parameters are smoothed in between.
+pub const CONTROL_BLOCK: usize = 16;
 const NUM_VOICES: usize = 8;
+/// Most patches one instrument can stack on its own (see `Synth::add_layer`).
+pub const MAX_LAYERS: usize = 4;
+
+/// Equal-power pan law: left and right gains for a pan of -63 (left) to
+/// 63 (right). Shared by the amp section and the multi-timbral mixer.
+pub(crate) fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = ((pan / 63.0).clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4; // maps [-1..+1] → [0..π/2]
+    (angle.cos(), angle.sin())
+}
+
+/// Log to the browser console. No-op on native builds (tests, offline renders),
+/// where wasm-bindgen imports are unavailable.
+fn log(msg: &str) {
+    #[cfg(target_arch = "wasm32")]
+    console::log_1(&msg.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = msg;
+}
 
 
 #[derive(Serialize)]
@@ -100,6 +137,63 @@ struct DebugInfo {
 
 
 
+/// A user value smoothed over its ramp time, plus the modulation offset on
+/// top of it ramped across each control block.
+struct ParamSmoother {
+    value: Smoother,
+    offset: Smoother,
+}
+
+impl ParamSmoother {
+    fn new(value: f32, ramp_time: f32, sample_rate: f32) -> Self {
+        Self { value: Smoother::new(value, ramp_time), offset: Smoother::block_ramp(0.0, sample_rate) }
+    }
+
+    /// Advance one sample towards `base` modulated to `modulated`.
+    #[inline]
+    fn next(&mut self, base: f32, modulated: f32, dt: f32) -> f32 {
+        self.value.next(base, dt) + self.offset.next(modulated - base, dt)
+    }
+}
+
+/// Smoothers for the synth-level parameters that are read per sample.
+struct Smoothers {
+    volume: ParamSmoother,
+    pan: ParamSmoother,
+    cutoff: ParamSmoother,
+    resonance: ParamSmoother,
+    overdrive: ParamSmoother,
+    carrier_mix: ParamSmoother,
+    op_level_ramp: f32, // Operator smoothers live in each voice; these are their times
+    op_ratio_ramp: f32,
+}
+
+impl Smoothers {
+    /// Default ramp for every parameter, in seconds.
+    const DEFAULT_RAMP: f32 = 0.005;
+
+    fn new(sample_rate: f32) -> Self {
+        let ramp = |v| ParamSmoother::new(v, Self::DEFAULT_RAMP, sample_rate);
+        Self {
+            volume: ramp(127.0),
+            pan: ramp(0.0),
+            cutoff: ramp(20000.0),
+            resonance: ramp(0.1),
+            overdrive: ramp(0.0),
+            carrier_mix: ramp(1.0),
+            op_level_ramp: Self::DEFAULT_RAMP,
+            op_ratio_ramp: 0.002,
+        }
+    }
+}
+
+/// Another patch in the same instrument, with its own voice pool.
+struct Layer {
+    synth: Option<Synth>, // None while JS holds it for editing
+    level: f32,           // 0–127
+    level_smoother: Smoother,
+}
+
 #[wasm_bindgen]
 pub struct Synth {
     voices: Vec<FMVoice>,
@@ -123,23 +217,55 @@ pub struct Synth {
     harm: f32,
     lfo1: Lfo,
     lfo2: Lfo,
-    chorus_enabled: bool,
-    delay_enabled:  bool,
-    reverb_enabled: bool,
+    lfo_per_voice: [bool; 2],  // LFO1/LFO2 run per voice instead of globally
+    last_voice: usize,         // Most recently triggered voice
 
     // Stereo filter pair (separate state for L and R)
     filter_l: Filter,
     filter_r: Filter,
+    filter_cutoff: f32,     // User cutoff (Hz); the filters get the smoothed value
+    filter_resonance: f32,  // User resonance (Q)
+    filter_env_curve_mod: f32, // This block's FilterEnvCurve modulation
 
     // Amp section parameters
     overdrive: f32,
     pan: f32,
     volume: f32,
     portamento_time: f32,
-    last_note_frequency: f32,  // For portamento continuity across voices
+    last_note_frequency: f32,  // For portamento continuity across voices (0 = no note yet)
     pitch_bend_range: f32,     // Pitch bend range in semitones (0-24)
     pitch_bend_value: f32,     // Current pitch bend (-1.0 to +1.0, where 0 = no bend)
     effects: Effects,
+
+    // Oversampled FM core + overdrive, decimated back to the host rate
+    oversampling: Oversampling,
+    decimator_l: Decimator,
+    decimator_r: Decimator,
+
+    // Engine seed driving every random source (noise, random LFOs)
+    seed: u32,
+
+    // Host tempo and song position (drives synced LFOs, arpeggiator and sequencer)
+    transport: Transport,
+    chord: ChordMemory,
+    arp: Arpeggiator,
+    seq: Sequencer,
+    seq_locks: Vec<(SeqParam, f32)>,  // p-locked values, laid over the patch each block
+    seq_saved: Vec<(SeqParam, f32)>,  // patch values under the locks while a block renders
+
+    // Per-sample ramps for stepped synth-level parameters
+    smoothers: Smoothers,
+
+    // Modulation matrix and its performance-controller sources (0..1)
+    mod_matrix: ModMatrix,
+    mod_wheel: f32,
+    aftertouch: f32,
+
+    // Splits and layers: the keys this patch's own voices play, and the
+    // other patches stacked with it
+    zone: Zone,
+    layers: Vec<Layer>,
+    is_layer: bool, // stacked in another instrument; can't hold layers itself
 }
 
 #[wasm_bindgen]
@@ -147,7 +273,18 @@ impl Synth {
     /// Initialize NUM_VOICES FM voices using the first algorithm by default
     #[wasm_bindgen(constructor)]
     pub fn new(sample_rate: f32) -> Synth {
-        console::log_1(&format!("🔊 4-Op FM Synth @ {} Hz", sample_rate).into());
+        let mut synth = Self::new_dry(sample_rate);
+        synth.effects.allocate();
+        synth
+    }
+
+    /// A synth whose effects hold their settings but no delay lines, for
+    /// multi-timbral parts, kit pads and layers, which render dry into a
+    /// shared bus or their host's mix. Played on its own, it sizes the
+    /// delay lines on its first `render_block`.
+    #[wasm_bindgen]
+    pub fn new_dry(sample_rate: f32) -> Synth {
+        log(&format!("🔊 4-Op FM Synth @ {} Hz", sample_rate));
 
         // load all algorithms, pick the first as default
         let algorithms = get_algorithms();
@@ -169,11 +306,11 @@ impl Synth {
         let filter_l = make_filter();
         let filter_r = make_filter();
 
-        let effects = Effects::new(sample_rate);
+        let effects = Effects::new_dry(sample_rate);
         let lfo1 = Lfo::new(sample_rate);
         let lfo2 = Lfo::new(sample_rate);
 
-        Synth {
+        let mut synth = Synth {
             voices,
             algorithms,
             octave_shift: 0,
@@ -192,22 +329,65 @@ impl Synth {
             detune: 0.0,
             filter_l,
             filter_r,
+            filter_cutoff: 20000.0,
+            filter_resonance: 0.1,
+            filter_env_curve_mod: 0.0,
             overdrive: 0.0,
             pan: 0.0,
             volume: 127.0,
             portamento_time: 0.0,
-            last_note_frequency: 440.0,
+            last_note_frequency: 0.0,
             pitch_bend_range: 2.0,  // Default 2 semitones (standard)
             pitch_bend_value: 0.0,   // No bend initially
             effects,
             lfo1,
             lfo2,
-            chorus_enabled: true,
-            delay_enabled:  true,
-            reverb_enabled: true,
+            lfo_per_voice: [false; 2],
+            last_voice: 0,
+            oversampling: Oversampling::Off,
+            decimator_l: Decimator::new(),
+            decimator_r: Decimator::new(),
+            seed: 0,
+            transport: Transport::new(),
+            chord: ChordMemory::new(),
+            arp: Arpeggiator::new(),
+            seq: Sequencer::new(),
+            seq_locks: Vec::new(),
+            seq_saved: Vec::new(),
+            smoothers: Smoothers::new(sample_rate),
+            mod_matrix: ModMatrix::new(),
+            mod_wheel: 0.0,
+            aftertouch: 0.0,
+            zone: Zone::default(),
+            layers: Vec::with_capacity(MAX_LAYERS),
+            is_layer: false,
+        };
+        synth.set_seed(0);
+        synth
+    }
+
+    /// Seed every random source in the engine (operator noise, random LFOs).
+    /// The same seed, patch and event sequence render bit-identical audio.
+    /// Also restarts the per-voice note counters, so call it before a render.
+    #[wasm_bindgen]
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        for (i, v) in self.voices.iter_mut().enumerate() {
+            v.set_seed(mix_seed(seed, i as u32));
+        }
+        self.lfo1.set_seed(mix_seed(seed, 0x4c46_4f31)); // "LFO1"
+        self.lfo2.set_seed(mix_seed(seed, 0x4c46_4f32)); // "LFO2"
+        self.arp.set_seed(mix_seed(seed, 0x4152_5030));  // "ARP0"
+        for (i, synth) in self.layer_synths().enumerate() {
+            synth.set_seed(mix_seed(seed, 0x4c59_5230 + i as u32)); // "LYR0" + layer
         }
     }
 
+    #[wasm_bindgen]
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
      // ——— Debug ———
 
      #[wasm_bindgen]
@@ -279,8 +459,8 @@ impl Synth {
              harm:                self.harm,
      
              // — Filter —
-             filter_cutoff:       self.filter_l.cutoff(),
-             filter_resonance:    self.filter_l.resonance(),
+             filter_cutoff:       self.filter_cutoff,
+             filter_resonance:    self.filter_resonance,
      
              // — Amp Section —
              overdrive:           self.overdrive,
@@ -312,7 +492,7 @@ impl Synth {
              lfo1_fade:           self.lfo1.fade() as f32,
              lfo1_destination:    lfo1_dest,
              lfo1_waveform:       lfo1_wave,
-             lfo1_mode:           lfo1_mode,
+             lfo1_mode,
              lfo1_depth:          self.lfo1.depth(),
      
              // — LFO 2 —
@@ -322,7 +502,7 @@ impl Synth {
              lfo2_fade:           self.lfo2.fade() as f32,
              lfo2_destination:    lfo2_dest,
              lfo2_waveform:       lfo2_wave,
-             lfo2_mode:           lfo2_mode,
+             lfo2_mode,
              lfo2_depth:          self.lfo2.depth(),
      
              // — Final Samples —
@@ -330,6 +510,7 @@ impl Synth {
              last_sample_r:       last_r,
          };
      
+         #[allow(deprecated)]
          JsValue::from_serde(&info).unwrap()
      }
      
@@ -346,8 +527,8 @@ impl Synth {
     /// Each value is 0–127; unconnected pairs should be 0.
     #[wasm_bindgen]
     pub fn set_mod_depth_matrix(&mut self, data: &[f32]) {
-        for i in 0..16.min(data.len()) {
-            self.mod_depth_matrix[i] = data[i].clamp(0.0, 127.0);
+        for (cell, &v) in self.mod_depth_matrix.iter_mut().zip(data) {
+            *cell = v.clamp(0.0, 127.0);
         }
     }
 
@@ -369,6 +550,122 @@ pub fn set_octave(&mut self, shift: i32) {
         }
     }
 
+    /// Per-voice pitch envelope: levels L1–L4 in semitones (±48) and segment
+    /// times T1–T4 in seconds (0 = jump, Infinity = never arrive). Rests at L4,
+    /// moves through L1 and L2 to L3 on note-on, holds L3, returns to L4 on
+    /// note-off. E.g. L1 +24 / T1 0 then L2 0 / T2 0.03 for a kick drop.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn set_pitch_env(&mut self,
+        l1: f32, l2: f32, l3: f32, l4: f32,
+        t1: f32, t2: f32, t3: f32, t4: f32,
+    ) {
+        for v in &mut self.voices {
+            v.set_pitch_env([l1, l2, l3, l4], [t1, t2, t3, t4]);
+        }
+    }
+
+    /// How much of the pitch envelope an operator (0-3) follows, -1.0..1.0.
+    /// All operators default to 1.0; zero the modulators for carrier-only swoops.
+    #[wasm_bindgen]
+    pub fn set_operator_pitch_env_amount(&mut self, op_index: usize, amount: f32) {
+        if op_index >= 4 { return; }
+        for v in &mut self.voices {
+            v.set_operator_pitch_env_amount(op_index, amount);
+        }
+    }
+
+    /// Replace an operator's (0-3) envelope with a DX-style rate/level
+    /// generator: rates R1–R4 and levels L1–L4, all 0–99. Moves through L1,
+    /// L2 to L3, holds L3 while the key is down, then goes to L4 at R4. The
+    /// amp envelope still gates the voice; carriers that all finish at L4 = 0
+    /// end it early. Further calls update the envelopes in place, so sounding
+    /// notes carry on from where they are.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn set_operator_dx_env(&mut self, op_index: usize,
+        r1: u8, r2: u8, r3: u8, r4: u8,
+        l1: u8, l2: u8, l3: u8, l4: u8,
+    ) {
+        if op_index >= 4 { return; }
+        for v in &mut self.voices {
+            v.set_operator_dx_env(op_index, [r1, r2, r3, r4], [l1, l2, l3, l4]);
+        }
+    }
+
+    /// Return an operator to its default envelope (undoes `set_operator_dx_env`).
+    #[wasm_bindgen]
+    pub fn clear_operator_dx_env(&mut self, op_index: usize) {
+        if op_index >= 4 { return; }
+        for v in &mut self.voices {
+            v.clear_operator_env(op_index);
+        }
+    }
+
+    /// Shape an operator's mod envelope stages: -1.0 logarithmic, 0.0 linear,
+    /// 1.0 exponential (fast-falling decays for plucky FM index sweeps).
+    #[wasm_bindgen]
+    pub fn set_operator_mod_env_curves(&mut self, op_index: usize, attack: f32, decay: f32, release: f32) {
+        if op_index >= 4 { return; }
+        for v in &mut self.voices {
+            v.set_operator_mod_env_curves(op_index, attack, decay, release);
+        }
+    }
+
+    /// Release time (0-127 knob, 0 = drop to zero at note-off) of an
+    /// operator's mod envelope, so FM depth decays after the key is released.
+    #[wasm_bindgen]
+    pub fn set_operator_mod_env_release(&mut self, op_index: usize, release: u32) {
+        if op_index >= 4 { return; }
+        for v in &mut self.voices {
+            v.set_operator_mod_env_release(op_index, release);
+        }
+    }
+
+    /// What a new note does to an operator's running mod envelope:
+    /// 0 reset to zero, 1 restart from the current level, 2 legato (no
+    /// retrigger while a note is held).
+    #[wasm_bindgen]
+    pub fn set_operator_mod_env_retrigger(&mut self, op_index: usize, mode: u32) {
+        if op_index >= 4 { return; }
+        let mode = RetriggerMode::from_id(mode);
+        for v in &mut self.voices {
+            v.set_operator_mod_env_retrigger(op_index, mode);
+        }
+    }
+
+    /// Loop an operator's mod envelope, making it a per-voice, note-synced
+    /// modulator for FM depth: mode 0 off, 1 attack–decay while held,
+    /// 2 stages `first`..=`last` while held (0 attack, 1 decay, 2 release;
+    /// `first == last` is off), 3 free-running attack–decay–release cycle
+    /// that ignores note-off.
+    #[wasm_bindgen]
+    pub fn set_operator_mod_env_loop(&mut self, op_index: usize, mode: u32, first: u32, last: u32) {
+        if op_index >= 4 { return; }
+        let mode = LoopMode::from_ids(mode, first, last);
+        for v in &mut self.voices {
+            v.set_operator_mod_env_loop(op_index, mode);
+        }
+    }
+
+    /// Amp envelope retrigger mode (see `set_operator_mod_env_retrigger`).
+    #[wasm_bindgen]
+    pub fn set_amp_env_retrigger(&mut self, mode: u32) {
+        let mode = RetriggerMode::from_id(mode);
+        for v in &mut self.voices {
+            v.set_amp_retrigger(mode);
+        }
+    }
+
+    /// Shape the amp envelope stages (-1.0 log .. 0.0 linear .. 1.0 exp).
+    /// Stage times are unchanged; release time is measured from full level.
+    #[wasm_bindgen]
+    pub fn set_amp_env_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        for v in &mut self.voices {
+            v.set_amp_curves(attack, decay, release);
+        }
+    }
+
     // in Synth's #[wasm_bindgen] impl
 #[wasm_bindgen]
 pub fn set_amp_env(&mut self,
@@ -393,6 +690,7 @@ pub fn set_amp_env(&mut self,
 
     #[wasm_bindgen]
     pub fn set_detune(&mut self, value: f32) {
+        self.detune = value;
         for v in &mut self.voices { v.apply_detune(value); }
     }
 
@@ -473,12 +771,12 @@ pub fn set_amp_env(&mut self,
         let mod_str: Vec<String> = modulations.iter().map(|(s,d)| format!("{}→{}", s, d)).collect();
         let carrier_str: Vec<String> = carriers.iter().map(|c| c.to_string()).collect();
         let out_str: Vec<String> = algo.output_routing.iter().map(|(op,ch)| format!("op{}→{}", op, ch)).collect();
-        console::log_1(&format!(
+        log(&format!(
             "[engine] set_custom_routing — mods: [{}], carriers: [{}], output: [{}]",
             mod_str.join(", "),
             carrier_str.join(", "),
             out_str.join(", "),
-        ).into());
+        ));
 
         for v in &mut self.voices { v.set_algorithm(algo.clone()); }
     }
@@ -486,54 +784,149 @@ pub fn set_amp_env(&mut self,
 
     // ——— Note handling ———
 
+    /// Start a note at full velocity.
     #[wasm_bindgen]
     pub fn note_on(&mut self, note_id: u32, freq: f32) {
-        // 1) If this note_id is already playing, reuse that voice
-        let idx = if let Some(i) = self.voices.iter().position(|v| v.get_note_id() == Some(note_id)) {
-            i
-        // 2) Find a free (inactive) voice
-        } else if let Some(i) = self.voices.iter().position(|v| !v.is_active()) {
-            i
-        // 3) Prefer stealing a releasing voice (active but no note_id)
-        } else if let Some(i) = self.voices.iter().position(|v| !v.is_held()) {
-            i
-        // 4) All voices held — steal the oldest held voice
+        self.note_on_velocity(note_id, freq, 127.0);
+    }
+
+    /// Start a note with a MIDI velocity (0-127), used by the mod matrix.
+    /// Notes pass through chord memory and the arpeggiator when they are
+    /// on; every note that comes out (and every sequencer note) goes to
+    /// each layer whose zone covers it, and to this patch's own voices when
+    /// it is in their zone.
+    #[wasm_bindgen]
+    pub fn note_on_velocity(&mut self, note_id: u32, freq: f32, velocity: f32) {
+        if self.chord.is_enabled() {
+            self.chord.note_on(note_id, freq, velocity);
+            self.run_chord_events();
         } else {
-            self.voices.iter().enumerate()
-                .min_by_key(|(_, v)| v.get_note_id().unwrap_or(u32::MAX))
-                .map(|(i, _)| i)
-                .unwrap_or(0)
-        };
+            self.play_note(note_id, freq, velocity);
+        }
+    }
 
-        // Check if any voices are active BEFORE triggering new note (for legato detection)
-        let any_active = self.voices.iter().any(|v| v.is_active());
+    #[wasm_bindgen]
+    pub fn note_off(&mut self, note_id: u32) {
+        if self.chord.is_enabled() {
+            self.chord.note_off(note_id);
+            self.run_chord_events();
+        }
+        self.stop_note(note_id);
+    }
 
-        // Pass last note frequency and active state for portamento continuity
-        let pitch_mul = 2_f32.powi(self.octave_shift);
-        let adjusted_freq = freq * pitch_mul;
-        self.voices[idx].note_on(note_id, freq, self.last_note_frequency, any_active);
-        self.last_note_frequency = adjusted_freq;  // Track for next note
+    /// Cut every sounding voice off within a few milliseconds, ignoring
+    /// the release time (drum choke groups).
+    #[wasm_bindgen]
+    pub fn choke(&mut self) {
+        for synth in self.layer_synths() {
+            synth.choke();
+        }
+        for voice in &mut self.voices {
+            voice.choke();
+        }
+        self.filter_l.note_off();
+        self.filter_r.note_off();
+        self.lfo1.note_off();
+        self.lfo2.note_off();
+    }
 
-        if !any_active {
-            self.filter_l.note_on();
-            self.filter_r.note_on();
+    /// Number of voices holding a note, layers included.
+    #[wasm_bindgen]
+    pub fn held_notes(&self) -> usize {
+        let layers: usize = self.layers.iter().filter_map(|l| l.synth.as_ref()).map(|s| s.held_notes()).sum();
+        layers + self.voices.iter().filter(|v| v.is_held()).count()
+    }
+
+    // ——— Splits and layers ———
+
+    /// Keys (MIDI notes, inclusive) this patch's own voices play. Give the
+    /// layers other ranges to split the keyboard, or overlapping ones to
+    /// stack patches.
+    #[wasm_bindgen]
+    pub fn set_key_range(&mut self, low: u8, high: u8) {
+        self.zone.set_keys(low, high);
+    }
+
+    /// Velocities (0-127, inclusive) this patch's own voices play.
+    #[wasm_bindgen]
+    pub fn set_velocity_range(&mut self, low: u8, high: u8) {
+        self.zone.set_velocities(low, high);
+    }
+
+    /// Transpose the notes this patch's own voices play, -48 to 48 semitones.
+    #[wasm_bindgen]
+    pub fn set_transpose(&mut self, semitones: f32) {
+        self.zone.set_transpose(semitones);
+    }
+
+    /// Stack `synth` on this instrument as a layer with its own voices. Its
+    /// key range, velocity range and transpose pick the notes it plays, from
+    /// those this patch's chord memory, arpeggiator and sequencer put out.
+    /// It renders without its own effects, so its delay lines are dropped
+    /// (build it with `new_dry` to skip them), and joins this patch's amp
+    /// and effects chain. Layers don't nest: any layers `synth` holds are
+    /// dropped. Returns the layer's index, or -1 when `MAX_LAYERS` are in
+    /// use or this synth is itself a layer.
+    ///
+    /// A layer shares its host's channel, pan, volume and insert effects;
+    /// for patches with their own channel, mixer strip and bus sends, use
+    /// `MultiSynth` parts instead.
+    #[wasm_bindgen]
+    pub fn add_layer(&mut self, mut synth: Synth) -> i32 {
+        if self.layers.len() == MAX_LAYERS || self.is_layer {
+            return -1;
         }
+        synth.make_layer();
+        self.layers.push(Layer {
+            synth: Some(synth),
+            level: 127.0,
+            level_smoother: Smoother::new(127.0, Smoothers::DEFAULT_RAMP),
+        });
+        self.layers.len() as i32 - 1
     }
 
+    /// Remove a layer, moving the later ones down one index.
     #[wasm_bindgen]
-    pub fn note_off(&mut self, note_id: u32) {
-        // Find the voice playing this note_id
-        if let Some(i) = self.voices.iter().position(|v| v.get_note_id() == Some(note_id)) {
-            self.voices[i].note_off(note_id);
+    pub fn remove_layer(&mut self, layer: usize) -> Option<Synth> {
+        if layer < self.layers.len() {
+            self.layers.remove(layer).synth.map(|mut synth| {
+                synth.is_layer = false;
+                synth
+            })
+        } else {
+            None
         }
-        // Only release filter envelope when no voices are still held
-        let any_held = self.voices.iter().any(|v| v.is_held());
-        if !any_held {
-            self.filter_l.note_off();
-            self.filter_r.note_off();
+    }
+
+    /// Take a layer's synth out for editing; the layer is silent until it
+    /// comes back through `set_layer`.
+    #[wasm_bindgen]
+    pub fn take_layer(&mut self, layer: usize) -> Option<Synth> {
+        self.layers.get_mut(layer).and_then(|l| l.synth.take())
+    }
+
+    /// Put an edited synth back into `layer` (dropping any layers it holds).
+    #[wasm_bindgen]
+    pub fn set_layer(&mut self, layer: usize, mut synth: Synth) {
+        if let Some(l) = self.layers.get_mut(layer) {
+            synth.make_layer();
+            l.synth = Some(synth);
+        }
+    }
+
+    /// Level of a layer in the instrument's mix, 0-127.
+    #[wasm_bindgen]
+    pub fn set_layer_level(&mut self, layer: usize, level: f32) {
+        if let Some(l) = self.layers.get_mut(layer) {
+            l.level = level.clamp(0.0, 127.0);
         }
     }
 
+    #[wasm_bindgen]
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
     // ——— Carrier mix ———
     #[wasm_bindgen]
     pub fn set_carrier_mix(&mut self, mix: f32) {
@@ -551,10 +944,10 @@ pub fn set_amp_env(&mut self,
     }
 
     #[wasm_bindgen]
-    pub fn set_filter_cutoff(&mut self, hz: f32) { self.filter_l.set_cutoff(hz); self.filter_r.set_cutoff(hz); }
+    pub fn set_filter_cutoff(&mut self, hz: f32) { self.filter_cutoff = hz; }
 
     #[wasm_bindgen]
-    pub fn set_filter_resonance(&mut self, q: f32) { self.filter_l.set_resonance(q); self.filter_r.set_resonance(q); }
+    pub fn set_filter_resonance(&mut self, q: f32) { self.filter_resonance = q; }
 
     #[wasm_bindgen]
     pub fn set_filter_attack(&mut self, v: f32) { self.filter_l.set_attack(v); self.filter_r.set_attack(v); }
@@ -566,6 +959,28 @@ pub fn set_amp_env(&mut self,
     pub fn set_filter_release(&mut self, v: f32) { self.filter_l.set_release(v); self.filter_r.set_release(v); }
     #[wasm_bindgen]
     pub fn set_filter_env_amount(&mut self, v: f32) { self.filter_l.set_env_amount(v); self.filter_r.set_env_amount(v); }
+    /// Filter envelope stage shapes (-1.0 log .. 0.0 linear .. 1.0 exp).
+    #[wasm_bindgen]
+    pub fn set_filter_env_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        self.filter_l.set_env_curves(attack, decay, release);
+        self.filter_r.set_env_curves(attack, decay, release);
+    }
+
+    /// Loop the filter envelope (modes as in `set_operator_mod_env_loop`).
+    #[wasm_bindgen]
+    pub fn set_filter_env_loop(&mut self, mode: u32, first: u32, last: u32) {
+        let mode = LoopMode::from_ids(mode, first, last);
+        self.filter_l.set_env_loop(mode);
+        self.filter_r.set_env_loop(mode);
+    }
+
+    /// Filter envelope retrigger mode (see `set_operator_mod_env_retrigger`).
+    #[wasm_bindgen]
+    pub fn set_filter_env_retrigger(&mut self, mode: u32) {
+        let mode = RetriggerMode::from_id(mode);
+        self.filter_l.set_env_retrigger(mode);
+        self.filter_r.set_env_retrigger(mode);
+    }
 
     // ——— Amp section setters ———
 
@@ -592,6 +1007,66 @@ pub fn set_amp_env(&mut self,
         }
     }
 
+    /// When notes glide: 0 off, 1 always (from the last note played), 2 legato
+    /// (only while another note is held, the default).
+    #[wasm_bindgen]
+    pub fn set_portamento_mode(&mut self, mode: u32) {
+        let mode = PortamentoMode::from_id(mode);
+        for v in &mut self.voices {
+            v.set_portamento_mode(mode);
+        }
+    }
+
+    /// 0 constant time (every glide takes the portamento time), 1 constant
+    /// rate (the portamento time is per octave).
+    #[wasm_bindgen]
+    pub fn set_glide_type(&mut self, glide: u32) {
+        let glide = GlideType::from_id(glide);
+        for v in &mut self.voices {
+            v.set_glide_type(glide);
+        }
+    }
+
+    /// FM core quality: 1 = off, 2 = 2x, 4 = 4x oversampling.
+    /// Voices and overdrive run at the higher rate, then half-band FIR
+    /// decimators bring the signal back before the filter.
+    #[wasm_bindgen]
+    pub fn set_oversampling(&mut self, factor: u32) {
+        let mode = Oversampling::from_factor(factor);
+        if mode != self.oversampling {
+            self.oversampling = mode;
+            self.decimator_l.reset();
+            self.decimator_r.reset();
+        }
+    }
+
+    /// Ramp time in ms used to de-zipper a parameter's setter changes
+    /// (modulation ramps across each control block instead). Params: 0 volume, 1 pan, 2 filter cutoff, 3 filter
+    /// resonance, 4 overdrive, 5 carrier mix, 6 operator level, 7 operator ratio.
+    #[wasm_bindgen]
+    pub fn set_smoothing_time(&mut self, param: u32, ms: f32) {
+        let secs = ms.clamp(0.0, 1000.0) / 1000.0;
+        let sm = &mut self.smoothers;
+        match param {
+            0 => sm.volume.value.set_ramp_time(secs),
+            1 => sm.pan.value.set_ramp_time(secs),
+            2 => sm.cutoff.value.set_ramp_time(secs),
+            3 => sm.resonance.value.set_ramp_time(secs),
+            4 => sm.overdrive.value.set_ramp_time(secs),
+            5 => sm.carrier_mix.value.set_ramp_time(secs),
+            6 | 7 => {
+                if param == 6 { sm.op_level_ramp = secs } else { sm.op_ratio_ramp = secs }
+                let (level, ratio) = (sm.op_level_ramp, sm.op_ratio_ramp);
+                for v in &mut self.voices {
+                    for op in &mut v.operators {
+                        op.set_smoothing_times(level, ratio);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
     #[wasm_bindgen]
     pub fn set_pitch_bend_range(&mut self, range: f32) {
         self.pitch_bend_range = range.clamp(0.0, 24.0);
@@ -604,6 +1079,9 @@ pub fn set_amp_env(&mut self,
         // Value should be -1.0 (full down) to +1.0 (full up), with 0.0 = center
         self.pitch_bend_value = value.clamp(-1.0, 1.0);
         self.apply_pitch_bend();
+        for synth in self.layer_synths() {
+            synth.set_pitch_bend(value);
+        }
     }
 
     /// Internal helper to calculate and apply pitch bend to all voices
@@ -682,19 +1160,93 @@ pub fn set_reverb_mix(&mut self, m: f32) {
 
 #[wasm_bindgen]
 pub fn set_chorus_enabled(&mut self, on: bool) {
-    self.chorus_enabled = on;
+    self.effects.chain.set_enabled(EffectSlot::Chorus, on);
 }
 
 #[wasm_bindgen]
 pub fn set_delay_enabled(&mut self, on: bool) {
-    self.delay_enabled = on;
+    self.effects.chain.set_enabled(EffectSlot::Delay, on);
 }
 
 #[wasm_bindgen]
 pub fn set_reverb_enabled(&mut self, on: bool) {
-    self.reverb_enabled = on;
+    self.effects.chain.set_enabled(EffectSlot::Reverb, on);
 }
 
+    // ——— Effects chain ———
+    // Effect ids: 0 chorus, 1 delay, 2 reverb, 3 + n for effects added natively.
+
+    /// Insert an effect before chain position `index` (past the end
+    /// appends). False if it is already in the chain or the chain is full.
+    #[wasm_bindgen]
+    pub fn insert_effect(&mut self, index: usize, effect: u32) -> bool {
+        EffectSlot::from_id(effect).is_some_and(|slot| self.effects.chain.insert(index, slot))
+    }
+
+    /// Take the effect at chain position `index` out; returns its id, or
+    /// -1 if there was none.
+    #[wasm_bindgen]
+    pub fn remove_effect(&mut self, index: usize) -> i32 {
+        self.effects.chain.remove(index).map_or(-1, |slot| slot.id() as i32)
+    }
+
+    /// Move the effect at chain position `from` to `to`.
+    #[wasm_bindgen]
+    pub fn move_effect(&mut self, from: usize, to: usize) {
+        self.effects.chain.move_slot(from, to);
+    }
+
+    /// Replace the chain with these effect ids, in order (unknown ids and
+    /// repeats are skipped).
+    #[wasm_bindgen]
+    pub fn set_effect_order(&mut self, effects: &[u32]) {
+        self.effects.chain.set_order(effects.iter().filter_map(|&id| EffectSlot::from_id(id)));
+    }
+
+    /// Effect ids in chain order, for saving with the patch.
+    #[wasm_bindgen]
+    pub fn get_effect_order(&self) -> Vec<u32> {
+        self.effects.chain.slots().iter().map(|&(slot, _)| slot.id()).collect()
+    }
+
+    /// Set parameter `param` of an effect, indexed as in `get_effect_params`.
+    #[wasm_bindgen]
+    pub fn set_effect_param(&mut self, effect: u32, param: usize, value: f32) {
+        if let Some(fx) = EffectSlot::from_id(effect).and_then(|slot| self.effects.effect_mut(slot)) {
+            fx.set_param(param, value);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_effect_param(&self, effect: u32, param: usize) -> f32 {
+        EffectSlot::from_id(effect)
+            .and_then(|slot| self.effects.effect(slot))
+            .map_or(0.0, |fx| fx.param(param))
+    }
+
+    /// Parameter descriptors (name, min, max, default, unit) of an effect,
+    /// or null for an unknown id.
+    #[wasm_bindgen]
+    pub fn get_effect_params(&self, effect: u32) -> JsValue {
+        match EffectSlot::from_id(effect).and_then(|slot| self.effects.effect(slot)) {
+            #[allow(deprecated)]
+            Some(fx) => JsValue::from_serde(fx.params()).unwrap(),
+            None => JsValue::NULL,
+        }
+    }
+
+    /// Latency of the switched-on chain, in samples.
+    #[wasm_bindgen]
+    pub fn effects_latency(&self) -> usize {
+        self.effects.latency()
+    }
+
+    /// Silence every effect's buffers and tails.
+    #[wasm_bindgen]
+    pub fn reset_effects(&mut self) {
+        self.effects.reset();
+    }
+
 
 #[wasm_bindgen]
 pub fn set_ratio_c(&mut self, r: f32) {
@@ -725,172 +1277,127 @@ pub fn set_ratio_b(&mut self, b1: f32, b2: f32) {
     // —— LFO1 parameter setters ——
     #[wasm_bindgen]
     pub fn set_lfo1_speed(&mut self, v: f32) {
-        self.lfo1.set_speed(v);
+        self.update_lfo(0, |l| l.set_speed(v));
     }
     #[wasm_bindgen]
     pub fn set_lfo1_multiplier(&mut self, m: i32) {
-        self.lfo1.set_multiplier(m);
+        self.update_lfo(0, |l| l.set_multiplier(m));
     }
     #[wasm_bindgen]
     pub fn set_lfo1_fade(&mut self, f: i32) {
-        self.lfo1.set_fade(f);
+        self.update_lfo(0, |l| l.set_fade(f));
     }
     #[wasm_bindgen]
     pub fn apply_lfo_modulation(&mut self, mod1: f32, mod2: f32) {
         let dest1 = self.lfo1.destination;
         let dest2 = self.lfo2.destination;
         // Route global synth params and per-voice
-        self.route_lfo(mod1, dest1);
-        self.route_lfo(mod2, dest2);
+        self.apply_mod(mod1, dest1);
+        self.apply_mod(mod2, dest2);
     }
 
-    fn route_lfo(&mut self, value: f32, dest: LfoDestination) {
+    fn apply_mod(&mut self, value: f32, dest: ModDestination) {
         match dest {
             // ----- Synth-level parameters -----
-            LfoDestination::ModDepthA => self.mod_depth_a += value,
-            LfoDestination::ModDepthB => self.mod_depth_b += value,
-            LfoDestination::Feedback  => self.feedback    += value,
-            LfoDestination::Harm      => self.harm        += value,
-            LfoDestination::CarrierMix=> self.carrier_mix += value,
+            ModDestination::CarrierMix=> self.carrier_mix += value,
 
-            LfoDestination::Overdrive => self.overdrive   += value,
-            LfoDestination::Pan       => self.pan         += value,
-            LfoDestination::Volume    => self.volume      += value,
+            ModDestination::Overdrive => self.overdrive   += value,
+            ModDestination::Pan       => self.pan         += value,
+            ModDestination::Volume    => self.volume      += value,
 
             // ----- Filter parameters -----
-            LfoDestination::FilterCutoff => {
-                let v = self.filter_l.cutoff() + value;
-                self.filter_l.set_cutoff(v); self.filter_r.set_cutoff(v);
-            }
-            LfoDestination::FilterResonance => {
-                let v = self.filter_l.resonance() + value;
-                self.filter_l.set_resonance(v); self.filter_r.set_resonance(v);
-            }
-            LfoDestination::FilterEnvAmount => {
+            ModDestination::Effect(param)   => self.effects.apply_mod(param, value),
+            ModDestination::FilterCutoff    => self.filter_cutoff    += value,
+            ModDestination::FilterResonance => self.filter_resonance += value,
+            ModDestination::FilterEnvCurve  => self.filter_env_curve_mod += value,
+            ModDestination::FilterEnvAmount => {
                 let v = self.filter_l.env_amount() + value;
                 self.filter_l.set_env_amount(v); self.filter_r.set_env_amount(v);
             }
-            LfoDestination::FilterAttack => {
+            ModDestination::FilterAttack => {
                 let v = self.filter_l.attack() + value;
                 self.filter_l.set_attack(v); self.filter_r.set_attack(v);
             }
-            LfoDestination::FilterDecay => {
+            ModDestination::FilterDecay => {
                 let v = self.filter_l.decay() + value;
                 self.filter_l.set_decay(v); self.filter_r.set_decay(v);
             }
-            LfoDestination::FilterSustain => {
+            ModDestination::FilterSustain => {
                 let v = self.filter_l.sustain() + value;
                 self.filter_l.set_sustain(v); self.filter_r.set_sustain(v);
             }
-            LfoDestination::FilterRelease => {
+            ModDestination::FilterRelease => {
                 let v = self.filter_l.release() + value;
                 self.filter_l.set_release(v); self.filter_r.set_release(v);
             }
 
-            // ----- Voice-level parameters -----
-            LfoDestination::RatioA     |
-            LfoDestination::RatioB     |
-            LfoDestination::RatioC     |
-            LfoDestination::AmpAttack  |
-            LfoDestination::AmpDecay   |
-            LfoDestination::AmpSustain |
-            LfoDestination::AmpRelease => {
+            // ----- Voice-level parameters (FM depths, ratios, operators, amp env) -----
+            ModDestination::ModDepthA  |
+            ModDestination::ModDepthB  |
+            ModDestination::Feedback   |
+            ModDestination::Harm       |
+            ModDestination::RatioA     |
+            ModDestination::RatioB     |
+            ModDestination::RatioC     |
+            ModDestination::AmpAttack  |
+            ModDestination::AmpDecay   |
+            ModDestination::AmpSustain |
+            ModDestination::AmpRelease |
+            ModDestination::WavetablePosition |
+            ModDestination::Pitch      |
+            ModDestination::LfoSpeed(_) |
+            ModDestination::LfoDepth(_) |
+            ModDestination::Portamento |
+            ModDestination::AmpEnvCurve |
+            ModDestination::PitchEnvDepth |
+            ModDestination::Detune     |
+            ModDestination::Operator(..) |
+            ModDestination::Connection(_) => {
                 for voice in &mut self.voices {
-                    voice.apply_lfo(dest, value);
+                    voice.apply_mod(dest, value);
                 }
             }
         }
     }
+    /// 0 Triangle, 1 Sine, 2 Square, 3 Saw, 4 Exponential, 5 Ramp, 6 Random,
+    /// 7 Custom (breakpoint curve), 8 Steps (step sequence).
     #[wasm_bindgen]
     pub fn set_lfo1_waveform(&mut self, w: u32) {
-        let wf = match w {
-            0 => Waveform::Triangle,
-            1 => Waveform::Sine,
-            2 => Waveform::Square,
-            3 => Waveform::Sawtooth,
-            4 => Waveform::Exponential,
-            5 => Waveform::Ramp,
-            6 => Waveform::Random,
-            _ => Waveform::Triangle,
-        };
-        self.lfo1.set_waveform(wf);
+        let wf = Self::lfo_waveform_from_id(w);
+        self.update_lfo(0, |l| l.set_waveform(wf));
     }
 
-    #[wasm_bindgen]
+    /// LFO1 target, from the same id space as the modulation matrix: 0–22 are
+/// the Digitone-style destinations, 23 pitch, 24–27 LFO1/LFO2 speed and depth,
+/// 28 glide time, 29/30 amp/filter envelope curves, 31 pitch envelope depth;
+/// 32 + op * 8 + param targets one operator (param 0 level, 1 ratio, 2 detune,
+/// 3 feedback, 4 harm, 5 morph, 6 mod envelope curve, 7 pitch envelope amount)
+/// and 64 + src * 4 + dst one FM connection. 80–86 target the effects: chorus
+/// depth/speed, delay time/feedback/mix, reverb decay/mix; 87 the global detune.
+/// LFO values are applied raw (depth × shape), not scaled to the destination.
+#[wasm_bindgen]
 pub fn set_lfo1_destination(&mut self, d: u32) {
-    let dest = match d {
-        0  => LfoDestination::ModDepthA,
-        1  => LfoDestination::ModDepthB,
-        2  => LfoDestination::RatioC,
-        3  => LfoDestination::RatioA,
-        4  => LfoDestination::RatioB,
-        5  => LfoDestination::Feedback,
-        6  => LfoDestination::Harm,
-        7  => LfoDestination::CarrierMix,
-        8  => LfoDestination::AmpAttack,
-        9  => LfoDestination::AmpDecay,
-        10 => LfoDestination::AmpSustain,
-        11 => LfoDestination::AmpRelease,
-        12 => LfoDestination::Overdrive,
-        13 => LfoDestination::Pan,
-        14 => LfoDestination::Volume,
-        15 => LfoDestination::FilterAttack,
-        16 => LfoDestination::FilterDecay,
-        17 => LfoDestination::FilterSustain,
-        18 => LfoDestination::FilterRelease,
-        19 => LfoDestination::FilterCutoff,
-        20 => LfoDestination::FilterResonance,
-        21 => LfoDestination::FilterEnvAmount,
-        _  => LfoDestination::ModDepthA,
-    };
-    self.lfo1.set_destination(dest);
+    let dest = ModDestination::from_id(d).unwrap_or(ModDestination::ModDepthA);
+    self.update_lfo(0, |l| l.set_destination(dest));
 }
 
 #[wasm_bindgen]
 pub fn set_lfo2_destination(&mut self, d: u32) {
-    let dest = match d {
-        0  => LfoDestination::ModDepthA,
-        1  => LfoDestination::ModDepthB,
-        2  => LfoDestination::RatioC,
-        3  => LfoDestination::RatioA,
-        4  => LfoDestination::RatioB,
-        5  => LfoDestination::Feedback,
-        6  => LfoDestination::Harm,
-        7  => LfoDestination::CarrierMix,
-        8  => LfoDestination::AmpAttack,
-        9  => LfoDestination::AmpDecay,
-       10  => LfoDestination::AmpSustain,
-       11  => LfoDestination::AmpRelease,
-       12  => LfoDestination::Overdrive,
-       13  => LfoDestination::Pan,
-       14  => LfoDestination::Volume,
-       15  => LfoDestination::FilterAttack,
-       16  => LfoDestination::FilterDecay,
-       17  => LfoDestination::FilterSustain,
-       18  => LfoDestination::FilterRelease,
-       19  => LfoDestination::FilterCutoff,
-       20  => LfoDestination::FilterResonance,
-       21  => LfoDestination::FilterEnvAmount,
-       _   => LfoDestination::ModDepthA,
-    };
-    self.lfo2.set_destination(dest);
+    let dest = ModDestination::from_id(d).unwrap_or(ModDestination::ModDepthA);
+    self.update_lfo(1, |l| l.set_destination(dest));
 }
 
 #[wasm_bindgen]
 pub fn set_lfo2_waveform(&mut self, w: u32) {
-    let wf = match w {
-        0 => Waveform::Triangle,
-        1 => Waveform::Sine,
-        2 => Waveform::Square,
-        3 => Waveform::Sawtooth,
-        4 => Waveform::Exponential,
-        5 => Waveform::Ramp,
-        6 => Waveform::Random,
-        _ => Waveform::Triangle,
-    };
-    self.lfo2.set_waveform(wf);
+    let wf = Self::lfo_waveform_from_id(w);
+    self.update_lfo(1, |l| l.set_waveform(wf));
 }
 
+  /// Select an operator's waveform across all voices.
+  /// `op_index`: 0 = C, 1 = A, 2 = B1, 3 = B2.
+  /// `wave_type_id` (oscillator.rs `WaveType` order): 0 Sine, 1 Square, 2 Saw,
+  /// 3 Triangle, 4 Noise, 5–12 OPZ W1–W8, 13 user wavetable, 14 Pink noise,
+  /// 15 Brown noise, 16 Digital noise.
   #[wasm_bindgen]
   pub fn set_operator_waveform(&mut self, op_index: usize, wave_type_id: u8) {
       if op_index >= 4 { return; }
@@ -901,147 +1408,1395 @@ pub fn set_lfo2_waveform(&mut self, w: u32) {
 
 
 
+    // ——— Wavetable operators ———
+
+    /// Load a wavetable from raw samples into an operator (0-3) across all voices.
+    /// `frame_size` is the cycle length in `data` (0 = whole buffer is one cycle).
+    /// Returns false if the buffer is empty or shorter than one frame.
     #[wasm_bindgen]
-    pub fn set_lfo1_start_phase(&mut self, p: f32) {
-        self.lfo1.set_start_phase(p);
+    pub fn load_operator_wavetable(&mut self, op_index: usize, data: &[f32], frame_size: usize) -> bool {
+        match Wavetable::from_samples(data, frame_size) {
+            Ok(wt) => self.assign_wavetable(op_index, wt),
+            Err(e) => {
+                log(&format!("[engine] wavetable rejected: {}", e));
+                false
+            }
+        }
     }
+
+    /// Load a WAV file (Serum-style 2048-sample frames, or a single cycle)
+    /// into an operator (0-3). Returns false if the file can't be decoded.
     #[wasm_bindgen]
-    pub fn set_lfo1_mode(&mut self, m: u32) {
-        let mode = match m {
-            0 => LfoMode::Free,
-            1 => LfoMode::Trigger,
-            2 => LfoMode::Hold,
-            3 => LfoMode::One,
-            4 => LfoMode::Half,
-            _ => LfoMode::Free,
-        };
-        self.lfo1.set_mode(mode);
+    pub fn load_operator_wavetable_wav(&mut self, op_index: usize, bytes: &[u8]) -> bool {
+        match Wavetable::from_wav_bytes(bytes) {
+            Ok(wt) => self.assign_wavetable(op_index, wt),
+            Err(e) => {
+                log(&format!("[engine] wavetable WAV rejected: {}", e));
+                false
+            }
+        }
     }
+
+    /// Set wavetable frame position (0.0-1.0) for an operator (0-3).
     #[wasm_bindgen]
-    pub fn set_lfo1_depth(&mut self, d: f32) {
-        self.lfo1.set_depth(d);
+    pub fn set_operator_wavetable_position(&mut self, op_index: usize, position: f32) {
+        if op_index >= 4 { return; }
+        for v in &mut self.voices {
+            v.set_operator_wavetable_position(op_index, position);
+        }
+    }
+
+    /// Set how far an operator's mod envelope sweeps its wavetable position (-1.0..1.0).
+    #[wasm_bindgen]
+    pub fn set_operator_wavetable_env_amount(&mut self, op_index: usize, amount: f32) {
+        if op_index >= 4 { return; }
+        for v in &mut self.voices {
+            v.set_operator_wavetable_env_amount(op_index, amount);
+        }
+    }
+
+    fn assign_wavetable(&mut self, op_index: usize, wavetable: Wavetable) -> bool {
+        if op_index >= 4 { return false; }
+        let shared = Arc::new(wavetable);
+        for v in &mut self.voices {
+            v.set_operator_wavetable(op_index, Arc::clone(&shared));
+        }
+        true
+    }
+
+    #[wasm_bindgen]
+    pub fn set_lfo1_start_phase(&mut self, p: f32) {
+        self.update_lfo(0, |l| l.set_start_phase(p));
+    }
+    #[wasm_bindgen]
+    pub fn set_lfo1_mode(&mut self, m: u32) {
+        let mode = Self::lfo_mode_from_id(m);
+        self.update_lfo(0, |l| l.set_mode(mode));
+    }
+    #[wasm_bindgen]
+    pub fn set_lfo1_depth(&mut self, d: f32) {
+        self.update_lfo(0, |l| l.set_depth(d));
     }
 
     // —— LFO2 parameter setters ——
     #[wasm_bindgen]
-    pub fn set_lfo2_speed(&mut self, v: f32) { self.lfo2.set_speed(v); }
+    pub fn set_lfo2_speed(&mut self, v: f32) { self.update_lfo(1, |l| l.set_speed(v)); }
+    #[wasm_bindgen]
+    pub fn set_lfo2_multiplier(&mut self, m: i32) { self.update_lfo(1, |l| l.set_multiplier(m)); }
+    #[wasm_bindgen]
+    pub fn set_lfo2_fade(&mut self, f: i32) { self.update_lfo(1, |l| l.set_fade(f)); }
+
+    #[wasm_bindgen]
+    pub fn set_lfo2_start_phase(&mut self, p: f32) { self.update_lfo(1, |l| l.set_start_phase(p)); }
+    #[wasm_bindgen]
+    pub fn set_lfo2_mode(&mut self, m: u32) {
+        let mode = Self::lfo_mode_from_id(m);
+        self.update_lfo(1, |l| l.set_mode(mode));
+    }
     #[wasm_bindgen]
-    pub fn set_lfo2_multiplier(&mut self, m: i32) { self.lfo2.set_multiplier(m); }
+    pub fn set_lfo2_depth(&mut self, d: f32) { self.update_lfo(1, |l| l.set_depth(d)); }
+
+    /// Sync LFO1 to tempo: speed then selects a musical division (0 = 16 bars
+    /// dotted … 63 = 1/64 triplet) and, while the transport plays, a Free or
+    /// Hold LFO locks its phase to the song position.
     #[wasm_bindgen]
-    pub fn set_lfo2_fade(&mut self, f: i32) { self.lfo2.set_fade(f); }
+    pub fn set_lfo1_sync(&mut self, on: bool) { self.update_lfo(0, |l| l.set_sync(on)); }
 
+    /// Sync LFO2 to tempo (see `set_lfo1_sync`).
     #[wasm_bindgen]
-    pub fn set_lfo2_start_phase(&mut self, p: f32) { self.lfo2.set_start_phase(p); }
+    pub fn set_lfo2_sync(&mut self, on: bool) { self.update_lfo(1, |l| l.set_sync(on)); }
+
+    /// Run LFO1 per voice (key-synced, one copy per note) instead of once globally.
+    /// Voice destinations (ratios, amp envelope, wavetable) follow each voice's
+    /// own LFO; synth-wide destinations follow the most recently played note.
     #[wasm_bindgen]
-    pub fn set_lfo2_mode(&mut self, _m: u32) { /* … */ }
+    pub fn set_lfo1_per_voice(&mut self, on: bool) { self.lfo_per_voice[0] = on; }
+
+    /// Run LFO2 per voice (see `set_lfo1_per_voice`).
+    #[wasm_bindgen]
+    pub fn set_lfo2_per_voice(&mut self, on: bool) { self.lfo_per_voice[1] = on; }
+
+    // ——— Custom LFO shapes ———
+
+    /// Breakpoint curve for LFO `lfo` (0/1) as flat `[phase, value, curve, …]`
+    /// triples: phase 0–1, value -1–1, curve -1–1 bends the segment leaving
+    /// the point. Played when the waveform is 7 (Custom).
+    #[wasm_bindgen]
+    pub fn set_lfo_curve(&mut self, lfo: usize, points: &[f32]) {
+        if lfo > 1 { return; }
+        let curve = LfoCurve::from_flat(points);
+        self.update_lfo(lfo, |l| l.set_curve(curve.clone()));
+    }
+
+    /// The curve as stored in the patch, in `set_lfo_curve`'s layout.
+    #[wasm_bindgen]
+    pub fn get_lfo_curve(&self, lfo: usize) -> Vec<f32> {
+        self.lfo_ref(lfo).curve().to_flat()
+    }
+
+    /// Step sequence (1–32 values, -1–1) played when the waveform is 8 (Steps).
+    #[wasm_bindgen]
+    pub fn set_lfo_steps(&mut self, lfo: usize, values: &[f32]) {
+        if lfo > 1 { return; }
+        self.update_lfo(lfo, |l| l.steps_mut().set_values(values));
+    }
+
+    #[wasm_bindgen]
+    pub fn get_lfo_steps(&self, lfo: usize) -> Vec<f32> {
+        self.lfo_ref(lfo).steps().values().to_vec()
+    }
+
+    /// Glide between steps: 0 = hard steps, 1 = slide across the whole step.
+    #[wasm_bindgen]
+    pub fn set_lfo_step_glide(&mut self, lfo: usize, glide: f32) {
+        if lfo > 1 { return; }
+        self.update_lfo(lfo, |l| l.steps_mut().set_glide(glide));
+    }
+
+    #[wasm_bindgen]
+    pub fn get_lfo_step_glide(&self, lfo: usize) -> f32 {
+        self.lfo_ref(lfo).steps().glide()
+    }
+
+    // ——— Transport ———
+
+    #[wasm_bindgen]
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.transport.set_bpm(bpm);
+        for synth in self.layer_synths() {
+            synth.set_tempo(bpm);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn set_playing(&mut self, playing: bool) {
+        self.transport.set_playing(playing);
+        for synth in self.layer_synths() {
+            synth.set_playing(playing);
+        }
+    }
+
+    /// Host song position in quarter-note beats (PPQ). Call whenever the host
+    /// position jumps, or every block to stay sample-locked to the host.
+    #[wasm_bindgen]
+    pub fn set_song_position(&mut self, beats: f64) {
+        self.transport.set_position(beats);
+        for synth in self.layer_synths() {
+            synth.set_song_position(beats);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn song_position(&self) -> f64 { self.transport.position() }
+
+    // ——— Chord memory ———
+
+    /// Play a chord from every key. Turning it off releases its chords.
+    #[wasm_bindgen]
+    pub fn set_chord_enabled(&mut self, on: bool) {
+        self.chord.set_enabled(on);
+        self.run_chord_events();
+    }
+
+    /// 0 major, 1 minor, 2 sus2, 3 sus4, 4 dim, 5 aug, 6 maj7, 7 min7,
+    /// 8 dom7, 9 min9, 10 fifth, 11 octave.
+    #[wasm_bindgen]
+    pub fn set_chord_type(&mut self, chord: u32) { self.chord.set_chord_type(ChordType::from_id(chord)); }
+
+    /// Store a chord as semitones from the played note (up to 8 tones).
+    #[wasm_bindgen]
+    pub fn set_chord_intervals(&mut self, intervals: &[i32]) { self.chord.set_intervals(intervals); }
+
+    /// The stored chord, for saving with the patch.
+    #[wasm_bindgen]
+    pub fn get_chord_intervals(&self) -> Vec<i32> { self.chord.intervals().to_vec() }
+
+    /// Store the chord currently held on the keyboard.
+    #[wasm_bindgen]
+    pub fn capture_chord(&mut self) {
+        let held: Vec<f32> = self.voices.iter()
+            .filter(|v| v.is_held())
+            .map(|v| v.note_frequency())
+            .collect();
+        self.chord.capture(&held);
+    }
+
+    /// Quantize chord tones to a scale: key 0 (C) to 11 (B); scale 0 off,
+    /// 1 major, 2 natural minor, 3 harmonic minor, 4 dorian, 5 phrygian,
+    /// 6 lydian, 7 mixolydian, 8 locrian, 9/10 major/minor pentatonic.
+    #[wasm_bindgen]
+    pub fn set_chord_scale(&mut self, key: u32, scale: u32) { self.chord.set_scale(key, Scale::from_id(scale)); }
+
+    /// Move the lowest `inversion` tones up an octave.
+    #[wasm_bindgen]
+    pub fn set_chord_inversion(&mut self, inversion: u32) { self.chord.set_inversion(inversion); }
+
+    /// 0 close, 1 drop 2, 2 spread.
+    #[wasm_bindgen]
+    pub fn set_chord_voicing(&mut self, voicing: u32) { self.chord.set_voicing(Voicing::from_id(voicing)); }
+
+    /// Strum delay between tones in ms (up to 500); negative strums downwards.
+    #[wasm_bindgen]
+    pub fn set_chord_strum(&mut self, ms: f32) { self.chord.set_strum_ms(ms); }
+
+    // ——— Arpeggiator ———
+
+    /// Route notes through the arpeggiator. Turning it off releases its notes.
+    #[wasm_bindgen]
+    pub fn set_arp_enabled(&mut self, on: bool) {
+        self.arp.set_enabled(on);
+        for i in 0..self.arp.events().len() {
+            if let ArpEvent::NoteOff { note_id } = self.arp.events()[i] {
+                self.release_note(note_id);
+            }
+        }
+    }
+
+    /// 0 up, 1 down, 2 up-down, 3 as played, 4 random, 5 chord.
+    #[wasm_bindgen]
+    pub fn set_arp_mode(&mut self, mode: u32) { self.arp.set_mode(ArpMode::from_id(mode)); }
+
+    /// Octave range, 1-4.
+    #[wasm_bindgen]
+    pub fn set_arp_octaves(&mut self, octaves: u32) { self.arp.set_octaves(octaves); }
+
+    /// Gate length as a fraction of a step (0.01-1.0, 1.0 = legato).
+    #[wasm_bindgen]
+    pub fn set_arp_gate(&mut self, gate: f32) { self.arp.set_gate(gate); }
+
+    /// Step length as a tempo sync division (same indices as synced LFOs;
+    /// the default is 1/16).
     #[wasm_bindgen]
-    pub fn set_lfo2_depth(&mut self, d: f32) { self.lfo2.set_depth(d); }
+    pub fn set_arp_rate(&mut self, division: u32) { self.arp.set_rate(division as usize); }
+
+    #[wasm_bindgen]
+    pub fn set_arp_latch(&mut self, on: bool) { self.arp.set_latch(on); }
+
+    /// Swing in percent, 50 (straight) to 75.
+    #[wasm_bindgen]
+    pub fn set_arp_swing(&mut self, percent: f32) { self.arp.set_swing(percent / 100.0); }
+
+    // ——— Sequencer ———
+
+    /// Play the pattern while the transport runs. Turning it off releases
+    /// its notes and locks.
+    #[wasm_bindgen]
+    pub fn set_seq_enabled(&mut self, on: bool) {
+        self.seq.set_enabled(on);
+        self.run_seq_events();
+    }
+
+    /// Pattern length in steps, 16-64.
+    #[wasm_bindgen]
+    pub fn set_seq_length(&mut self, steps: u32) { self.seq.pattern_mut().set_num_steps(steps as usize); }
+
+    /// Step length as a tempo sync division (same indices as synced LFOs;
+    /// the default is 1/16).
+    #[wasm_bindgen]
+    pub fn set_seq_rate(&mut self, division: u32) { self.seq.set_rate(division as usize); }
+
+    /// Put a trig on `step`: MIDI note, velocity 0-127, length in steps and
+    /// micro-timing (-0.5..0.5 of a step).
+    #[wasm_bindgen]
+    pub fn set_seq_trig(&mut self, step: usize, note: u8, velocity: f32, length: f32, micro: f32) {
+        if let Some(s) = self.seq.pattern_mut().step_mut(step) {
+            s.trig = Some(Trig::new(note, velocity, length, micro));
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_seq_trig(&mut self, step: usize) {
+        if let Some(s) = self.seq.pattern_mut().step_mut(step) {
+            s.trig = None;
+        }
+    }
+
+    /// Lock a parameter on `step` to an absolute value. `param` is a
+    /// modulation destination id (see `set_lfo1_destination`); the lockable
+    /// ones are listed in `SeqParam::from_id`, e.g. 19 filter cutoff,
+    /// 64 + src * 4 + dst one depth matrix cell. Depth A/B (0/1) lock all the
+    /// cells from C/A or B1/B2.
+    #[wasm_bindgen]
+    pub fn set_seq_lock(&mut self, step: usize, param: u32, value: f32) {
+        if let Some(s) = self.seq.pattern_mut().step_mut(step) {
+            for p in SeqParam::targets(param) {
+                s.set_lock(p, value);
+            }
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_seq_lock(&mut self, step: usize, param: u32) {
+        if let Some(s) = self.seq.pattern_mut().step_mut(step) {
+            for p in SeqParam::targets(param) {
+                s.clear_lock(p);
+            }
+        }
+    }
+
+    /// Pattern as flat data, for saving with the patch.
+    #[wasm_bindgen]
+    pub fn get_seq_pattern(&self) -> Vec<f32> {
+        self.seq.pattern().to_flat()
+    }
+
+    /// Load a pattern saved with `get_seq_pattern`.
+    #[wasm_bindgen]
+    pub fn set_seq_pattern(&mut self, data: &[f32]) {
+        self.seq.set_pattern(Pattern::from_flat(data));
+    }
+
+    // ——— Modulation matrix ———
+
+    /// Route `source` to `destination` in matrix slot `slot` (0-15).
+    /// Source ids: 1 LFO1, 2 LFO2, 3–6 mod env C/A/B1/B2, 7 velocity, 8 key,
+    /// 9 aftertouch, 10 mod wheel, 11 random per note (0 = none).
+    /// Destination ids are shared with the LFOs (see `set_lfo1_destination`).
+    /// `amount` is bipolar (-1..1); `via` scales the slot by a second source
+    /// (0 = none). An unknown source or destination clears the slot.
+    #[wasm_bindgen]
+    pub fn set_mod_slot(&mut self, slot: usize, source: u32, destination: u32, amount: f32, via: u32) {
+        match (ModSource::from_id(source), ModDestination::from_id(destination)) {
+            (Some(source), Some(destination)) => self.mod_matrix.set_slot(slot, ModSlot {
+                source,
+                destination,
+                amount,
+                via: ModSource::from_id(via),
+            }),
+            _ => self.mod_matrix.clear_slot(slot),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_mod_slot(&mut self, slot: usize) {
+        self.mod_matrix.clear_slot(slot);
+    }
+
+    /// Mod wheel position, 0-127.
+    #[wasm_bindgen]
+    pub fn set_mod_wheel(&mut self, v: f32) {
+        self.mod_wheel = (v / 127.0).clamp(0.0, 1.0);
+        for synth in self.layer_synths() {
+            synth.set_mod_wheel(v);
+        }
+    }
+
+    /// Channel aftertouch, 0-127.
+    #[wasm_bindgen]
+    pub fn set_aftertouch(&mut self, v: f32) {
+        self.aftertouch = (v / 127.0).clamp(0.0, 1.0);
+        for synth in self.layer_synths() {
+            synth.set_aftertouch(v);
+        }
+    }
 
     
 
     // ——— Audio rendering ———
     #[wasm_bindgen]
     pub fn process_sample_array(&mut self) -> Float32Array {
-        let mut out = Vec::with_capacity(BLOCK * 2);
+        let mut out = [0.0f32; BLOCK * 2];
+        self.render_block(&mut out);
+
+        // Package into a Float32Array for JS
+        let array = Float32Array::new_with_length((BLOCK * 2) as u32);
+        array.copy_from(&out);
+        array
+    }
+}
+
+impl Synth {
+    /// Mutable access to a layer's synth, for native hosts and tests.
+    pub fn layer_mut(&mut self, layer: usize) -> Option<&mut Synth> {
+        self.layers.get_mut(layer).and_then(|l| l.synth.as_mut())
+    }
+
+    /// Pitches (Hz) of this patch's voices holding a note, for native
+    /// hosts and tests.
+    pub fn held_frequencies(&self) -> Vec<f32> {
+        self.voices.iter().filter(|v| v.is_held()).map(|v| v.note_frequency()).collect()
+    }
+
+    fn layer_synths(&mut self) -> impl Iterator<Item = &mut Synth> {
+        self.layers.iter_mut().filter_map(|l| l.synth.as_mut())
+    }
+
+    /// Ready a synth to play inside another instrument: dry, and flat.
+    fn make_layer(&mut self) {
+        self.release_effect_buffers();
+        self.layers.clear();
+        self.is_layer = true;
+    }
+
+    /// Play a note on the layers it falls in and, when it is in their zone,
+    /// this patch's own voices.
+    fn start_note(&mut self, note_id: u32, freq: f32, velocity: f32) {
+        for synth in self.layer_synths() {
+            synth.note_on_velocity(note_id, freq, velocity);
+        }
+        if let Some(freq) = self.zone.map(freq, velocity) {
+            self.start_voice(note_id, freq, velocity);
+        }
+    }
+
+    fn release_note(&mut self, note_id: u32) {
+        for synth in self.layer_synths() {
+            synth.note_off(note_id);
+        }
+        self.release_voice(note_id);
+    }
+
+    /// Allocate a voice and start `note_id` on it.
+    fn start_voice(&mut self, note_id: u32, freq: f32, velocity: f32) {
+        // 1) If this note_id is already playing, reuse that voice
+        let idx = if let Some(i) = self.voices.iter().position(|v| v.get_note_id() == Some(note_id)) {
+            i
+        // 2) Find a free (inactive) voice
+        } else if let Some(i) = self.voices.iter().position(|v| !v.is_active()) {
+            i
+        // 3) Prefer stealing a releasing voice (active but no note_id)
+        } else if let Some(i) = self.voices.iter().position(|v| !v.is_held()) {
+            i
+        // 4) All voices held — steal the oldest held voice
+        } else {
+            self.voices.iter().enumerate()
+                .min_by_key(|(_, v)| v.get_note_id().unwrap_or(u32::MAX))
+                .map(|(i, _)| i)
+                .unwrap_or(0)
+        };
+
+        // Check voices BEFORE triggering the new note: active for the filter
+        // envelope, held (on another voice) for legato glides
+        let any_active = self.voices.iter().any(|v| v.is_active());
+        let legato = self.voices.iter().enumerate()
+            .any(|(i, v)| v.is_held() && (i != idx || v.get_note_id() != Some(note_id)));
+
+        // Glide from the last note played, whichever voice played it
+        let pitch_mul = 2_f32.powi(self.octave_shift);
+        let adjusted_freq = freq * pitch_mul;
+        self.voices[idx].set_velocity(velocity / 127.0);
+        self.voices[idx].note_on(note_id, freq, self.last_note_frequency, legato);
+        self.last_note_frequency = adjusted_freq;  // Track for next note
+        self.last_voice = idx;
+
+        // Global LFOs retrigger on every note (per-voice copies are triggered by the voice)
+        self.lfo1.note_on();
+        self.lfo2.note_on();
+
+        if !any_active {
+            self.filter_l.note_on();
+            self.filter_r.note_on();
+        }
+    }
+
+    fn release_voice(&mut self, note_id: u32) {
+        // Find the voice playing this note_id
+        if let Some(i) = self.voices.iter().position(|v| v.get_note_id() == Some(note_id)) {
+            self.voices[i].note_off(note_id);
+        }
+        // Only release filter envelope when no voices are still held
+        let any_held = self.voices.iter().any(|v| v.is_held());
+        if !any_held {
+            self.filter_l.note_off();
+            self.filter_r.note_off();
+            self.lfo1.note_off();
+            self.lfo2.note_off();
+        }
+    }
+
+    /// Send a note to the arpeggiator, or straight to a voice.
+    fn play_note(&mut self, note_id: u32, freq: f32, velocity: f32) {
+        if self.arp.is_enabled() {
+            self.arp.note_on(note_id, freq, velocity);
+        } else {
+            self.start_note(note_id, freq, velocity);
+        }
+    }
+
+    fn stop_note(&mut self, note_id: u32) {
+        if self.arp.is_enabled() {
+            self.arp.note_off(note_id);
+        }
+        // Also covers notes that started before the arpeggiator was switched on
+        self.release_note(note_id);
+    }
+
+    /// Play the chord tones chord memory queued.
+    fn run_chord_events(&mut self) {
+        for i in 0..self.chord.events().len() {
+            match self.chord.events()[i] {
+                ChordEvent::NoteOn { note_id, freq, velocity } => self.play_note(note_id, freq, velocity),
+                ChordEvent::NoteOff { note_id } => self.stop_note(note_id),
+            }
+        }
+    }
+
+    /// Advance the arpeggiator one sample and play the notes it produced.
+    fn run_arp(&mut self, dt: f32) {
+        self.arp.process(dt);
+        for i in 0..self.arp.events().len() {
+            match self.arp.events()[i] {
+                ArpEvent::NoteOn { note_id, freq, velocity } => self.start_note(note_id, freq, velocity),
+                ArpEvent::NoteOff { note_id } => self.release_note(note_id),
+            }
+        }
+    }
+
+    /// Play the events the sequencer queued.
+    fn run_seq_events(&mut self) {
+        for i in 0..self.seq.events().len() {
+            match self.seq.events()[i] {
+                SeqEvent::NoteOn { note_id, freq, velocity } => self.start_note(note_id, freq, velocity),
+                SeqEvent::NoteOff { note_id } => self.release_note(note_id),
+                SeqEvent::Lock(param, value) => {
+                    match self.seq_locks.iter_mut().find(|(p, _)| *p == param) {
+                        Some(lock) => lock.1 = value,
+                        None => self.seq_locks.push((param, value)),
+                    }
+                }
+                SeqEvent::Unlock => self.seq_locks.clear(),
+            }
+        }
+    }
+
+    /// Lay the sequencer's locked values over the patch for one control
+    /// block, keeping the patch values to put back afterwards.
+    fn apply_seq_locks(&mut self) {
+        let mut saved = std::mem::take(&mut self.seq_saved);
+        saved.clear();
+        for i in 0..self.seq_locks.len() {
+            let (param, value) = self.seq_locks[i];
+            saved.push((param, self.seq_param(param)));
+            self.set_seq_param(param, value);
+        }
+        self.seq_saved = saved;
+    }
+
+    /// Put back the patch values `apply_seq_locks` covered, so host edits
+    /// made while a lock holds are what plays once it lifts.
+    fn restore_seq_locks(&mut self) {
+        let saved = std::mem::take(&mut self.seq_saved);
+        for &(param, value) in saved.iter().rev() {
+            self.set_seq_param(param, value);
+        }
+        self.seq_saved = saved;
+        self.seq_saved.clear();
+    }
+
+    /// Current (patch) value of a lockable parameter.
+    fn seq_param(&self, param: SeqParam) -> f32 {
+        match param {
+            SeqParam::CarrierMix      => self.carrier_mix,
+            SeqParam::Volume          => self.volume,
+            SeqParam::Pan             => self.pan,
+            SeqParam::Overdrive       => self.overdrive,
+            SeqParam::FilterCutoff    => self.filter_cutoff,
+            SeqParam::FilterResonance => self.filter_resonance,
+            SeqParam::FilterEnvAmount => self.filter_l.env_amount(),
+            SeqParam::RatioC          => self.ratio_c,
+            SeqParam::RatioA          => self.ratio_a,
+            SeqParam::RatioB1         => self.ratio_b1,
+            SeqParam::RatioB2         => self.ratio_b2,
+            SeqParam::Detune          => self.detune,
+            SeqParam::Feedback        => self.feedback,
+            SeqParam::Harm            => self.harm,
+            SeqParam::Lfo1Speed       => self.lfo1.speed(),
+            SeqParam::Lfo1Depth       => self.lfo1.depth(),
+            SeqParam::Lfo2Speed       => self.lfo2.speed(),
+            SeqParam::Lfo2Depth       => self.lfo2.depth(),
+            SeqParam::ModDepth(cell)  => self.mod_depth_matrix[cell],
+        }
+    }
+
+    /// Set a lockable parameter through its regular setter.
+    fn set_seq_param(&mut self, param: SeqParam, v: f32) {
+        match param {
+            SeqParam::CarrierMix      => self.set_carrier_mix(v),
+            SeqParam::Volume          => self.set_volume(v),
+            SeqParam::Pan             => self.set_pan(v),
+            SeqParam::Overdrive       => self.set_overdrive(v),
+            SeqParam::FilterCutoff    => self.set_filter_cutoff(v),
+            SeqParam::FilterResonance => self.set_filter_resonance(v),
+            SeqParam::FilterEnvAmount => self.set_filter_env_amount(v),
+            SeqParam::RatioC          => self.set_ratio_c(v),
+            SeqParam::RatioA          => self.set_ratio_a(v),
+            SeqParam::RatioB1         => self.set_ratio_b(v, self.ratio_b2),
+            SeqParam::RatioB2         => self.set_ratio_b(self.ratio_b1, v),
+            SeqParam::Detune          => self.set_detune(v),
+            SeqParam::Feedback        => self.set_feedback(v),
+            SeqParam::Harm            => self.set_harm(v),
+            SeqParam::Lfo1Speed       => self.set_lfo1_speed(v),
+            SeqParam::Lfo1Depth       => self.set_lfo1_depth(v),
+            SeqParam::Lfo2Speed       => self.set_lfo2_speed(v),
+            SeqParam::Lfo2Depth       => self.set_lfo2_depth(v),
+            SeqParam::ModDepth(cell)  => self.mod_depth_matrix[cell] = v.clamp(0.0, 127.0),
+        }
+    }
+
+    /// Apply an LFO setting to the global LFO `idx` (0/1) and every voice's copy.
+    fn update_lfo(&mut self, idx: usize, f: impl Fn(&mut Lfo)) {
+        f(if idx == 0 { &mut self.lfo1 } else { &mut self.lfo2 });
+        for v in &mut self.voices {
+            f(&mut v.lfos[idx]);
+        }
+    }
+
+    fn lfo_ref(&self, idx: usize) -> &Lfo {
+        if idx == 0 { &self.lfo1 } else { &self.lfo2 }
+    }
+
+    fn lfo_waveform_from_id(w: u32) -> Waveform {
+        match w {
+            0 => Waveform::Triangle,
+            1 => Waveform::Sine,
+            2 => Waveform::Square,
+            3 => Waveform::Sawtooth,
+            4 => Waveform::Exponential,
+            5 => Waveform::Ramp,
+            6 => Waveform::Random,
+            7 => Waveform::Custom,
+            8 => Waveform::Steps,
+            _ => Waveform::Triangle,
+        }
+    }
+
+    fn lfo_mode_from_id(m: u32) -> LfoMode {
+        match m {
+            0 => LfoMode::Free,
+            1 => LfoMode::Trigger,
+            2 => LfoMode::Hold,
+            3 => LfoMode::One,
+            4 => LfoMode::Half,
+            _ => LfoMode::Free,
+        }
+    }
+
+    /// Render one block of `BLOCK` interleaved stereo frames into `out`
+    /// (`out.len()` must be `BLOCK * 2`). This is the native entry point used
+    /// by tests and offline renders; `process_sample_array` wraps it for JS.
+    pub fn render_block(&mut self, out: &mut [f32]) {
+        assert_eq!(out.len(), BLOCK * 2, "render_block needs BLOCK * 2 samples");
+        self.effects.allocate();
+        for chunk in out.chunks_exact_mut(CONTROL_BLOCK * 2) {
+            self.render_control_block(chunk, true);
+        }
+    }
+
+    /// Free the effects' delay lines of a synth that renders dry inside
+    /// another engine; they come back on its next `render_block`.
+    pub(crate) fn release_effect_buffers(&mut self) {
+        self.effects.release_buffers();
+    }
+
+    /// Like `render_block`, but without the patch's effects chain, for
+    /// engines that run the synth into a shared effects bus.
+    pub fn render_block_dry(&mut self, out: &mut [f32]) {
+        assert_eq!(out.len(), BLOCK * 2, "render_block_dry needs BLOCK * 2 samples");
+        for chunk in out.chunks_exact_mut(CONTROL_BLOCK * 2) {
+            self.render_control_block(chunk, false);
+        }
+    }
+
+    /// Render `CONTROL_BLOCK` frames: evaluate LFOs and the mod matrix once,
+    /// then run the audio path with per-sample smoothed parameters.
+    fn render_control_block(&mut self, out: &mut [f32], with_effects: bool) {
         let dt = 1.0 / self.sample_rate;
-        use std::f32::consts::FRAC_PI_4;
+        let block_dt = dt * (out.len() / 2) as f32;
+
+        // Sequencer locks for steps starting in this block go over the patch
+        // before the base values are saved, so modulation stacks on them
+        self.seq.begin_block(self.transport.clock(), block_dt);
+        self.run_seq_events();
+        self.apply_seq_locks();
     
         // ─── Advance LFOs and apply their modulation ───
         // Save base values so LFO modulation doesn't accumulate across blocks
-        let base_mod_depth_a = self.mod_depth_a;
-        let base_mod_depth_b = self.mod_depth_b;
-        let base_feedback    = self.feedback;
-        let base_harm        = self.harm;
         let base_carrier_mix = self.carrier_mix;
         let base_overdrive   = self.overdrive;
         let base_pan         = self.pan;
         let base_volume      = self.volume;
-        let base_filter_cutoff    = self.filter_l.cutoff();
-        let base_filter_resonance = self.filter_l.resonance();
+        let base_filter_cutoff    = self.filter_cutoff;
+        let base_filter_resonance = self.filter_resonance;
+        let base_filter_env = [
+            self.filter_l.env_amount(),
+            self.filter_l.attack(),
+            self.filter_l.decay(),
+            self.filter_l.sustain(),
+            self.filter_l.release(),
+        ];
+
+        // LFOs run at control rate, so advance them by a whole control block
+        self.effects.clear_modulation();
+        self.arp.set_clock(self.transport.clock());
+        self.transport.advance(block_dt);
+        let clock = self.transport.clock();
+        self.lfo1.set_clock(clock);
+        self.lfo2.set_clock(clock);
+        let dests = [self.lfo1.destination, self.lfo2.destination];
+        let global = [self.lfo1.process(block_dt), self.lfo2.process(block_dt)];
+
+        let global_shapes = [self.lfo1.shape_value(), self.lfo2.shape_value()];
+
+        // Voice-level destinations: each voice uses its own LFO in per-voice mode
+        let mut synth_values = global;
+        let mut sources = ModSourceValues::default();
+        sources.set(ModSource::ModWheel, self.mod_wheel);
+        sources.set(ModSource::Aftertouch, self.aftertouch);
+        let mut synth_sources = sources.clone();
+        for (i, v) in self.voices.iter_mut().enumerate() {
+            // Per-voice LFOs run on last block's speed/depth modulation
+            let own = v.process_lfos(block_dt, clock, self.lfo_per_voice);
+            v.clear_modulation();
+            for n in 0..2 {
+                let (value, shape) = if self.lfo_per_voice[n] {
+                    (own[n], v.lfos[n].shape_value())
+                } else {
+                    (global[n], global_shapes[n])
+                };
+                if dests[n].is_voice_level() {
+                    v.apply_mod(dests[n], value);
+                }
+                sources.set(if n == 0 { ModSource::Lfo1 } else { ModSource::Lfo2 }, shape);
+                // Synth-wide destinations follow the most recent note
+                if self.lfo_per_voice[n] && i == self.last_voice {
+                    synth_values[n] = own[n];
+                }
+            }
 
-        let mod1 = self.lfo1.process(dt);
-        let mod2 = self.lfo2.process(dt);
-        self.apply_lfo_modulation(mod1, mod2);
+            // Matrix: voice destinations per voice, synth-wide ones from the last note
+            v.write_mod_sources(&mut sources);
+            self.mod_matrix.evaluate(&sources, |d, x| {
+                if d.is_voice_level() {
+                    v.apply_mod(d, x);
+                }
+            });
+            if i == self.last_voice {
+                synth_sources = sources.clone();
+            }
+        }
+        // The global LFOs follow the most recent note's speed/depth modulation
+        // from the next block on
+        if let Some(v) = self.voices.get(self.last_voice) {
+            let [[s1, d1], [s2, d2]] = [v.lfo_modulation(0), v.lfo_modulation(1)];
+            self.lfo1.set_modulation(s1, d1);
+            self.lfo2.set_modulation(s2, d2);
+        }
+        self.filter_env_curve_mod = 0.0;
+        for n in 0..2 {
+            if !dests[n].is_voice_level() {
+                self.apply_mod(synth_values[n], dests[n]);
+            }
+        }
+        let matrix = std::mem::take(&mut self.mod_matrix);
+        matrix.evaluate(&synth_sources, |d, x| {
+            if !d.is_voice_level() {
+                self.apply_mod(x, d);
+            }
+        });
+        self.mod_matrix = matrix;
+        self.filter_l.set_env_curve_offset(self.filter_env_curve_mod);
+        self.filter_r.set_env_curve_offset(self.filter_env_curve_mod);
 
         // Clamp modulated values to valid ranges
-        self.mod_depth_a = self.mod_depth_a.clamp(0.0, 127.0);
-        self.mod_depth_b = self.mod_depth_b.clamp(0.0, 127.0);
         self.carrier_mix = self.carrier_mix.clamp(0.0, 1.0);
         self.volume      = self.volume.clamp(0.0, 127.0);
     
-        for _ in 0..BLOCK {
-            // 1) Mix all voices
-            let mut l = 0.0;
-            let mut r = 0.0;
-            for v in &mut self.voices {
-                let (vl, vr) = v.generate_sample(
-                    dt,
-                    &self.mod_depth_matrix,
-                    self.carrier_mix,
-                );
-                l += vl;
-                r += vr;
+        let factor = self.oversampling.factor();
+        let sub_dt = dt / factor as f32;
+
+        // Layers render their own voices, filter and amp for this block and
+        // join the mix ahead of this patch's pan and volume. Notes the
+        // generators start mid-block reach them at the next control block
+        let mut layers_out = [0.0f32; CONTROL_BLOCK * 2];
+        for layer in &mut self.layers {
+            let Some(synth) = layer.synth.as_mut() else { continue };
+            let mut block = [0.0f32; CONTROL_BLOCK * 2];
+            synth.render_control_block(&mut block, false);
+            for (mixed, frame) in layers_out.chunks_exact_mut(2).zip(block.chunks_exact(2)) {
+                let level = layer.level_smoother.next(layer.level, dt) / 127.0;
+                mixed[0] += frame[0] * level;
+                mixed[1] += frame[1] * level;
             }
-    
-            // 2) Overdrive (tanh driver) — bypass entirely at zero to avoid tanh harmonic distortion
-            if self.overdrive > 0.0 {
-                let drive_gain = 1.0 + (self.overdrive / 127.0) * 9.0;
-                l = (l * drive_gain).tanh();
-                r = (r * drive_gain).tanh();
+        }
+
+        for (frame, layer) in out.chunks_exact_mut(2).zip(layers_out.chunks_exact(2)) {
+            // Strummed chord tones, arpeggiator and sequencer notes start and
+            // stop on the exact sample
+            if self.chord.is_enabled() {
+                self.chord.process(dt);
+                self.run_chord_events();
+            }
+            if self.arp.is_enabled() {
+                self.run_arp(dt);
+            }
+            if self.seq.is_enabled() {
+                self.seq.process(dt);
+                self.run_seq_events();
+            }
+
+            // Glide synth-level parameters towards this control block's values
+            let sm = &mut self.smoothers;
+            let carrier_mix = sm.carrier_mix.next(base_carrier_mix, self.carrier_mix, dt);
+            let overdrive   = sm.overdrive.next(base_overdrive, self.overdrive, dt);
+            let pan         = sm.pan.next(base_pan, self.pan, dt);
+            let volume      = sm.volume.next(base_volume, self.volume, dt);
+            let cutoff      = sm.cutoff.next(base_filter_cutoff, self.filter_cutoff, dt);
+            let resonance   = sm.resonance.next(base_filter_resonance, self.filter_resonance, dt);
+            if cutoff != self.filter_l.cutoff() {
+                self.filter_l.set_cutoff(cutoff);
+                self.filter_r.set_cutoff(cutoff);
             }
+            if resonance != self.filter_l.resonance() {
+                self.filter_l.set_resonance(resonance);
+                self.filter_r.set_resonance(resonance);
+            }
+            let drive_gain = 1.0 + (overdrive / 127.0) * 9.0;
+
+            // 1) Mix all voices (factor× per output sample when oversampling)
+            let mut os_l = [0.0f32; 4];
+            let mut os_r = [0.0f32; 4];
+            for k in 0..factor {
+                let mut l = 0.0;
+                let mut r = 0.0;
+                for v in &mut self.voices {
+                    let (vl, vr) = v.generate_sample(
+                        sub_dt,
+                        &self.mod_depth_matrix,
+                        carrier_mix,
+                    );
+                    l += vl;
+                    r += vr;
+                }
+
+                // 2) Overdrive (tanh driver) — bypass entirely at zero to avoid tanh harmonic distortion
+                if overdrive > 0.0 {
+                    l = (l * drive_gain).tanh();
+                    r = (r * drive_gain).tanh();
+                }
+                os_l[k] = l;
+                os_r[k] = r;
+            }
+
+            // Back to the host rate
+            let mut l = self.decimator_l.process(self.oversampling, &os_l[..factor]);
+            let mut r = self.decimator_r.process(self.oversampling, &os_r[..factor]);
     
             // 3) Multimode filter (separate L/R state)
             let lf = self.filter_l.process(l, dt);
             let rf = self.filter_r.process(r, dt);
     
-            // 4) Stereo pan (equal-power law)
-            let pan_norm = (self.pan / 63.0).clamp(-1.0, 1.0);
-            let angle = (pan_norm + 1.0) * FRAC_PI_4; // maps [-1..+1] → [0..π/2]
-            let pan_l = angle.cos();
-            let pan_r = angle.sin();
-            let lp = lf * pan_l;
-            let rp = rf * pan_r;
+            // 4) Stereo pan (equal-power law) and master volume, over the
+            //    layers too
+            let (pan_l, pan_r) = pan_gains(pan);
+            let vol = (volume / 127.0).clamp(0.0, 1.0);
+            l = (lf + layer[0]) * pan_l * vol;
+            r = (rf + layer[1]) * pan_r * vol;
     
-            // 5) Master volume
-            let vol = (self.volume / 127.0).clamp(0.0, 1.0);
-            l = lp * vol;
-            r = rp * vol;
-    
-            // 6) Global effects chain, gated by your new flags
-            if self.chorus_enabled {
-                let (cl, cr) = self.effects.chorus.process(l, r, dt);
-                l = cl; r = cr;
-            }
-            if self.delay_enabled {
-                let (dl, dr) = self.effects.delay.process(l, r, dt);
-                l = dl; r = dr;
-            }
-            if self.reverb_enabled {
-                let (rl, rr) = self.effects.reverb.process(l, r, dt);
-                l = rl; r = rr;
+            // 5) Insert effects chain, in the order the host set
+            if with_effects {
+                (l, r) = self.effects.process(l, r, dt);
             }
     
-            out.push(l);
-            out.push(r);
+            frame[0] = l;
+            frame[1] = r;
         }
     
         // Restore base values (undo LFO modulation so it doesn't accumulate)
-        self.mod_depth_a = base_mod_depth_a;
-        self.mod_depth_b = base_mod_depth_b;
-        self.feedback    = base_feedback;
-        self.harm        = base_harm;
         self.carrier_mix = base_carrier_mix;
         self.overdrive   = base_overdrive;
         self.pan         = base_pan;
         self.volume      = base_volume;
-        self.filter_l.set_cutoff(base_filter_cutoff);
-        self.filter_r.set_cutoff(base_filter_cutoff);
-        self.filter_l.set_resonance(base_filter_resonance);
-        self.filter_r.set_resonance(base_filter_resonance);
+        self.filter_cutoff    = base_filter_cutoff;
+        self.filter_resonance = base_filter_resonance;
+        let [env_amount, attack, decay, sustain, release] = base_filter_env;
+        for f in [&mut self.filter_l, &mut self.filter_r] {
+            f.set_env_amount(env_amount);
+            f.set_attack(attack);
+            f.set_decay(decay);
+            f.set_sustain(sustain);
+            f.set_release(release);
+        }
+        self.restore_seq_locks();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    /// A patch that exercises every random source: noise operators and a
+    /// Random-shape LFO on the filter.
+    fn noisy_synth(seed: u32) -> Synth {
+        let mut synth = Synth::new(SR);
+        synth.set_seed(seed);
+        synth.set_custom_routing(&[1, 0], &[0, 2]);
+        synth.set_mod_depth_matrix(&[0.0, 0.0, 0.0, 0.0, 90.0, 0.0, 0.0, 0.0,
+                                     0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        synth.set_operator_waveform(1, 4);  // white noise modulator
+        synth.set_operator_waveform(2, 16); // digital noise carrier
+        synth.set_lfo1_waveform(6);         // Random
+        synth.set_lfo1_speed(63.0);
+        synth.set_lfo1_depth(0.5);
+        synth.set_lfo1_destination(7);      // CarrierMix
+        synth
+    }
 
-        // Package into a Float32Array for JS
-        let array = Float32Array::new_with_length((BLOCK * 2) as u32);
-        array.copy_from(&out);
-        array
+    /// Play a fixed event list and return the rendered interleaved output.
+    fn render(seed: u32) -> Vec<f32> {
+        let mut synth = noisy_synth(seed);
+        let mut out = Vec::new();
+        let mut block = [0.0f32; BLOCK * 2];
+        for b in 0..40 {
+            match b {
+                0  => synth.note_on(60, 261.63),
+                5  => synth.note_on(64, 329.63),
+                12 => synth.note_off(60),
+                20 => synth.note_on(60, 261.63),
+                30 => { synth.note_off(60); synth.note_off(64); }
+                _  => {}
+            }
+            synth.render_block(&mut block);
+            out.extend_from_slice(&block);
+        }
+        out
+    }
+
+    #[test]
+    fn same_seed_renders_bit_identical() {
+        let a = render(1234);
+        let b = render(1234);
+        assert!(a.iter().any(|&s| s != 0.0), "render was silent");
+        assert!(
+            a.iter().zip(&b).all(|(x, y)| x.to_bits() == y.to_bits()),
+            "same seed produced different output"
+        );
+    }
+
+    #[test]
+    fn different_seed_renders_differently() {
+        assert_ne!(render(1), render(2));
+    }
+
+    /// Peak output over `blocks` blocks after a note-on at `velocity`.
+    /// Skips the first two blocks so parameter ramps have settled.
+    fn peak_after_note(synth: &mut Synth, velocity: f32, blocks: usize) -> f32 {
+        let mut block = [0.0f32; BLOCK * 2];
+        synth.note_on_velocity(60, 261.63, velocity);
+        let mut peak = 0.0f32;
+        for b in 0..blocks + 2 {
+            synth.render_block(&mut block);
+            if b >= 2 {
+                peak = block.iter().fold(peak, |p, s| p.max(s.abs()));
+            }
+        }
+        peak
+    }
+
+    /// A synth with the time-based effects off, so tails don't mask the dry signal.
+    fn dry_synth() -> Synth {
+        let mut synth = Synth::new(SR);
+        synth.set_chorus_enabled(false);
+        synth.set_delay_enabled(false);
+        synth.set_reverb_enabled(false);
+        synth
+    }
+
+    #[test]
+    fn velocity_slot_scales_volume() {
+        let peak = |velocity| {
+            let mut synth = dry_synth();
+            synth.set_volume(0.0);
+            synth.set_mod_slot(0, 7, 14, 1.0, 0); // velocity → volume
+            peak_after_note(&mut synth, velocity, 8)
+        };
+        let soft = peak(32.0);
+        let loud = peak(127.0);
+        assert!(soft > 0.0 && loud > soft * 2.0, "soft {} loud {}", soft, loud);
+    }
+
+    #[test]
+    fn aftertouch_via_gates_slot_and_modulation_does_not_accumulate() {
+        let mut synth = dry_synth();
+        synth.set_mod_slot(3, 10, 14, -1.0, 9); // mod wheel → -volume, via aftertouch
+        synth.set_mod_wheel(127.0);
+        assert!(peak_after_note(&mut synth, 127.0, 4) > 0.0, "via source at zero should gate the slot");
+        synth.set_aftertouch(127.0);
+        let mut block = [0.0f32; BLOCK * 2];
+        for _ in 0..3 {
+            synth.render_block(&mut block); // past the volume ramp
+        }
+        assert!(block.iter().all(|s| s.abs() < 1e-3), "full negative volume should silence");
+        assert_eq!(synth.volume, 127.0);
+    }
+
+    /// A volume jump ramps over the smoothing time instead of stepping.
+    #[test]
+    fn volume_changes_are_smoothed() {
+        let first_frame_after_cut = |ramp_ms| {
+            let mut synth = dry_synth();
+            synth.set_smoothing_time(0, ramp_ms);
+            peak_after_note(&mut synth, 127.0, 2);
+            synth.set_volume(0.0);
+            let mut block = [0.0f32; BLOCK * 2];
+            synth.render_block(&mut block);
+            block[0].abs().max(block[1].abs())
+        };
+        assert_eq!(first_frame_after_cut(0.0), 0.0);
+        assert!(first_frame_after_cut(5.0) > 0.0);
+    }
+
+    /// In poly mode a new voice glides from the last note, which another voice played.
+    #[test]
+    fn portamento_glides_from_last_note_across_voices() {
+        let start_freq = |mode, hold_first: bool| {
+            let mut synth = dry_synth();
+            synth.set_portamento_time(64.0);
+            synth.set_portamento_mode(mode);
+            synth.note_on(1, 220.0);
+            if !hold_first {
+                synth.note_off(1);
+            }
+            synth.note_on(2, 440.0);
+            synth.voices[synth.last_voice].operators[0].osc.base_frequency
+        };
+        assert!((start_freq(2, true) - 220.0).abs() < 0.01, "legato glide from the held note");
+        assert!((start_freq(2, false) - 440.0).abs() < 0.01, "legato mode: released note, no glide");
+        assert!((start_freq(1, false) - 220.0).abs() < 0.01, "always mode glides anyway");
+        assert!((start_freq(0, true) - 440.0).abs() < 0.01);
     }
-}
\ No newline at end of file
+
+    /// Oversampling runs the voices at a higher rate but must not change
+    /// pitch or glide time.
+    #[test]
+    fn oversampling_keeps_pitch_and_glide_time() {
+        let render = |factor| {
+            let mut synth = dry_synth();
+            synth.set_oversampling(factor);
+            synth.set_portamento_time(40.0);
+            synth.set_portamento_mode(1); // always glide
+            synth.note_on(1, 220.0);
+            synth.note_off(1);
+            synth.note_on(2, 440.0);
+            let mut block = [0.0f32; BLOCK * 2];
+            let mut glide = Vec::new();
+            let mut left: Vec<f32> = Vec::new();
+            for _ in 0..750 { // 2 s
+                synth.render_block(&mut block);
+                glide.push(synth.voices[synth.last_voice].operators[0].osc.base_frequency);
+                left.extend(block.iter().step_by(2));
+            }
+            // Upward zero crossings in the last second, after the glide
+            let crossings = left[SR as usize..].windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count();
+            (glide, crossings)
+        };
+        let (glide_1x, crossings_1x) = render(1);
+        assert!(glide_1x[10] < 430.0, "still gliding after 10 blocks");
+        assert!((crossings_1x as f32 - 440.0).abs() <= 2.0, "{} crossings", crossings_1x);
+        for factor in [2, 4] {
+            let (glide, crossings) = render(factor);
+            for (a, b) in glide.iter().zip(&glide_1x) {
+                assert!((a - b).abs() < 0.5, "{}x glide {} vs {}", factor, a, b);
+            }
+            assert_eq!(crossings, crossings_1x, "{}x pitch", factor);
+        }
+    }
+
+    /// Kick-style pitch drops in the 20–50 ms range can be set directly.
+    #[test]
+    fn pitch_envelope_drops_in_tens_of_milliseconds() {
+        let mut synth = dry_synth();
+        synth.set_pitch_env(24.0, 0.0, 0.0, 0.0, 0.0, 0.03, 0.0, 0.0);
+        synth.note_on(1, 55.0);
+        let mut block = [0.0f32; BLOCK * 2];
+        let pitch: Vec<f32> = (0..20)
+            .map(|_| {
+                synth.render_block(&mut block);
+                synth.voices[synth.last_voice].operators[0].osc.base_frequency
+            })
+            .collect();
+        // Blocks are 128 samples, about 2.7 ms
+        assert!(pitch[3] > 55.0 * 2.0, "10 ms in, still {} Hz", pitch[3]);
+        assert!((pitch[12] - 55.0).abs() < 0.01, "32 ms in, {} Hz", pitch[12]);
+    }
+
+    /// The pitch envelope's depth is a modulation destination like any other.
+    #[test]
+    fn mod_wheel_can_scale_the_pitch_envelope_away() {
+        let mut synth = dry_synth();
+        synth.set_pitch_env(24.0, 0.0, 0.0, 0.0, 0.0, 0.03, 0.0, 0.0);
+        synth.set_mod_slot(0, 10, 31, -1.0, 0); // mod wheel → pitch env depth
+        synth.set_mod_wheel(127.0);
+        synth.note_on(1, 55.0);
+        let mut block = [0.0f32; BLOCK * 2];
+        for _ in 0..3 {
+            synth.render_block(&mut block);
+        }
+        let freq = synth.voices[synth.last_voice].operators[0].osc.base_frequency;
+        assert!((freq - 55.0).abs() < 0.01, "{} Hz", freq);
+    }
+
+    /// Matrix slots can drive an LFO's speed; a stopped LFO starts moving.
+    #[test]
+    fn matrix_modulates_lfo_speed() {
+        let lfo_moves = |wheel: f32| {
+            let mut synth = dry_synth();
+            synth.set_lfo1_waveform(3); // saw
+            synth.set_lfo1_depth(1.0);
+            synth.set_lfo1_speed(0.0);
+            synth.set_mod_slot(0, 10, 24, 0.5, 0); // mod wheel → LFO1 speed
+            synth.set_mod_wheel(wheel);
+            let mut block = [0.0f32; BLOCK * 2];
+            synth.render_block(&mut block);
+            let before = synth.lfo1.current();
+            synth.render_block(&mut block);
+            synth.lfo1.current() != before
+        };
+        assert!(!lfo_moves(0.0));
+        assert!(lfo_moves(127.0));
+    }
+
+    /// Bass left, pad right, with a bell stacked on the pad's upper range:
+    /// each note reaches the voice pools whose zones cover it.
+    #[test]
+    fn splits_and_layers_dispatch_to_their_voice_pools() {
+        let mut synth = dry_synth(); // the bass
+        synth.set_key_range(0, 59);
+        synth.set_transpose(-12.0);
+        let mut pad = dry_synth();
+        pad.set_key_range(60, 127);
+        let mut bell = dry_synth();
+        bell.set_key_range(84, 72); // ends in either order
+        bell.set_transpose(12.0);
+        assert_eq!(synth.add_layer(pad), 0);
+        assert_eq!(synth.add_layer(bell), 1);
+        let held = |synth: &mut Synth| -> Vec<usize> {
+            let own = synth.voices.iter().filter(|v| v.is_held()).count();
+            vec![own, synth.layer_mut(0).unwrap().held_notes(), synth.layer_mut(1).unwrap().held_notes()]
+        };
+
+        synth.note_on(48, 130.81);
+        assert_eq!(held(&mut synth), vec![1, 0, 0], "C3: bass only");
+        let bass = synth.voices.iter().find(|v| v.is_held()).unwrap();
+        assert!((bass.note_frequency() - 65.41).abs() < 0.01, "an octave down");
+        synth.note_on(64, 329.63);
+        assert_eq!(held(&mut synth), vec![1, 1, 0], "E4: pad");
+        synth.note_on(76, 659.26);
+        assert_eq!(held(&mut synth), vec![1, 2, 1], "E5: pad and bell");
+        assert_eq!(synth.held_notes(), 4);
+        let bell = synth.layer_mut(1).unwrap().voices.iter().find(|v| v.is_held()).unwrap();
+        assert!((bell.note_frequency() - 1318.51).abs() < 0.05, "an octave up");
+        for id in [48, 64, 76] {
+            synth.note_off(id);
+        }
+        assert_eq!(synth.held_notes(), 0);
+
+        for _ in 2..MAX_LAYERS {
+            assert!(synth.add_layer(dry_synth()) >= 0);
+        }
+        assert_eq!(synth.add_layer(dry_synth()), -1, "full");
+        assert!(synth.remove_layer(MAX_LAYERS).is_none());
+        assert!(synth.remove_layer(0).is_some());
+        assert_eq!(synth.num_layers(), MAX_LAYERS - 1);
+    }
+
+    /// Layers sound through the instrument's output at their level, and go
+    /// quiet while taken out for editing.
+    #[test]
+    fn layers_mix_at_their_level() {
+        let peak = |synth: &mut Synth| {
+            let mut block = [0.0f32; BLOCK * 2];
+            (0..4).fold(0.0f32, |m, _| {
+                synth.render_block(&mut block);
+                block.iter().fold(m, |m, s| m.max(s.abs()))
+            })
+        };
+        let mut synth = dry_synth();
+        synth.set_key_range(0, 0); // own voices play nothing here
+        synth.add_layer(dry_synth());
+        synth.note_on(60, 261.63);
+        let full = peak(&mut synth);
+        assert!(full > 0.05, "layer plays through the instrument: {}", full);
+
+        synth.set_layer_level(0, 127.0 / 4.0);
+        peak(&mut synth); // past the level ramp
+        assert!((peak(&mut synth) / full - 0.25).abs() < 0.01);
+
+        let layer = synth.take_layer(0).unwrap();
+        assert_eq!(peak(&mut synth), 0.0);
+        synth.set_layer(0, layer);
+        assert!(peak(&mut synth) > 0.0, "the layer keeps its held note");
+    }
+
+    /// The instrument's pan, volume and note generators cover its layers,
+    /// and layers can't hold layers of their own.
+    #[test]
+    fn layers_follow_the_instrument_amp_and_arpeggiator() {
+        let render = |synth: &mut Synth| {
+            let mut block = [0.0f32; BLOCK * 2];
+            (0..4).fold((0.0f32, 0.0f32), |(l, r), _| {
+                synth.render_block(&mut block);
+                block.chunks_exact(2).fold((l, r), |(l, r), f| (l.max(f[0].abs()), r.max(f[1].abs())))
+            })
+        };
+        let mut synth = dry_synth();
+        synth.set_key_range(0, 0); // own voices play nothing here
+        let mut nested = dry_synth();
+        assert_eq!(nested.add_layer(dry_synth()), 0);
+        synth.add_layer(nested);
+        assert_eq!(synth.layer_mut(0).unwrap().num_layers(), 0, "flattened");
+        assert_eq!(synth.layer_mut(0).unwrap().add_layer(dry_synth()), -1);
+
+        synth.set_pan(-63.0);
+        synth.note_on(60, 261.63);
+        render(&mut synth); // past the pan ramp
+        let (l, r) = render(&mut synth);
+        assert!(l > 0.05 && r < 1e-4, "the layer pans hard left: {} {}", l, r);
+        synth.set_volume(0.0);
+        render(&mut synth);
+        assert_eq!(render(&mut synth), (0.0, 0.0), "and follows the volume");
+        synth.note_off(60);
+
+        synth.set_arp_enabled(true);
+        synth.note_on(64, 329.63);
+        synth.note_on(60, 261.63);
+        render(&mut synth);
+        let layer = synth.layer_mut(0).unwrap();
+        let held: Vec<_> = layer.voices.iter().filter(|v| v.is_held()).map(|v| v.get_note_id().unwrap() & 0xFFFF).collect();
+        assert_eq!(held, vec![60], "the arpeggiator steps the layer");
+    }
+
+    /// Dry synths and layers carry no delay lines until they play on their
+    /// own.
+    #[test]
+    fn layers_and_dry_synths_skip_effect_buffers() {
+        let mut synth = Synth::new(SR);
+        assert!(synth.effects.is_allocated());
+        synth.add_layer(Synth::new(SR));
+        let layer = synth.take_layer(0).unwrap();
+        assert!(!layer.effects.is_allocated());
+
+        let mut dry = Synth::new_dry(SR);
+        assert!(!dry.effects.is_allocated());
+        let mut block = [0.0f32; BLOCK * 2];
+        dry.render_block_dry(&mut block);
+        assert!(!dry.effects.is_allocated());
+        dry.render_block(&mut block);
+        assert!(dry.effects.is_allocated());
+    }
+
+    /// The arpeggiator plays held keys one at a time through the voices.
+    #[test]
+    fn arpeggiator_steps_through_held_notes() {
+        let mut synth = dry_synth();
+        synth.set_arp_enabled(true);
+        synth.note_on(64, 329.63);
+        synth.note_on(60, 261.63);
+        let held = |synth: &Synth| {
+            synth.voices.iter().filter(|v| v.is_held()).map(|v| v.get_note_id().unwrap() & 0xFFFF).collect::<Vec<_>>()
+        };
+        let mut block = [0.0f32; BLOCK * 2];
+        synth.render_block(&mut block);
+        assert_eq!(held(&synth), vec![60], "lowest note first, one at a time");
+        // 1/16 at 120 bpm is 6000 samples: the second step is in block 47
+        for _ in 0..47 {
+            synth.render_block(&mut block);
+        }
+        assert_eq!(held(&synth), vec![64]);
+        synth.note_off(60);
+        synth.note_off(64);
+        synth.set_arp_enabled(false);
+        assert!(held(&synth).is_empty());
+    }
+
+    /// Depth A/B locks set the depth matrix cells the voices actually read.
+    #[test]
+    fn depth_group_locks_drive_the_depth_matrix() {
+        let render = |lock: Option<u32>| {
+            let mut synth = dry_synth();
+            synth.set_custom_routing(&[1, 0], &[0, 2]); // A → C
+            synth.set_seq_trig(0, 57, 100.0, 1.0, 0.0);
+            if let Some(id) = lock {
+                synth.set_seq_lock(0, id, 127.0);
+            }
+            synth.set_seq_enabled(true);
+            synth.set_playing(true);
+            let mut block = [0.0f32; BLOCK * 2];
+            let mut out = Vec::new();
+            for _ in 0..4 {
+                synth.render_block(&mut block);
+                out.extend_from_slice(&block);
+            }
+            assert!(synth.mod_depth_matrix.iter().all(|&d| d == 0.0), "locks restore the patch");
+            out
+        };
+        let plain = render(None);
+        assert_ne!(render(Some(0)), plain, "depth A covers A → C");
+        assert_eq!(render(Some(1)), plain, "depth B only covers B1/B2");
+    }
+
+    /// Sequencer trigs play the voices and locks revert after their step.
+    #[test]
+    fn sequencer_plays_trigs_and_reverts_locks() {
+        let mut synth = dry_synth();
+        synth.set_seq_trig(0, 69, 100.0, 1.0, 0.0);
+        synth.set_seq_lock(0, 19, 800.0); // filter cutoff
+        synth.set_seq_lock(0, 68, 90.0);  // mod depth cell 1 → 0
+        synth.set_seq_trig(1, 57, 100.0, 1.0, 0.0);
+        synth.set_seq_pattern(&synth.get_seq_pattern());
+        synth.set_seq_enabled(true);
+        synth.set_playing(true);
+
+        let mut block = [0.0f32; BLOCK * 2];
+        synth.render_block(&mut block);
+        synth.render_block(&mut block); // past the cutoff ramp
+        assert_eq!(synth.filter_l.cutoff(), 800.0);
+        let held = synth.voices.iter().find(|v| v.is_held()).expect("step 0 plays");
+        assert!((held.operators[0].osc.base_frequency - 440.0).abs() < 0.01);
+        // Locks lie over the patch instead of changing it
+        assert_eq!(synth.filter_cutoff, 20000.0);
+        assert_eq!(synth.mod_depth_matrix[4], 0.0);
+
+        // A host edit under a lock waits for the lock to lift, then plays
+        synth.set_filter_cutoff(1000.0);
+        synth.render_block(&mut block);
+        assert_eq!(synth.filter_l.cutoff(), 800.0);
+
+        // Step 1 (6000 samples in) has no locks: back to the patch
+        for _ in 0..46 {
+            synth.render_block(&mut block);
+        }
+        assert_eq!(synth.filter_l.cutoff(), 1000.0);
+        assert_eq!(synth.filter_cutoff, 1000.0);
+
+        synth.set_playing(false);
+        synth.render_block(&mut block);
+        assert!(synth.voices.iter().all(|v| !v.is_held()), "notes stop with the transport");
+    }
+
+    /// A captured chord plays from one key and stops with it.
+    #[test]
+    fn chord_memory_plays_captured_chord() {
+        let mut synth = dry_synth();
+        synth.note_on(60, 261.63);
+        synth.note_on(63, 311.13);
+        synth.note_on(67, 392.0);
+        synth.capture_chord();
+        for id in [60, 63, 67] {
+            synth.note_off(id);
+        }
+        assert_eq!(synth.get_chord_intervals(), vec![0, 3, 7]);
+
+        synth.set_chord_enabled(true);
+        synth.note_on(69, 440.0);
+        let mut held: Vec<f32> = synth.voices.iter()
+            .filter(|v| v.is_held())
+            .map(|v| v.note_frequency())
+            .collect();
+        held.sort_by(f32::total_cmp);
+        assert_eq!(held.len(), 3);
+        assert!((held[1] - 523.25).abs() < 0.1 && (held[2] - 659.26).abs() < 0.1, "{:?}", held);
+        synth.note_off(69);
+        assert!(synth.voices.iter().all(|v| !v.is_held()));
+    }
+}
@@ -0,0 +1,157 @@
+// src/transport.rs
+//! Tempo clock and host transport.
+//!
+//! The host sets BPM, play state and (optionally, every block) its song
+//! position in quarter-note beats. While playing the synth advances the
+//! position itself between host updates. Synced LFOs read a `Clock` snapshot.
+
+/// Straight note lengths in beats (quarter notes, 4/4), slowest first:
+/// 16 bars … 1/64.
+const STRAIGHT_DIVISIONS: [f32; 11] = [
+    64.0, 32.0, 16.0, 8.0, 4.0, // 16, 8, 4, 2, 1 bars
+    2.0, 1.0, 0.5, 0.25,        // 1/2, 1/4, 1/8, 1/16
+    0.125, 0.0625,              // 1/32, 1/64
+];
+
+/// Sync division index of a 1/16 note.
+pub const SIXTEENTH: usize = 25;
+
+/// Number of sync divisions (each straight length dotted, straight, triplet).
+pub const NUM_DIVISIONS: usize = STRAIGHT_DIVISIONS.len() * 3;
+
+/// Every straight, dotted and triplet length in beats, sorted slowest first
+/// at compile time.
+const DIVISIONS: [f32; NUM_DIVISIONS] = {
+    let mut all = [0.0f32; NUM_DIVISIONS];
+    let mut i = 0;
+    while i < STRAIGHT_DIVISIONS.len() {
+        let straight = STRAIGHT_DIVISIONS[i];
+        all[i * 3] = straight * 1.5;           // dotted
+        all[i * 3 + 1] = straight;
+        all[i * 3 + 2] = straight * 2.0 / 3.0; // triplet
+        i += 1;
+    }
+    // Insertion sort, longest first: each dotted length outlasts the triplet
+    // of the next straight length up
+    let mut i = 1;
+    while i < NUM_DIVISIONS {
+        let mut j = i;
+        while j > 0 && all[j] > all[j - 1] {
+            let t = all[j];
+            all[j] = all[j - 1];
+            all[j - 1] = t;
+            j -= 1;
+        }
+        i += 1;
+    }
+    all
+};
+
+/// Cycle length in beats of sync division `index`, with every straight,
+/// dotted and triplet length sorted slowest first (0 = 16 bars dotted,
+/// last = 1/64 triplet), so higher indices are always faster.
+pub fn division_beats(index: usize) -> f32 {
+    DIVISIONS[index.min(NUM_DIVISIONS - 1)]
+}
+
+/// What a synced LFO needs to know about time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Clock {
+    pub bpm: f32,
+    /// Song position in beats while the transport plays, `None` when stopped.
+    pub position: Option<f64>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self { bpm: 120.0, position: None }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Transport {
+    bpm: f32,
+    playing: bool,
+    position: f64, // beats
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self { bpm: 120.0, playing: false, position: 0.0 }
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.clamp(20.0, 999.0);
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// Jump to `beats` (host PPQ position in quarter notes).
+    pub fn set_position(&mut self, beats: f64) {
+        self.position = beats.max(0.0);
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Move the song position on by `dt` seconds if playing.
+    pub fn advance(&mut self, dt: f32) {
+        if self.playing {
+            self.position += dt as f64 * self.bpm as f64 / 60.0;
+        }
+    }
+
+    pub fn clock(&self) -> Clock {
+        Clock {
+            bpm: self.bpm,
+            position: self.playing.then_some(self.position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divisions_run_slowest_to_fastest() {
+        assert_eq!(division_beats(0), 96.0); // 16 bars dotted
+        assert_eq!(division_beats(1), 64.0); // 16 bars
+        assert!((0..NUM_DIVISIONS).any(|i| division_beats(i) == 1.0)); // 1/4
+        assert_eq!(division_beats(SIXTEENTH), 0.25);
+        assert!((division_beats(NUM_DIVISIONS - 1) - 0.0625 * 2.0 / 3.0).abs() < 1e-7);
+        for i in 1..NUM_DIVISIONS {
+            assert!(division_beats(i) < division_beats(i - 1));
+        }
+    }
+
+    #[test]
+    fn advances_only_while_playing() {
+        let mut t = Transport::new();
+        t.set_bpm(120.0);
+        t.advance(1.0);
+        assert_eq!(t.position(), 0.0);
+        assert_eq!(t.clock().position, None);
+        t.set_playing(true);
+        t.advance(1.0);
+        assert!((t.position() - 2.0).abs() < 1e-9);
+        assert_eq!(t.clock().position, Some(t.position()));
+    }
+}
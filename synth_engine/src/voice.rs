@@ -1,12 +1,51 @@
+use std::sync::Arc;
+
 use crate::oscillator::WaveType;
+use crate::wavetable::Wavetable;
 
 use crate::operator::FMOperator;
 use crate::algorithm::FMAlgorithm;
-use crate::envelope::Envelope;         // Carrier envelope
+use crate::envelope::{Envelope, LoopMode, RetriggerMode};         // Carrier envelope
 use crate::mod_envelope::ModEnvelope;   // Modulator envelope
 use crate::noop_envelope::NoopEnvelope;
-use crate::lfo::LfoDestination;
+use crate::dx_envelope::DxEnvelope;
+use crate::pitch_envelope::PitchEnvelope;
+use crate::portamento::{GlideType, Portamento, PortamentoMode};
+use crate::lfo::Lfo;
+use crate::mod_matrix::{ModDestination, ModSource, ModSourceValues, OperatorParam};
+use crate::operator::OperatorMod;
+use crate::rng::{mix_seed, Rng};
+use crate::smoother::Smoother;
+use crate::transport::Clock;
+
+/// Fade time of a choked voice, in seconds (short enough to cut, long
+/// enough not to click).
+const CHOKE_TIME: f32 = 0.003;
+
+/// Ramps the voice's block-rate modulation to each block's value, one
+/// sample at a time, so fast LFOs don't step every control block.
+struct VoiceModRamps {
+    depth: [Smoother; 16],
+    amp: [Smoother; 4],
+    pitch: Smoother,
+    wavetable: Smoother,
+    pitch_env: [Smoother; 4],
+    pitch_env_depth: Smoother,
+}
 
+impl VoiceModRamps {
+    fn new(sample_rate: f32) -> Self {
+        let ramp = Smoother::block_ramp(0.0, sample_rate);
+        Self {
+            depth: [ramp; 16],
+            amp: [ramp; 4],
+            pitch: Smoother::block_ramp(1.0, sample_rate),
+            wavetable: ramp,
+            pitch_env: [ramp; 4],
+            pitch_env_depth: ramp,
+        }
+    }
+}
 
 pub struct FMVoice {
     pub operators: [FMOperator; 4],
@@ -20,11 +59,34 @@ pub struct FMVoice {
     last_output_r: f32,
     octave_shift: i32,
     sample_counter: u64,
-    portamento_time: f32,      // 0-127 (0=instant, 127=slowest)
-    current_frequency: f32,     // Current frequency during glide
-    target_frequency: f32,      // Target frequency to glide towards
-    sample_rate: f32,           // Needed for glide rate calculation
+    portamento: Portamento,     // Glide from the previous note (log domain)
     pitch_bend_multiplier: f32, // Frequency multiplier from pitch bend (1.0 = no bend)
+    wavetable_lfo: f32,         // LFO offset added to every operator's wavetable position
+    seed: u32,                  // Per-voice seed for operator noise
+    trigger_count: u32,         // Notes played on this voice (decorrelates successive hits)
+    pub lfos: [Lfo; 2],         // Key-synced copies of LFO1/LFO2 (used in per-voice mode)
+    base_ratios: [f32; 4],      // User-set operator ratios (before detune and modulation)
+    detune: f32,                // Global detune knob as set by apply_detune
+    detune_offset: f32,         // Modulation added to the detune knob
+    detune_factor: f32,         // Symmetric detune spread from apply_detune
+    amp_base: [f32; 4],         // User-set amp attack, decay, sustain, release
+    velocity: f32,              // Note-on velocity 0..1
+    random_per_note: f32,       // Bipolar value drawn at each note-on
+    pitch_mod_multiplier: f32,  // Frequency multiplier from Pitch modulation
+    depth_offsets: [f32; 16],   // Modulation added to each mod_depth_matrix cell
+    amp_offsets: [f32; 4],      // Modulation added to amp attack, decay, sustain, release
+    amp_curve_offset: f32,      // Modulation added to the amp envelope curves
+    mod_env_curve_offsets: [f32; 4], // Modulation added to each operator's mod envelope curves
+    lfo_offsets: [[f32; 2]; 2], // Modulation added to each LFO's speed and depth
+    glide_offset: f32,          // Modulation added to the glide time (s)
+    pitch_env_offsets: [f32; 4], // Modulation added to each operator's pitch envelope amount
+    pitch_env_depth_offset: f32, // Modulation scaling every pitch envelope amount (+1 = double)
+    mod_ramps: VoiceModRamps,   // Interpolate the offsets above across the block
+    harm: f32,                  // Global harm as set by update_harm
+    harm_offset: f32,           // Modulation added to the global harm
+    pitch_env: PitchEnvelope,   // Per-voice pitch envelope (semitones)
+    pitch_env_amounts: [f32; 4], // How much of the pitch envelope each operator follows
+    choke_gain: Option<f32>,    // Fade-out gain once choked, `None` while playing normally
 }
 
 impl FMVoice {
@@ -41,27 +103,158 @@ impl FMVoice {
     }
   
 
- /// Called by Synth::route_lfo for voice-specific params
- pub fn apply_lfo(&mut self, dest: LfoDestination, value: f32) {
-    match dest {
-        LfoDestination::RatioA => self.set_ratio_a(self.operators[1].frequency_ratio + value),
-        LfoDestination::RatioB => {
-            // both operator 2 (B1) and operator 3 (B2)
-            let new_b1 = self.operators[2].frequency_ratio + value;
-            let new_b2 = self.operators[3].frequency_ratio + value;
-            self.set_ratio_b(new_b1, new_b2);
+    /// Add a modulation offset to a voice-level destination. Offsets stack on
+    /// top of the user values restored by `clear_modulation`, so they never
+    /// accumulate across blocks.
+    pub fn apply_mod(&mut self, dest: ModDestination, value: f32) {
+        match dest {
+            // Depth A covers connections from C/A, depth B those from B1/B2
+            ModDestination::ModDepthA => self.offset_depths(|src| src <= 1, value),
+            ModDestination::ModDepthB => self.offset_depths(|src| src >= 2, value),
+            ModDestination::Connection(cell) => {
+                if let Some(d) = self.depth_offsets.get_mut(cell) {
+                    *d += value;
+                }
+            }
+            ModDestination::Feedback => {
+                for op in self.operators.iter_mut().filter(|op| op.is_modulator) {
+                    op.modulation.feedback += value;
+                }
+            }
+            ModDestination::Harm => {
+                let before = Self::harm_split(self.harm + self.harm_offset);
+                self.harm_offset += value;
+                let after = Self::harm_split(self.harm + self.harm_offset);
+                for (op, (b, a)) in self.operators.iter_mut().zip(before.iter().zip(after.iter())) {
+                    op.modulation.harm += a - b;
+                }
+            }
+            ModDestination::Operator(op, param) if op < 4 => match param {
+                OperatorParam::Level    => self.operators[op].modulation.level += value,
+                OperatorParam::Ratio    => self.offset_ratio(op, value),
+                OperatorParam::Detune   => self.operators[op].modulation.detune += value,
+                OperatorParam::Feedback => self.operators[op].modulation.feedback += value,
+                OperatorParam::Harm     => self.operators[op].modulation.harm += value,
+                OperatorParam::Morph    => self.operators[op].modulation.morph += value,
+                OperatorParam::EnvCurve => self.mod_env_curve_offsets[op] += value,
+                OperatorParam::PitchEnv => self.pitch_env_offsets[op] += value,
+            },
+
+            ModDestination::RatioA => self.offset_ratio(1, value),
+            ModDestination::RatioB => {
+                // both operator 2 (B1) and operator 3 (B2)
+                self.offset_ratio(2, value);
+                self.offset_ratio(3, value);
+            }
+            ModDestination::RatioC => self.offset_ratio(0, value),
+            ModDestination::Detune => {
+                let before = Self::detune_spread(self.detune + self.detune_offset);
+                self.detune_offset += value;
+                let change = Self::detune_spread(self.detune + self.detune_offset) - before;
+                for op in 0..4 {
+                    let sign = if op < 2 { 1.0 } else { -1.0 };
+                    self.offset_ratio(op, self.base_ratios[op] * sign * change);
+                }
+            }
+
+            ModDestination::AmpAttack  => self.amp_offsets[0] += value,
+            ModDestination::AmpDecay   => self.amp_offsets[1] += value,
+            ModDestination::AmpSustain => self.amp_offsets[2] += value,
+            ModDestination::AmpRelease => self.amp_offsets[3] += value,
+
+            ModDestination::AmpEnvCurve => self.amp_curve_offset += value,
+
+            ModDestination::WavetablePosition => self.wavetable_lfo += value,
+            ModDestination::Pitch => self.pitch_mod_multiplier *= 2_f32.powf(value / 12.0),
+            ModDestination::PitchEnvDepth => self.pitch_env_depth_offset += value,
+            ModDestination::Portamento => self.glide_offset += value,
+
+            ModDestination::LfoSpeed(n) if n < 2 => self.lfo_offsets[n][0] += value,
+            ModDestination::LfoDepth(n) if n < 2 => self.lfo_offsets[n][1] += value,
+
+            _ => {}, // Other destinations handled at synth-level
         }
+    }
 
-        LfoDestination::RatioC => self.set_ratio_c(self.operators[0].frequency_ratio + value),
+    /// Fill the per-note matrix sources (velocity, key, mod envelopes, random).
+    /// LFO and controller sources are filled in by the synth.
+    pub fn write_mod_sources(&self, sources: &mut ModSourceValues) {
+        sources.set(ModSource::Velocity, self.velocity);
+        // ±1 at ±5 octaves around middle C
+        let key = (self.portamento.target_frequency() / 261.63).log2() / 5.0;
+        sources.set(ModSource::Key, key.clamp(-1.0, 1.0));
+        sources.set(ModSource::RandomPerNote, self.random_per_note);
+        for (i, env) in self.operator_mod_envs.iter().enumerate() {
+            sources.set(ModSource::ModEnv(i), env.level);
+        }
+    }
 
-        LfoDestination::AmpAttack  => self.amp_envelope.attack  = (self.amp_envelope.attack  + value).max(0.0),
-        LfoDestination::AmpDecay   => self.amp_envelope.decay   = (self.amp_envelope.decay   + value).max(0.0),
-        LfoDestination::AmpSustain => self.amp_envelope.sustain = (self.amp_envelope.sustain + value).clamp(0.0,1.0),
-        LfoDestination::AmpRelease => self.amp_envelope.release = (self.amp_envelope.release + value).max(0.0),
+    /// Velocity (0..1) for the next `note_on`.
+    pub fn set_velocity(&mut self, velocity: f32) {
+        self.velocity = velocity.clamp(0.0, 1.0);
+    }
 
-        _ => {}, // Other destinations handled at synth-level
+    fn offset_depths(&mut self, group: impl Fn(usize) -> bool, value: f32) {
+        for (cell, d) in self.depth_offsets.iter_mut().enumerate() {
+            if group(cell / 4) {
+                *d += value;
+            }
+        }
+    }
+
+    fn offset_ratio(&mut self, op: usize, value: f32) {
+        self.operators[op].modulation.ratio += value;
+    }
+
+    /// Restore every modulatable voice parameter to its user value.
+    /// Called at the start of each control block, before modulation is re-applied.
+    pub fn clear_modulation(&mut self) {
+        self.amp_offsets = [0.0; 4];
+        self.amp_curve_offset = 0.0;
+        self.mod_env_curve_offsets = [0.0; 4];
+        self.lfo_offsets = [[0.0; 2]; 2];
+        self.glide_offset = 0.0;
+        self.pitch_env_offsets = [0.0; 4];
+        self.pitch_env_depth_offset = 0.0;
+        self.wavetable_lfo = 0.0;
+        self.pitch_mod_multiplier = 1.0;
+        self.depth_offsets = [0.0; 16];
+        self.harm_offset = 0.0;
+        self.detune_offset = 0.0;
+        for op in &mut self.operators {
+            op.modulation = OperatorMod::default();
+        }
+    }
+
+    /// Push base ratio × detune spread for operator `op` to the operator.
+    /// A-group (ops 0, 1) goes sharp, B-group (ops 2, 3) goes flat.
+    fn update_ratio(&mut self, op: usize) {
+        let spread = if op < 2 { 1.0 + self.detune_factor } else { 1.0 - self.detune_factor };
+        self.operators[op].set_frequency_ratio(self.base_ratios[op] * spread);
+    }
+
+    /// Advance the key-synced LFOs switched on in `per_voice` by `dt`
+    /// seconds; the others stay put and read 0. Speed and depth modulation
+    /// applied since the last `clear_modulation` takes effect here, so call
+    /// this before clearing.
+    pub fn process_lfos(&mut self, dt: f32, clock: Clock, per_voice: [bool; 2]) -> [f32; 2] {
+        let mut out = [0.0; 2];
+        for (((lfo, value), on), [speed, depth]) in
+            self.lfos.iter_mut().zip(&mut out).zip(per_voice).zip(self.lfo_offsets)
+        {
+            if on {
+                lfo.set_clock(clock);
+                lfo.set_modulation(speed, depth);
+                *value = lfo.process(dt);
+            }
+        }
+        out
+    }
+
+    /// Speed and depth offsets this voice's modulation put on LFO `n`.
+    pub fn lfo_modulation(&self, n: usize) -> [f32; 2] {
+        self.lfo_offsets[n]
     }
-}
 
 
 pub fn new(sample_rate: f32, default_algo: FMAlgorithm) -> Self {
@@ -69,6 +262,7 @@ pub fn new(sample_rate: f32, default_algo: FMAlgorithm) -> Self {
     let default_carrier_env = Envelope::from_digitone(0, 0, 127, 10);
     // Voice amp envelope: same clean init
     let amp_env = Envelope::from_digitone(0, 0, 127, 10);
+    let amp_base = [amp_env.attack, amp_env.decay, amp_env.sustain, amp_env.release];
 
     // Only the carrier operators need amplitude envelopes now
     Self {
@@ -99,44 +293,75 @@ pub fn new(sample_rate: f32, default_algo: FMAlgorithm) -> Self {
         last_output_l: 0.0,
         last_output_r: 0.0,
         sample_counter: 0,
-        portamento_time: 0.0,
-        current_frequency: 440.0,
-        target_frequency: 440.0,
-        sample_rate,
+        portamento: Portamento::new(),
         pitch_bend_multiplier: 1.0,  // No bend initially
+        wavetable_lfo: 0.0,
+        seed: 0,
+        trigger_count: 0,
+        lfos: [Lfo::new(sample_rate), Lfo::new(sample_rate)],
+        base_ratios: [1.0; 4],
+        detune: 0.0,
+        detune_offset: 0.0,
+        detune_factor: 0.0,
+        amp_base,
+        velocity: 1.0,
+        random_per_note: 0.0,
+        pitch_mod_multiplier: 1.0,
+        depth_offsets: [0.0; 16],
+        amp_offsets: [0.0; 4],
+        amp_curve_offset: 0.0,
+        mod_env_curve_offsets: [0.0; 4],
+        lfo_offsets: [[0.0; 2]; 2],
+        glide_offset: 0.0,
+        pitch_env_offsets: [0.0; 4],
+        pitch_env_depth_offset: 0.0,
+        mod_ramps: VoiceModRamps::new(sample_rate),
+        harm: 0.0,
+        harm_offset: 0.0,
+        pitch_env: PitchEnvelope::default(),
+        pitch_env_amounts: [1.0; 4],
+        choke_gain: None,
     }
 }
 
 
-pub fn note_on(&mut self, note_id: u32, frequency: f32, last_global_freq: f32, any_voices_active: bool) {
-    // Capture previous state BEFORE modifying (for legato detection)
+/// Start a note. `last_global_freq` is the last note played on any voice
+/// (0 = none) and `legato` whether another note is still held.
+pub fn note_on(&mut self, note_id: u32, frequency: f32, last_global_freq: f32, legato: bool) {
+    // Capture previous state BEFORE modifying (restart smoothing on fresh voices)
     let was_active = self.active;
 
     self.note_id = Some(note_id);
     self.active = true;
+    self.choke_gain = None;
 
     let pitch_mul = 2_f32.powi(self.octave_shift);
     let adjusted_freq = frequency * pitch_mul;
 
-    // Set target frequency for portamento
-    self.target_frequency = adjusted_freq;
-
-    // Legato-only portamento:
-    // - If portamento is off: always snap
-    // - If no voices were active (first note): snap
-    // - If this voice was inactive but other voices active (legato): glide from last global freq
-    // - If this voice was active: keep current freq and continue gliding
-    if self.portamento_time == 0.0 || !any_voices_active {
-        self.current_frequency = adjusted_freq;
-    } else if !was_active {
-        self.current_frequency = last_global_freq;
-    }
-    // Otherwise, current_frequency stays as-is and will glide to target_frequency
+    // Glide from the last note played on any voice (0 = none yet); the
+    // portamento mode decides whether this note glides at all
+    let from = (last_global_freq > 0.0).then_some(last_global_freq);
+    self.portamento.set_time_offset(self.glide_offset);
+    self.portamento.note_on(adjusted_freq, from, legato);
 
     // Set base frequencies to current frequency (will be updated during glide)
     for op in &mut self.operators {
-        op.osc.base_frequency = self.current_frequency;
+        op.osc.base_frequency = self.portamento.current_frequency();
+        if !was_active {
+            op.reset_smoothing();
+        }
     }
+    if !was_active {
+        self.reset_mod_ramps();
+    }
+
+    // Fresh, reproducible noise stream per operator for this note
+    self.trigger_count = self.trigger_count.wrapping_add(1);
+    let note_seed = mix_seed(self.seed, self.trigger_count);
+    for (i, op) in self.operators.iter_mut().enumerate() {
+        op.osc.seed_noise(mix_seed(note_seed, i as u32));
+    }
+    self.random_per_note = Rng::new(mix_seed(note_seed, 0x524e_4431)).next_bipolar(); // "RND1"
 
     // Trigger carrier envelopes (if you have amplitude shaping)
     for op in self.operators.iter_mut() {
@@ -148,6 +373,12 @@ pub fn note_on(&mut self, note_id: u32, frequency: f32, last_global_freq: f32, a
         env.note_on();
     }
     self.amp_envelope.note_on();
+    self.pitch_env.note_on();
+
+    // Key-synced LFOs restart (or latch) per note
+    for lfo in &mut self.lfos {
+        lfo.note_on();
+    }
 
 }
 
@@ -162,38 +393,95 @@ pub fn note_off(&mut self, note_id: u32) {
             env.note_off();
         }
         self.amp_envelope.note_off();
+        self.pitch_env.note_off();
+        for lfo in &mut self.lfos {
+            lfo.note_off();
+        }
         self.note_id = None; // Clear note_id so this voice is "released"
     }
 }
 
+/// Jump the voice's modulation ramps to this block's values, so a new note
+/// starts at the modulated settings.
+fn reset_mod_ramps(&mut self) {
+    let ramps = &mut self.mod_ramps;
+    for (ramp, &target) in ramps.depth.iter_mut().zip(&self.depth_offsets) {
+        ramp.reset(target);
+    }
+    for (ramp, &target) in ramps.amp.iter_mut().zip(&self.amp_offsets) {
+        ramp.reset(target);
+    }
+    ramps.pitch.reset(self.pitch_mod_multiplier);
+    ramps.wavetable.reset(self.wavetable_lfo);
+    for (ramp, &target) in ramps.pitch_env.iter_mut().zip(&self.pitch_env_offsets) {
+        ramp.reset(target);
+    }
+    ramps.pitch_env_depth.reset(self.pitch_env_depth_offset);
+}
+
+/// Cut the voice off with a short fade, whatever its release time (drum
+/// choke groups: an open hi-hat silenced by the closed one).
+pub fn choke(&mut self) {
+    if let Some(id) = self.note_id {
+        self.note_off(id);
+    }
+    if self.active && self.choke_gain.is_none() {
+        self.choke_gain = Some(1.0);
+    }
+}
+
 /// Returns true if this voice is held (note is down, not yet released).
 pub fn is_held(&self) -> bool {
     self.active && self.note_id.is_some()
 }
 
+/// Frequency of the note being played (where any glide is heading).
+pub fn note_frequency(&self) -> f32 {
+    self.portamento.target_frequency()
+}
+
 
 
     /// Apply symmetric detune across operator pairs.
     /// A-group (ops 0=C, 1=A) goes sharp, B-group (ops 2=B1, 3=B2) goes flat.
     /// Creates chorus/thickening effect. detune_value is 0-127 (0=none, 127=max).
     pub fn apply_detune(&mut self, detune_value: f32) {
-        let factor = if detune_value <= 64.0 {
-            detune_value / 640.0
+        self.detune = detune_value;
+        self.detune_factor = Self::detune_spread(detune_value);
+        for op in 0..4 {
+            self.update_ratio(op);
+        }
+    }
+
+    /// Ratio spread for a 0-127 detune knob.
+    fn detune_spread(detune_value: f32) -> f32 {
+        let v = detune_value.clamp(0.0, 127.0);
+        if v <= 64.0 {
+            v / 640.0
         } else {
-            (detune_value - 64.0) / 320.0
-        };
+            (v - 64.0) / 320.0
+        }
+    }
 
-        // A-group goes sharp
-        self.operators[0].frequency_ratio *= 1.0 + factor;
-        self.operators[1].frequency_ratio *= 1.0 + factor;
-        // B-group goes flat
-        self.operators[2].frequency_ratio *= 1.0 - factor;
-        self.operators[3].frequency_ratio *= 1.0 - factor;
+    /// Set the voice's noise seed and restart its note counter.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.trigger_count = 0;
+        self.lfos[0].set_seed(mix_seed(seed, 0x4c46_4f31)); // "LFO1"
+        self.lfos[1].set_seed(mix_seed(seed, 0x4c46_4f32)); // "LFO2"
     }
 
     /// Set portamento time (0-127). 0 = instant pitch change, 127 = slowest glide.
     pub fn set_portamento_time(&mut self, time: f32) {
-        self.portamento_time = time.clamp(0.0, 127.0);
+        self.portamento.set_time_knob(time);
+    }
+
+    pub fn set_portamento_mode(&mut self, mode: PortamentoMode) {
+        self.portamento.set_mode(mode);
+    }
+
+    pub fn set_glide_type(&mut self, glide: GlideType) {
+        self.portamento.set_glide_type(glide);
     }
 
     /// Set pitch bend multiplier (1.0 = no bend, <1.0 = bend down, >1.0 = bend up)
@@ -215,9 +503,6 @@ pub fn is_held(&self) -> bool {
     ///
     /// `delta_time` is the time per sample (e.g., 1.0 / sample_rate).
     /// `mod_depth` scales the modulator's effect on the carrier.
-
-
-
     pub fn generate_sample(
         &mut self,
         delta_time:       f32,
@@ -231,40 +516,75 @@ pub fn is_held(&self) -> bool {
 
         self.sample_counter = self.sample_counter.wrapping_add(1);
 
-        // Test log - should appear once per second when voice is active
-        if self.sample_counter % 48000 == 0 {
+        // Test log - should appear once per second when voice is active. The
+        // counter runs at the oversampled rate, so count in seconds, not at 48 kHz
+        #[cfg(target_arch = "wasm32")]
+        let samples_per_second = (1.0 / delta_time).round().max(1.0) as u64;
+        #[cfg(target_arch = "wasm32")]
+        if self.sample_counter.is_multiple_of(samples_per_second) {
             web_sys::console::log_1(&format!("[RUST-VOICE] Active! counter={}", self.sample_counter).into());
         }
 
-        // Portamento: glide from current_frequency to target_frequency
-        if self.portamento_time > 0.0 && (self.current_frequency - self.target_frequency).abs() > 0.01 {
-            // Map portamento_time (0-127) to glide speed
-            // Higher time = slower glide (longer time to reach target)
-            // Glide speed in Hz/sec - exponentially scaled for musicality
-            let time_seconds = (self.portamento_time / 127.0) * 2.0; // 0-2 seconds max
-            let glide_rate = (self.target_frequency - self.current_frequency) / (time_seconds * self.sample_rate);
-
-            self.current_frequency += glide_rate;
-
-            // Clamp to prevent overshooting
-            if (glide_rate > 0.0 && self.current_frequency > self.target_frequency) ||
-               (glide_rate < 0.0 && self.current_frequency < self.target_frequency) {
-                self.current_frequency = self.target_frequency;
-            }
-        }
-
-        // Apply pitch bend to current frequency
-        let final_frequency = self.current_frequency * self.pitch_bend_multiplier;
-
-        // Update all operator base frequencies with portamento + pitch bend
+        // Step this block's modulation one sample along its ramp
         for op in &mut self.operators {
-            op.osc.base_frequency = final_frequency;
+            op.advance_modulation(delta_time);
+        }
+        let ramps = &mut self.mod_ramps;
+        let mut depth_offsets = [0.0f32; 16];
+        for ((d, ramp), &target) in depth_offsets.iter_mut().zip(&mut ramps.depth).zip(&self.depth_offsets) {
+            *d = ramp.next(target, delta_time);
+        }
+        let [a, d, s, r] = self.amp_base;
+        let mut amp_offsets = [0.0f32; 4];
+        for ((o, ramp), &target) in amp_offsets.iter_mut().zip(&mut ramps.amp).zip(&self.amp_offsets) {
+            *o = ramp.next(target, delta_time);
+        }
+        self.amp_envelope.attack  = (a + amp_offsets[0]).max(0.0);
+        self.amp_envelope.decay   = (d + amp_offsets[1]).max(0.0);
+        self.amp_envelope.sustain = (s + amp_offsets[2]).clamp(0.0, 1.0);
+        self.amp_envelope.release = (r + amp_offsets[3]).max(0.01);
+        let pitch_mod = ramps.pitch.next(self.pitch_mod_multiplier, delta_time);
+        let wavetable_lfo = ramps.wavetable.next(self.wavetable_lfo, delta_time);
+        let peg_scale = (1.0 + ramps.pitch_env_depth.next(self.pitch_env_depth_offset, delta_time)).max(0.0);
+        let mut peg_amounts = [0.0f32; 4];
+        for (((out, ramp), &offset), &amount) in peg_amounts.iter_mut().zip(&mut ramps.pitch_env)
+            .zip(&self.pitch_env_offsets).zip(&self.pitch_env_amounts)
+        {
+            *out = (amount + ramp.next(offset, delta_time)).clamp(-1.0, 1.0) * peg_scale;
+        }
+        // Curve changes are stepped per block (a shape, not a level)
+        self.amp_envelope.set_curve_offset(self.amp_curve_offset);
+
+        // Portamento glide, then pitch bend
+        let final_frequency = self.portamento.process(delta_time) * self.pitch_bend_multiplier * pitch_mod;
+
+        // Update all operator base frequencies with portamento + pitch bend,
+        // plus each operator's share of the pitch envelope
+        let peg = self.pitch_env.process(delta_time);
+        for (op, &amount) in self.operators.iter_mut().zip(peg_amounts.iter()) {
+            op.osc.base_frequency = if peg == 0.0 || amount == 0.0 {
+                final_frequency
+            } else {
+                final_frequency * 2_f32.powf(peg * amount / 12.0)
+            };
         }
 
         // Process per-operator mod envelopes exactly once per sample
         let mut op_env_levels = [0.0f32; 4];
-        for i in 0..4 {
-            op_env_levels[i] = self.operator_mod_envs[i].process(delta_time);
+        for ((level, env), &curve) in op_env_levels.iter_mut().zip(self.operator_mod_envs.iter_mut())
+            .zip(&self.mod_env_curve_offsets)
+        {
+            env.set_curve_offset(curve);
+            *level = env.process(delta_time);
+        }
+
+        // Wavetable scan position: base + own mod envelope + LFO
+        for (op, &env) in self.operators.iter_mut().zip(op_env_levels.iter()) {
+            if let WaveType::Wavetable = op.osc.wave {
+                op.osc.wavetable_position =
+                    (op.wavetable_position + env * op.wavetable_env_amount
+                        + wavetable_lfo + op.morph_offset()).clamp(0.0, 1.0);
+            }
         }
 
         /********* Step 1: Compute each operator in dependency order *********/
@@ -297,14 +617,14 @@ pub fn is_held(&self) -> bool {
                         // The feedback creates a phase offset proportional to the operator's output.
                         // For FM feedback: output(n) = sin(2π*phase + β*output(n-1))
                         // Scale to create ~1 cycle (TAU radians) of phase offset at maximum
-                        let fb_amount = self.operators[src].feedback_amount / 127.0;
+                        let fb_amount = self.operators[src].effective_feedback() / 127.0;
                         let fb_beta = fb_amount; // Direct scaling: 0-1.0 cycles
                         let fb_contribution = self.operators[src].last_output * fb_beta;
                         pm_in += fb_contribution;
 
                         // Debug logging (WASM only — web_sys unavailable in native tests)
                         #[cfg(target_arch = "wasm32")]
-                        if self.sample_counter < 10 || (fb_amount > 0.0 && (self.sample_counter % 4800 == 0)) {
+                        if self.sample_counter < 10 || (fb_amount > 0.0 && self.sample_counter.is_multiple_of(samples_per_second / 10)) {
                             web_sys::console::log_1(
                                 &format!("[RUST-FB] Op{} fb_amt={:.3} beta={:.3} last={:.3} contrib={:.3}",
                                     i, fb_amount, fb_beta, self.operators[src].last_output, fb_contribution).into()
@@ -313,7 +633,8 @@ pub fn is_held(&self) -> bool {
                     } else {
                         // External modulator: per-connection depth from spatial matrix
                         let raw = outputs[src].unwrap_or(0.0);
-                        let spatial_depth = mod_depth_matrix[src * 4 + dst];
+                        let cell = src * 4 + dst;
+                        let spatial_depth = (mod_depth_matrix[cell] + depth_offsets[cell]).clamp(0.0, 127.0);
                         let env_level = op_env_levels[src];
                         let effective_depth = spatial_depth * env_level;
                         let beta = Self::depth_to_index(effective_depth);
@@ -329,10 +650,9 @@ pub fn is_held(&self) -> bool {
 
         // Any operator still not computed (true circular dependency) —
         // force-process with zero modulation
-        for i in 0..4 {
-            if outputs[i].is_none() {
-                let sig = self.operators[i].generate_sample_pm(0.0, delta_time);
-                outputs[i] = Some(sig);
+        for (out, op) in outputs.iter_mut().zip(self.operators.iter_mut()) {
+            if out.is_none() {
+                *out = Some(op.generate_sample_pm(0.0, delta_time));
             }
         }
 
@@ -344,10 +664,19 @@ pub fn is_held(&self) -> bool {
         }
 
         /********* Step 3: Amp envelope + return *****************************/
-        let amp = self.amp_envelope.process(delta_time);
+        let mut amp = self.amp_envelope.process(delta_time);
+        if let Some(gain) = self.choke_gain.as_mut() {
+            *gain -= delta_time / CHOKE_TIME;
+            amp *= gain.max(0.0);
+        }
 
-        // Auto-deactivate when release finishes (envelope reaches Idle)
-        if self.amp_envelope.is_idle() {
+        // Auto-deactivate when release finishes (envelope reaches Idle), every
+        // carrier's own envelope has died away (DX L4 = 0) or a choke has faded out
+        let routing = &self.algorithm.output_routing;
+        let carriers_done = !routing.is_empty()
+            && routing.iter().all(|&(i, _)| self.operators[i].envelope.is_finished());
+        if self.amp_envelope.is_idle() || carriers_done || self.choke_gain.is_some_and(|g| g <= 0.0) {
+            self.amp_envelope.reset();
             self.active = false;
             self.note_id = None;
             self.last_output_l = 0.0;
@@ -392,17 +721,26 @@ pub fn is_held(&self) -> bool {
     }
 
     pub fn set_attack(&mut self, value: f32) {
+        self.amp_base[0] = value;
         self.amp_envelope.attack = value;
     }
     pub fn set_decay(&mut self, value: f32) {
+        self.amp_base[1] = value;
         self.amp_envelope.decay = value;
     }
     pub fn set_sustain(&mut self, value: f32) {
+        self.amp_base[2] = value;
         self.amp_envelope.sustain = value;
     }
     pub fn set_release(&mut self, value: f32) {
         // never allow true zero
-        self.amp_envelope.release = value.max(0.01);
+        self.amp_base[3] = value.max(0.01);
+        self.amp_envelope.release = self.amp_base[3];
+    }
+
+    /// Amp envelope stage shapes, -1.0 (log) .. 1.0 (exp).
+    pub fn set_amp_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        self.amp_envelope.set_curves(attack, decay, release);
     }
 
     pub fn set_operator_waveform(&mut self, op_index: usize, wave_type_id: u8) {
@@ -413,6 +751,18 @@ pub fn is_held(&self) -> bool {
             2 => WaveType::Saw,
             3 => WaveType::Triangle,
             4 => WaveType::Noise,
+            5 => WaveType::OpzW1,
+            6 => WaveType::OpzW2,
+            7 => WaveType::OpzW3,
+            8 => WaveType::OpzW4,
+            9 => WaveType::OpzW5,
+            10 => WaveType::OpzW6,
+            11 => WaveType::OpzW7,
+            12 => WaveType::OpzW8,
+            13 => WaveType::Wavetable,
+            14 => WaveType::PinkNoise,
+            15 => WaveType::BrownNoise,
+            16 => WaveType::DigitalNoise,
             _ => WaveType::Sine,
         };
         self.operators[op_index].set_waveform(wave_type);
@@ -426,21 +776,25 @@ pub fn is_held(&self) -> bool {
     // --- New methods for user input ---
 
     pub fn set_ratio_c(&mut self, ratio: f32) {
-        self.operators[0].set_frequency_ratio(ratio.clamp(0.25, 16.0));
+        self.base_ratios[0] = ratio.clamp(0.25, 16.0);
+        self.update_ratio(0);
     }
 
     pub fn set_ratio_a(&mut self, ratio: f32) {
-        self.operators[1].set_frequency_ratio(ratio.clamp(0.25, 16.0));
+        self.base_ratios[1] = ratio.clamp(0.25, 16.0);
+        self.update_ratio(1);
     }
 
     pub fn set_ratio_b(&mut self, ratio_b1: f32, ratio_b2: f32) {
-        self.operators[2].set_frequency_ratio(ratio_b1.clamp(0.25, 16.0));
-        self.operators[3].set_frequency_ratio(ratio_b2.clamp(0.25, 16.0));
+        self.base_ratios[2] = ratio_b1.clamp(0.25, 16.0);
+        self.base_ratios[3] = ratio_b2.clamp(0.25, 16.0);
+        self.update_ratio(2);
+        self.update_ratio(3);
     }
 
     pub fn set_global_feedback(&mut self, new_feedback: f32) {
         self.global_feedback_amount = new_feedback;
-        for (_idx, op) in self.operators.iter_mut().enumerate() {
+        for op in self.operators.iter_mut() {
             if op.is_modulator {
                 op.set_feedback_amount(self.global_feedback_amount);
             }
@@ -471,21 +825,106 @@ pub fn is_held(&self) -> bool {
         self.operators[op_index].set_level(level);
     }
 
+    /// Load a shared wavetable into a specific operator (0-3).
+    pub fn set_operator_wavetable(&mut self, op_index: usize, wavetable: Arc<Wavetable>) {
+        if op_index >= self.operators.len() { return; }
+        self.operators[op_index].set_wavetable(wavetable);
+    }
+
+    /// Set wavetable frame position (0.0-1.0) for a specific operator (0-3).
+    pub fn set_operator_wavetable_position(&mut self, op_index: usize, position: f32) {
+        if op_index >= self.operators.len() { return; }
+        self.operators[op_index].set_wavetable_position(position);
+    }
+
+    /// Set how far the operator's mod envelope sweeps its wavetable position.
+    pub fn set_operator_wavetable_env_amount(&mut self, op_index: usize, amount: f32) {
+        if op_index >= self.operators.len() { return; }
+        self.operators[op_index].set_wavetable_env_amount(amount);
+    }
+
     /// Set mod envelope for a specific operator (0-3).
     pub fn set_operator_mod_env(&mut self, op_index: usize, attack: u32, decay: u32, end: u32) {
         if op_index >= 4 { return; }
-        self.operator_mod_envs[op_index] = ModEnvelope::new_from_values(attack, decay, end);
+        self.operator_mod_envs[op_index].set_values(attack, decay, end);
+    }
+
+    /// Release knob (0-127) of an operator's mod envelope.
+    pub fn set_operator_mod_env_release(&mut self, op_index: usize, release: u32) {
+        if op_index >= 4 { return; }
+        self.operator_mod_envs[op_index].set_release(release);
+    }
+
+    pub fn set_operator_mod_env_retrigger(&mut self, op_index: usize, mode: RetriggerMode) {
+        if op_index >= 4 { return; }
+        self.operator_mod_envs[op_index].set_retrigger(mode);
+    }
+
+    pub fn set_operator_mod_env_loop(&mut self, op_index: usize, mode: LoopMode) {
+        if op_index >= 4 { return; }
+        self.operator_mod_envs[op_index].set_loop_mode(mode);
+    }
+
+    pub fn set_amp_retrigger(&mut self, mode: RetriggerMode) {
+        self.amp_envelope.set_retrigger(mode);
+    }
+
+    /// Pitch envelope levels (semitones) and segment times (seconds).
+    pub fn set_pitch_env(&mut self, levels: [f32; 4], times: [f32; 4]) {
+        self.pitch_env.set_params(levels, times);
+    }
+
+    /// How much of the pitch envelope an operator follows (-1.0..1.0, 0 = none).
+    pub fn set_operator_pitch_env_amount(&mut self, op_index: usize, amount: f32) {
+        if op_index >= 4 { return; }
+        self.pitch_env_amounts[op_index] = amount.clamp(-1.0, 1.0);
+    }
+
+    /// Give an operator a DX-style rate/level envelope (R1–R4, L1–L4, 0–99).
+    /// The amp envelope still multiplies the voice and ends it when idle, so
+    /// its release cuts any longer R4 tail; once every carrier's DX envelope
+    /// has reached an L4 of 0 the voice ends without waiting for the amp.
+    pub fn set_operator_dx_env(&mut self, op_index: usize, rates: [u8; 4], levels: [u8; 4]) {
+        if op_index >= 4 { return; }
+        // Edits to an existing DX envelope keep notes going where they are
+        let op = &mut self.operators[op_index];
+        if !op.envelope.set_dx_params(rates, levels) {
+            op.set_envelope(Box::new(DxEnvelope::new(rates, levels)));
+        }
+    }
+
+    /// Put an operator back on its default envelope (ADSR on carriers, none on modulators).
+    pub fn clear_operator_env(&mut self, op_index: usize) {
+        if op_index >= 4 { return; }
+        let op = &mut self.operators[op_index];
+        if op.is_modulator {
+            op.set_envelope(Box::new(NoopEnvelope));
+        } else {
+            op.set_envelope(Box::new(Envelope::from_digitone(0, 0, 127, 10)));
+        }
+    }
+
+    /// Stage shapes of an operator's mod envelope (-1.0 log .. 1.0 exp).
+    pub fn set_operator_mod_env_curves(&mut self, op_index: usize, attack: f32, decay: f32, release: f32) {
+        if op_index >= 4 { return; }
+        self.operator_mod_envs[op_index].set_curves(attack, decay, release);
     }
 
     pub fn update_harm(&mut self, harm: f32) {
+        self.harm = harm;
+        let split = Self::harm_split(harm);
+        for (op, &h) in self.operators.iter_mut().zip(split.iter()).take(3) {
+            op.set_harm(h);
+        }
+    }
+
+    /// Per-operator harm for a global harm value: positive folds A and B1,
+    /// negative folds C. B2 keeps its own setting.
+    fn harm_split(harm: f32) -> [f32; 4] {
         if harm >= 0.0 {
-            self.operators[1].set_harm(harm);
-            self.operators[2].set_harm(harm);
-            self.operators[0].set_harm(0.0);
+            [0.0, harm, harm, 0.0]
         } else {
-            self.operators[0].set_harm(harm);
-            self.operators[1].set_harm(0.0);
-            self.operators[2].set_harm(0.0);
+            [harm, 0.0, 0.0, 0.0]
         }
     }
 
@@ -495,6 +934,7 @@ pub fn is_held(&self) -> bool {
 mod tests {
     use super::*;
     use crate::algorithm::get_algorithms;
+    use crate::synth::CONTROL_BLOCK;
 
     const SAMPLE_RATE: f32 = 44100.0;
     const DT: f32 = 1.0 / SAMPLE_RATE;
@@ -796,5 +1236,206 @@ mod tests {
                 "Non-finite output after hot-swap: ({}, {})", l, r);
         }
     }
+
+    // ——— Noise seeding ———
+
+    fn render_noise_hit(seed: u32, hits: usize) -> Vec<f32> {
+        let mut voice = make_custom_voice(vec![], vec![0]);
+        voice.set_seed(seed);
+        voice.set_operator_waveform(0, 4); // white noise
+        let mut out = Vec::new();
+        for _ in 0..hits {
+            voice.note_on(60, 440.0, 440.0, false);
+            out = (0..512).map(|_| voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0).0).collect();
+            voice.note_off(60);
+        }
+        out
+    }
+
+    /// Voices with different seeds must not play identical noise.
+    #[test]
+    fn noise_decorrelates_across_voices() {
+        assert_ne!(render_noise_hit(0, 1), render_noise_hit(1, 1));
+    }
+
+    /// The same seed and note sequence re-renders identically,
+    /// while successive hits on one voice still differ.
+    #[test]
+    fn noise_is_reproducible_per_note() {
+        assert_eq!(render_noise_hit(4, 2), render_noise_hit(4, 2));
+        assert_ne!(render_noise_hit(4, 1), render_noise_hit(4, 2));
+    }
+
+    // ——— Operator / connection modulation ———
+
+    /// Render algo 1 (A → C at cell 4) with `matrix`, applying `dest` each block.
+    fn render_modulated(matrix: &[f32; 16], dest: Option<(ModDestination, f32)>) -> Vec<f32> {
+        let mut voice = make_voice(0);
+        voice.note_on(0, 220.0, 220.0, false);
+        let mut out = Vec::new();
+        for _ in 0..8 {
+            voice.clear_modulation();
+            if let Some((d, v)) = dest {
+                voice.apply_mod(d, v);
+            }
+            out.extend((0..128).map(|_| voice.generate_sample(DT, matrix, 1.0).0));
+        }
+        out
+    }
+
+    /// Modulating one connection (or depth A) matches a static matrix depth.
+    #[test]
+    fn connection_and_depth_a_modulation_drive_the_matrix() {
+        let mut matrix = [0.0f32; 16];
+        matrix[4] = 90.0; // A → C
+        let reference = render_modulated(&matrix, None);
+        let zero = [0.0f32; 16];
+        assert_eq!(render_modulated(&zero, Some((ModDestination::Connection(4), 90.0))), reference);
+        assert_eq!(render_modulated(&zero, Some((ModDestination::ModDepthA, 90.0))), reference);
+        assert_ne!(render_modulated(&zero, None), reference);
+    }
+
+    /// Per-operator offsets move a single operator and are undone by clear_modulation.
+    #[test]
+    fn operator_modulation_is_per_operator_and_cleared() {
+        let mut voice = make_voice(0);
+        let run_block = |voice: &mut FMVoice| {
+            for op in &mut voice.operators {
+                for _ in 0..CONTROL_BLOCK {
+                    op.advance_modulation(DT);
+                }
+            }
+        };
+        voice.set_ratio_b(2.0, 3.0);
+        voice.apply_mod(ModDestination::Operator(2, OperatorParam::Ratio), 1.0);
+        voice.apply_mod(ModDestination::Operator(1, OperatorParam::Feedback), 200.0);
+        assert_eq!(voice.operators[2].modulation.ratio, 1.0);
+        assert_eq!(voice.operators[3].modulation.ratio, 0.0);
+        assert_eq!(voice.operators[2].frequency_ratio, 2.0, "user ratio untouched");
+        run_block(&mut voice);
+        assert_eq!(voice.operators[1].effective_feedback(), 127.0);
+
+        voice.clear_modulation();
+        assert_eq!(voice.operators[2].modulation.ratio, 0.0);
+        run_block(&mut voice);
+        assert_eq!(voice.operators[1].effective_feedback(), 0.0);
+    }
+
+    /// Block-rate modulation is interpolated per sample and reaches the
+    /// block's value at the block's end, rather than lagging behind it.
+    #[test]
+    fn modulation_ramps_across_the_control_block() {
+        let render = |level: f32| {
+            let mut voice = make_custom_voice(vec![], vec![0]);
+            voice.note_on(0, 440.0, 440.0, false);
+            voice.clear_modulation();
+            voice.apply_mod(ModDestination::Operator(0, OperatorParam::Level), level);
+            (0..CONTROL_BLOCK * 2)
+                .map(|_| voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0).0)
+                .collect::<Vec<f32>>()
+        };
+        let dry = render(0.0);
+        let wet = render(-127.0);
+        for (k, (&d, &w)) in dry.iter().zip(&wet).enumerate() {
+            let gain = 1.0 - ((k + 1) as f32 / CONTROL_BLOCK as f32).min(1.0);
+            assert!((w - d * gain).abs() < 1e-5, "sample {}: {} vs {}", k, w, d * gain);
+        }
+    }
+
+    /// Silencing the carrier through its level leaves nothing on its output.
+    #[test]
+    fn operator_level_modulation_mutes_carrier() {
+        let mut voice = make_custom_voice(vec![], vec![0]);
+        voice.apply_mod(ModDestination::Operator(0, OperatorParam::Level), -127.0);
+        voice.note_on(0, 440.0, 440.0, false); // a new note starts at the modulated level
+        let peak = (0..512)
+            .map(|_| voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0).0.abs())
+            .fold(0.0f32, f32::max);
+        assert_eq!(peak, 0.0);
+    }
+
+    #[test]
+    fn dx_envelope_shapes_carrier_until_cleared() {
+        let peak = |voice: &mut FMVoice| {
+            voice.note_on(0, 440.0, 440.0, false);
+            (0..2048)
+                .map(|_| voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0).0.abs())
+                .fold(0.0f32, f32::max)
+        };
+        let mut voice = make_custom_voice(vec![], vec![0]);
+        let full = peak(&mut voice);
+        // L3 = 59 holds the carrier 30 dB down
+        voice.set_operator_dx_env(0, [99, 99, 99, 99], [59, 59, 59, 0]);
+        let quiet = peak(&mut voice);
+        assert!(quiet < full * 0.05, "dx env {} vs default {}", quiet, full);
+        voice.clear_operator_env(0);
+        assert!((peak(&mut voice) - full).abs() < full * 0.01);
+    }
+
+    #[test]
+    fn dx_envelope_edits_keep_held_notes_sounding() {
+        let mut voice = make_custom_voice(vec![], vec![0]);
+        voice.set_operator_dx_env(0, [99, 99, 99, 99], [99, 99, 99, 0]);
+        voice.note_on(0, 440.0, 440.0, false);
+        for _ in 0..1024 {
+            voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0);
+        }
+        voice.set_operator_dx_env(0, [99, 99, 99, 99], [99, 99, 98, 0]);
+        voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0);
+        let level = voice.operators[0].envelope.get_level();
+        assert!(level > 0.9, "held carrier cut to {} by a knob move", level);
+    }
+
+    /// A carrier's DX envelope dying out at L4 = 0 frees the voice, while
+    /// envelopes without an end leave it to the amp release.
+    #[test]
+    fn finished_dx_carriers_end_the_voice() {
+        let active_after_release = |dx: bool| {
+            let mut voice = make_custom_voice(vec![], vec![0]);
+            voice.set_release(5.0);
+            if dx {
+                voice.set_operator_dx_env(0, [99, 99, 99, 99], [99, 99, 99, 0]);
+            }
+            voice.note_on(0, 440.0, 440.0, false);
+            for _ in 0..1024 {
+                voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0);
+            }
+            voice.note_off(0);
+            for _ in 0..(0.1 * SAMPLE_RATE) as usize {
+                voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0);
+            }
+            voice.is_active()
+        };
+        assert!(!active_after_release(true), "dx carrier at L4 = 0 should end the voice");
+        assert!(active_after_release(false), "amp release still running");
+    }
+
+    #[test]
+    fn pitch_envelope_follows_per_operator_amount() {
+        let mut voice = make_custom_voice(vec![], vec![0]);
+        voice.set_pitch_env([12.0, 12.0, 12.0, 0.0], [0.0; 4]);
+        voice.set_operator_pitch_env_amount(1, 0.0);
+        voice.set_operator_pitch_env_amount(2, 0.5);
+        voice.note_on(0, 440.0, 440.0, false);
+        voice.generate_sample(DT, &matrix_ab(0.0, 0.0), 1.0);
+        let freq = |i: usize| voice.operators[i].osc.base_frequency;
+        assert!((freq(0) - 880.0).abs() < 0.01, "full octave up: {}", freq(0));
+        assert!((freq(1) - 440.0).abs() < 0.01, "operator opted out");
+        assert!((freq(2) - 440.0 * 2_f32.sqrt()).abs() < 0.01);
+    }
+
+    /// Only the LFOs in per-voice mode run on the voice's copies.
+    #[test]
+    fn per_voice_lfos_run_only_when_switched_on() {
+        let mut voice = make_voice(0);
+        for lfo in &mut voice.lfos {
+            lfo.set_speed(40.0);
+        }
+        voice.note_on(1, 440.0, 0.0, false);
+        for _ in 0..100 {
+            voice.process_lfos(0.001, Clock::default(), [true, false]);
+        }
+        assert_ne!(voice.lfos[0].shape_value(), 0.0);
+        assert_eq!(voice.lfos[1].shape_value(), 0.0, "copy of a global LFO stays idle");
+    }
 }
-    
\ No newline at end of file
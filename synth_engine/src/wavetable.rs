@@ -0,0 +1,409 @@
+// src/wavetable.rs
+//! User wavetables for operators.
+//!
+//! A `Wavetable` holds one or more single-cycle frames, each resampled to
+//! `FRAME_SIZE` samples and mip-mapped so high notes read from a band-limited
+//! copy. Frames are shared between voices through an `Arc`, so loading a table
+//! once is enough for the whole synth.
+
+use std::fmt;
+
+/// Samples per frame after import (Serum-style).
+pub const FRAME_SIZE: usize = 2048;
+
+/// Smallest mip level we bother building.
+const MIN_LEVEL_SIZE: usize = 8;
+
+/// Taps in the half-band filter used to build each mip level (odd).
+const MIP_TAPS: usize = 31;
+
+/// Errors produced while importing a WAV file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WavetableError {
+    /// Missing `RIFF`/`WAVE` header or truncated chunk.
+    InvalidWav,
+    /// Compressed or otherwise unsupported sample format.
+    UnsupportedFormat { format_tag: u16, bits: u16 },
+    /// The file (or buffer) holds no samples.
+    Empty,
+}
+
+impl fmt::Display for WavetableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavetableError::InvalidWav => write!(f, "not a valid RIFF/WAVE file"),
+            WavetableError::UnsupportedFormat { format_tag, bits } => {
+                write!(f, "unsupported WAV format (tag {}, {} bits)", format_tag, bits)
+            }
+            WavetableError::Empty => write!(f, "wavetable contains no samples"),
+        }
+    }
+}
+
+impl std::error::Error for WavetableError {}
+
+/// One frame, stored as a chain of progressively band-limited copies.
+/// `levels[0]` is the full `FRAME_SIZE` table, each next level is half as long.
+struct MipFrame {
+    levels: Vec<Vec<f32>>,
+}
+
+impl MipFrame {
+    fn new(cycle: Vec<f32>) -> Self {
+        let kernel = half_band_kernel();
+        let mut levels = vec![cycle];
+        while levels.last().map_or(0, |l| l.len()) > MIN_LEVEL_SIZE {
+            let prev = levels.last().unwrap();
+            levels.push(decimate_cyclic(prev, &kernel));
+        }
+        MipFrame { levels }
+    }
+
+    /// Linear-interpolated read of `level` at `phase` (cycles, 0..1).
+    #[inline]
+    fn read(&self, level: usize, phase: f32) -> f32 {
+        let table = &self.levels[level.min(self.levels.len() - 1)];
+        let len = table.len();
+        let pos = phase * len as f32;
+        let i0 = (pos as usize) % len;
+        let i1 = (i0 + 1) % len;
+        let frac = pos - pos.floor();
+        table[i0] + (table[i1] - table[i0]) * frac
+    }
+}
+
+/// A multi-frame, mip-mapped single-cycle wavetable.
+pub struct Wavetable {
+    frames: Vec<MipFrame>,
+}
+
+impl Wavetable {
+    /// Build a wavetable from raw samples.
+    ///
+    /// `frame_size` is the length of one cycle in `data`; 0 treats the whole
+    /// buffer as a single cycle. Every frame is resampled to `FRAME_SIZE`.
+    /// A trailing partial frame is dropped.
+    pub fn from_samples(data: &[f32], frame_size: usize) -> Result<Self, WavetableError> {
+        let frame_size = if frame_size == 0 { data.len() } else { frame_size };
+        if frame_size == 0 || data.len() < frame_size {
+            return Err(WavetableError::Empty);
+        }
+
+        let frames = data
+            .chunks_exact(frame_size)
+            .map(|cycle| MipFrame::new(resample_cycle(cycle, FRAME_SIZE)))
+            .collect();
+
+        Ok(Wavetable { frames })
+    }
+
+    /// Import a WAV file (PCM 8/16/24/32-bit or 32-bit float, first channel).
+    ///
+    /// The frame size comes from a Serum `clm ` chunk when present; otherwise
+    /// files that are a whole multiple of `FRAME_SIZE` are split into
+    /// `FRAME_SIZE` frames and anything else is treated as one cycle.
+    pub fn from_wav_bytes(bytes: &[u8]) -> Result<Self, WavetableError> {
+        let wav = parse_wav(bytes)?;
+        let frame_size = match wav.clm_frame_size {
+            Some(n) if n > 0 => n,
+            _ if wav.samples.len() >= FRAME_SIZE && wav.samples.len() % FRAME_SIZE == 0 => FRAME_SIZE,
+            _ => 0,
+        };
+        Self::from_samples(&wav.samples, frame_size)
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Read the table at `phase` (cycles, 0..1).
+    ///
+    /// `position` (0..1) scans across frames with a linear crossfade.
+    /// `phase_inc` is the oscillator's per-sample increment in cycles and
+    /// picks the mip level whose harmonics stay below Nyquist.
+    pub fn sample(&self, position: f32, phase: f32, phase_inc: f32) -> f32 {
+        let level = Self::mip_level(phase_inc);
+        let phase = phase.rem_euclid(1.0);
+
+        let last = self.frames.len() - 1;
+        let pos = position.clamp(0.0, 1.0) * last as f32;
+        let f0 = pos.floor() as usize;
+        let f1 = (f0 + 1).min(last);
+        let mix = pos - f0 as f32;
+
+        let a = self.frames[f0].read(level, phase);
+        if mix == 0.0 || f0 == f1 {
+            return a;
+        }
+        let b = self.frames[f1].read(level, phase);
+        a + (b - a) * mix
+    }
+
+    /// Level k holds `FRAME_SIZE >> k` samples and therefore harmonics up to
+    /// half that; choose the largest table whose top harmonic is still below
+    /// Nyquist at this pitch.
+    fn mip_level(phase_inc: f32) -> usize {
+        let inc = phase_inc.abs();
+        if inc <= 0.0 {
+            return 0;
+        }
+        let ratio = FRAME_SIZE as f32 * inc;
+        if ratio <= 1.0 { 0 } else { ratio.log2().ceil() as usize }
+    }
+}
+
+/// Cyclic linear resample of one cycle to `len` samples.
+fn resample_cycle(cycle: &[f32], len: usize) -> Vec<f32> {
+    if cycle.len() == len {
+        return cycle.to_vec();
+    }
+    let n = cycle.len();
+    (0..len)
+        .map(|i| {
+            let pos = i as f32 * n as f32 / len as f32;
+            let i0 = pos as usize % n;
+            let i1 = (i0 + 1) % n;
+            let frac = pos - pos.floor();
+            cycle[i0] + (cycle[i1] - cycle[i0]) * frac
+        })
+        .collect()
+}
+
+/// Blackman-windowed sinc at a quarter of the sample rate (half-band).
+fn half_band_kernel() -> [f32; MIP_TAPS] {
+    use std::f32::consts::PI;
+    let mut k = [0.0f32; MIP_TAPS];
+    let mid = (MIP_TAPS / 2) as f32;
+    for (i, c) in k.iter_mut().enumerate() {
+        let n = i as f32 - mid;
+        let sinc = if n == 0.0 { 0.5 } else { (PI * 0.5 * n).sin() / (PI * n) };
+        let w = 0.42 - 0.5 * (2.0 * PI * i as f32 / (MIP_TAPS - 1) as f32).cos()
+            + 0.08 * (4.0 * PI * i as f32 / (MIP_TAPS - 1) as f32).cos();
+        *c = sinc * w;
+    }
+    let sum: f32 = k.iter().sum();
+    k.iter_mut().for_each(|c| *c /= sum);
+    k
+}
+
+/// Low-pass a cyclic table and keep every other sample.
+fn decimate_cyclic(table: &[f32], kernel: &[f32]) -> Vec<f32> {
+    let n = table.len();
+    let mid = kernel.len() / 2;
+    (0..n / 2)
+        .map(|j| {
+            let centre = 2 * j;
+            kernel.iter().enumerate()
+                .map(|(t, &c)| c * table[(centre + n + t - mid) % n])
+                .sum()
+        })
+        .collect()
+}
+
+struct ParsedWav {
+    samples: Vec<f32>,
+    clm_frame_size: Option<usize>,
+}
+
+fn read_u16(b: &[u8], at: usize) -> Option<u16> {
+    b.get(at..at + 2).map(|s| u16::from_le_bytes([s[0], s[1]]))
+}
+
+fn read_u32(b: &[u8], at: usize) -> Option<u32> {
+    b.get(at..at + 4).map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+}
+
+/// Body start, body end and next chunk offset for a chunk header at `at`
+/// with a body of `size` bytes. Sizes come from the file, so a hostile one
+/// must not wrap on wasm32: `None` if any offset overflows `usize`.
+fn chunk_bounds(at: usize, size: usize) -> Option<(usize, usize, usize)> {
+    let body_start = at.checked_add(8)?;
+    let body_end = body_start.checked_add(size)?;
+    // chunks are word-aligned
+    let next = body_end.checked_add(size & 1)?;
+    Some((body_start, body_end, next))
+}
+
+fn parse_wav(bytes: &[u8]) -> Result<ParsedWav, WavetableError> {
+    if bytes.get(0..4) != Some(b"RIFF") || bytes.get(8..12) != Some(b"WAVE") {
+        return Err(WavetableError::InvalidWav);
+    }
+
+    let mut fmt: Option<(u16, u16, u16)> = None; // (tag, channels, bits)
+    let mut data: Option<&[u8]> = None;
+    let mut clm_frame_size = None;
+
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let size = read_u32(bytes, at + 4).ok_or(WavetableError::InvalidWav)? as usize;
+        let (body_start, body_end, next) = chunk_bounds(at, size).ok_or(WavetableError::InvalidWav)?;
+        let body = bytes.get(body_start..body_end)
+            .or_else(|| bytes.get(body_start..)) // tolerate a short final chunk
+            .ok_or(WavetableError::InvalidWav)?;
+
+        match id {
+            b"fmt " => {
+                let mut tag = read_u16(body, 0).ok_or(WavetableError::InvalidWav)?;
+                let channels = read_u16(body, 2).ok_or(WavetableError::InvalidWav)?;
+                let bits = read_u16(body, 14).ok_or(WavetableError::InvalidWav)?;
+                if tag == 0xFFFE {
+                    // WAVE_FORMAT_EXTENSIBLE: real tag is the first word of the sub-format GUID
+                    tag = read_u16(body, 24).ok_or(WavetableError::InvalidWav)?;
+                }
+                fmt = Some((tag, channels.max(1), bits));
+            }
+            b"data" => data = Some(body),
+            b"clm " => {
+                // Serum writes e.g. "<!>2048 01000000 wavetable (…)"
+                let text = String::from_utf8_lossy(body);
+                clm_frame_size = text.strip_prefix("<!>")
+                    .and_then(|t| t.split_whitespace().next())
+                    .and_then(|n| n.parse().ok());
+            }
+            _ => {}
+        }
+
+        at = next;
+    }
+
+    let (tag, channels, bits) = fmt.ok_or(WavetableError::InvalidWav)?;
+    let data = data.ok_or(WavetableError::InvalidWav)?;
+
+    let bytes_per_sample = (bits as usize).div_ceil(8);
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 8)  => |s| (s[0] as f32 - 128.0) / 128.0,
+        (1, 16) => |s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0,
+        (1, 24) => |s| (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32 / 8_388_608.0,
+        (1, 32) => |s| i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |s| f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+        _ => return Err(WavetableError::UnsupportedFormat { format_tag: tag, bits }),
+    };
+
+    let stride = bytes_per_sample * channels as usize;
+    let samples: Vec<f32> = data.chunks_exact(stride).map(decode).collect();
+    if samples.is_empty() {
+        return Err(WavetableError::Empty);
+    }
+
+    Ok(ParsedWav { samples, clm_frame_size })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    fn sine_cycle(len: usize, harmonic: usize) -> Vec<f32> {
+        (0..len).map(|i| (TAU * harmonic as f32 * i as f32 / len as f32).sin()).collect()
+    }
+
+    fn wav_pcm16(samples: &[f32], clm: Option<&str>) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &s in samples {
+            data.extend_from_slice(&((s * 32767.0) as i16).to_le_bytes());
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());      // PCM
+        out.extend_from_slice(&1u16.to_le_bytes());      // mono
+        out.extend_from_slice(&48_000u32.to_le_bytes());
+        out.extend_from_slice(&96_000u32.to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        if let Some(text) = clm {
+            out.extend_from_slice(b"clm ");
+            out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            out.extend_from_slice(text.as_bytes());
+            if text.len() % 2 == 1 { out.push(0); }
+        }
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        out
+    }
+
+    #[test]
+    fn splits_buffer_into_frames() {
+        let mut data = sine_cycle(FRAME_SIZE, 1);
+        data.extend(sine_cycle(FRAME_SIZE, 2));
+        let wt = Wavetable::from_samples(&data, FRAME_SIZE).unwrap();
+        assert_eq!(wt.num_frames(), 2);
+
+        // position 0 → first frame, position 1 → second frame
+        let a = wt.sample(0.0, 0.125, 0.0);
+        let b = wt.sample(1.0, 0.125, 0.0);
+        assert!((a - (TAU * 0.125).sin()).abs() < 1e-3);
+        assert!((b - (TAU * 0.25).sin()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn short_single_cycle_is_resampled() {
+        let wt = Wavetable::from_samples(&sine_cycle(600, 1), 0).unwrap();
+        assert_eq!(wt.num_frames(), 1);
+        assert!((wt.sample(0.0, 0.25, 0.0) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn empty_buffer_is_rejected() {
+        assert_eq!(Wavetable::from_samples(&[], 0).err(), Some(WavetableError::Empty));
+    }
+
+    #[test]
+    fn high_notes_read_band_limited_levels() {
+        // A cycle with a strong 200th harmonic must lose it at high pitch.
+        let data: Vec<f32> = sine_cycle(FRAME_SIZE, 1).iter()
+            .zip(sine_cycle(FRAME_SIZE, 200))
+            .map(|(a, b)| 0.5 * a + 0.5 * b)
+            .collect();
+        let wt = Wavetable::from_samples(&data, FRAME_SIZE).unwrap();
+
+        // 2 kHz at 48 kHz: harmonic 200 would sit at 400 kHz.
+        let inc = 2000.0 / 48_000.0;
+        let peak = (0..FRAME_SIZE)
+            .map(|i| {
+                let ph = i as f32 / FRAME_SIZE as f32;
+                (wt.sample(0.0, ph, inc) - 0.5 * (TAU * ph).sin()).abs()
+            })
+            .fold(0.0f32, f32::max);
+        assert!(peak < 0.05, "harmonic 200 leaked into high mip level: {}", peak);
+    }
+
+    #[test]
+    fn parses_pcm16_wav_with_clm_chunk() {
+        let mut data = sine_cycle(256, 1);
+        data.extend(sine_cycle(256, 3));
+        let bytes = wav_pcm16(&data, Some("<!>256 10000000 wavetable"));
+        let wt = Wavetable::from_wav_bytes(&bytes).unwrap();
+        assert_eq!(wt.num_frames(), 2);
+        assert!((wt.sample(0.0, 0.25, 0.0) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_chunk_size_past_the_end() {
+        // A junk chunk claiming 4 GiB hides the data chunk after it
+        let mut bytes = wav_pcm16(&sine_cycle(256, 1), None);
+        let junk = [b"junk".as_slice(), &0xFFFF_FFFFu32.to_le_bytes()].concat();
+        bytes.splice(36..36, junk);
+        assert_eq!(Wavetable::from_wav_bytes(&bytes).err(), Some(WavetableError::InvalidWav));
+    }
+
+    #[test]
+    fn chunk_offsets_that_overflow_are_rejected() {
+        assert_eq!(chunk_bounds(12, 5), Some((20, 25, 26)));
+        // A 4 GiB body past a header near the top of the address space
+        assert_eq!(chunk_bounds(usize::MAX - 0xFFFF, 0xFFFF_FFFF), None);
+        assert_eq!(chunk_bounds(usize::MAX - 4, 0), None, "header itself overflows");
+        assert_eq!(chunk_bounds(usize::MAX - 9, 1), None, "pad byte overflows");
+        assert_eq!(chunk_bounds(usize::MAX - 10, 2), Some((usize::MAX - 2, usize::MAX, usize::MAX)));
+    }
+
+    #[test]
+    fn rejects_non_wav_bytes() {
+        assert_eq!(Wavetable::from_wav_bytes(b"not a wav").err(), Some(WavetableError::InvalidWav));
+    }
+}
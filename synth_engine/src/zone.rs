@@ -0,0 +1,80 @@
+// src/zone.rs
+//! Key and velocity zones for splits and layers.
+//!
+//! A zone gives a patch the slice of the keyboard (and of the velocity
+//! range) it answers to, plus a transpose. Patches with side-by-side key
+//! ranges split the keyboard; overlapping ranges layer them. `Synth` keeps
+//! one for its own voices and each of its layers, and `MultiSynth` one per
+//! part.
+
+/// Pitch a zone without key tracking plays before transpose (middle C).
+const FIXED_PITCH: f32 = 261.63;
+
+/// Which notes a patch plays, and how it shifts them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Zone {
+    /// Lowest and highest MIDI key, inclusive.
+    pub keys: (u8, u8),
+    /// Lowest and highest velocity, inclusive.
+    pub velocities: (u8, u8),
+    /// Semitones added to every note.
+    pub transpose: f32,
+    /// Whether the pitch follows the key. Without it every key in the zone
+    /// plays middle C plus `transpose` (a drum pad).
+    pub key_track: bool,
+}
+
+impl Default for Zone {
+    /// The whole keyboard at any velocity, untransposed.
+    fn default() -> Self {
+        Self { keys: (0, 127), velocities: (0, 127), transpose: 0.0, key_track: true }
+    }
+}
+
+impl Zone {
+    /// Keys (MIDI notes, inclusive), the ends in either order.
+    pub fn set_keys(&mut self, low: u8, high: u8) {
+        self.keys = (low.min(high).min(127), high.max(low).min(127));
+    }
+
+    /// Velocities (0-127, inclusive), the ends in either order.
+    pub fn set_velocities(&mut self, low: u8, high: u8) {
+        self.velocities = (low.min(high).min(127), high.max(low).min(127));
+    }
+
+    /// Transpose in semitones, -48 to 48.
+    pub fn set_transpose(&mut self, semitones: f32) {
+        self.transpose = semitones.clamp(-48.0, 48.0);
+    }
+
+    /// Frequency to play for a note at `freq` and `velocity` (0-127), or
+    /// `None` when the note falls outside the zone. The key is the nearest
+    /// MIDI note to `freq`.
+    pub fn map(&self, freq: f32, velocity: f32) -> Option<f32> {
+        let key = (69.0 + 12.0 * (freq / 440.0).log2()).round();
+        let in_keys = key >= self.keys.0 as f32 && key <= self.keys.1 as f32;
+        let velocity = velocity.round();
+        let in_velocities = velocity >= self.velocities.0 as f32 && velocity <= self.velocities.1 as f32;
+        let root = if self.key_track { freq } else { FIXED_PITCH };
+        (in_keys && in_velocities).then(|| root * 2.0f32.powf(self.transpose / 12.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zones_split_layer_and_transpose() {
+        let split = Zone { keys: (0, 59), ..Zone::default() };
+        assert_eq!(split.map(261.63, 100.0), None, "C4 is key 60");
+        assert_eq!(split.map(246.94, 100.0), Some(246.94));
+        let soft = Zone { velocities: (0, 63), transpose: 12.0, ..Zone::default() };
+        assert_eq!(soft.map(440.0, 100.0), None);
+        assert!((soft.map(440.0, 40.0).unwrap() - 880.0).abs() < 0.01);
+        let pad = Zone { keys: (36, 36), transpose: -24.0, key_track: false, ..Zone::default() };
+        assert!((pad.map(65.41, 100.0).unwrap() - 65.41).abs() < 0.01, "C2 pad tuned to C2");
+        let toms = Zone { keys: (45, 50), transpose: -12.0, key_track: false, ..Zone::default() };
+        assert_eq!(toms.map(110.0, 100.0), toms.map(146.83, 100.0), "every key plays the pad's tuning");
+    }
+}